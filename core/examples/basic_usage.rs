@@ -140,23 +140,25 @@ fn create_simple_world() -> WorldMap {
         id: Uuid::new_v4(),
         name: "Example World".to_string(),
         version: "1.0.0".to_string(),
+        schema_version: SCHEMA_VERSION,
         created_at: Utc::now(),
         modified_at: Utc::now(),
         width: 100,
         height: 100,
         seed: 12345,
         settings: MapSettings {
-            distance_unit: "km".to_string(),
+            distance_unit: DistanceUnit::Kilometre,
             distance_scale: 1.0,
-            area_unit: "km²".to_string(),
-            height_unit: "m".to_string(),
+            area_unit: AreaUnit::SquareKilometre,
+            height_unit: HeightUnit::Metre,
             height_exponent: 1.8,
-            temperature_scale: "°C".to_string(),
+            temperature_scale: TemperatureScale::Celsius,
             population_rate: 1.0,
             urbanization: 1.0,
             latitude: 45.0,
             longitude: 0.0,
         },
+        notes: Default::default(),
     };
     
     // Create a simple heightmap
@@ -175,15 +177,15 @@ fn create_simple_world() -> WorldMap {
     let mut cells = Vec::new();
     for i in 0..10 {
         let cell = Cell {
-            id: i,
+            id: CellId(i),
             coordinates: Point2::new(i as f32 * 10.0, i as f32 * 10.0),
             height: 0.5,
             biome: BiomeType::Temperate,
             temperature: 15.0,
             precipitation: 800.0,
             population: 1000,
-            culture: Some(0),
-            state: Some(0),
+            culture: Some(CultureId(0)),
+            state: Some(StateId(0)),
             province: None,
             religion: None,
         };
@@ -192,53 +194,61 @@ fn create_simple_world() -> WorldMap {
     
     // Create a sample culture
     let culture = Culture {
-        id: 0,
+        id: CultureId(0),
         name: "Example Culture".to_string(),
-        color: "#FF0000".to_string(),
+        color: Color::rgb(255, 0, 0),
         base: 0,
-        origins: vec![0],
+        origins: vec![CultureId(0)],
         shield: "default".to_string(),
         center: Point2::new(50.0, 50.0),
         area: 1000.0,
         rural: 0.8,
         urban: 0.2,
         expansionism: 1.0,
+        note: None,
+        attributes: Attributes::new(),
     };
     
     // Create a sample state
     let state = State {
-        id: 0,
+        id: StateId(0),
         name: "Example Kingdom".to_string(),
         full_name: "The Kingdom of Example".to_string(),
-        color: "#0000FF".to_string(),
-        capital: 0,
+        color: Color::rgb(0, 0, 255),
+        capital: BurgId(0),
         center: Point2::new(50.0, 50.0),
         area: 1000.0,
         population: 10000,
         rural: 0.7,
         urban: 0.3,
         burgs: 1,
-        culture: 0,
-        type_: "Kingdom".to_string(),
+        culture: CultureId(0),
+        type_: StateType::Generic,
         expansionism: 1.0,
-        cells: (0..10).collect(),
+        cells: (0..10).map(CellId).collect(),
+        coa: None,
+        note: Some(Note::new("The Kingdom of Example", "<p>Founded by the first settlers of the valley.</p>")),
+        attributes: [("ruler", "Queen Ada")].into_iter().collect(),
     };
     
     // Create a sample settlement
     let settlement = Settlement {
-        id: 0,
+        id: BurgId(0),
         name: "Example City".to_string(),
-        cell: 0,
+        cell: CellId(0),
         x: 50.0,
         y: 50.0,
-        state: 0,
+        state: StateId(0),
         i: 0,
-        culture: 0,
-        feature: 0,
-        capital: 1,
+        culture: CultureId(0),
+        feature: FeatureId(0),
+        capital: true,
         port: 0,
         population: 5000.0,
-        type_: "City".to_string(),
+        type_: SettlementType::Generic,
+        coa: None,
+        note: None,
+        attributes: Attributes::new(),
     };
     
     WorldMap {
@@ -248,10 +258,16 @@ fn create_simple_world() -> WorldMap {
         features: Vec::new(),
         cultures: vec![culture],
         states: vec![state],
+        provinces: Vec::new(),
         burgs: vec![settlement],
         rivers: Vec::new(),
         routes: Vec::new(),
         markers: Vec::new(),
         zones: Vec::new(),
+        diplomacy: DiplomacyMatrix::new(),
+        history: Timeline::default(),
+        calendar: Calendar::default(),
+        events: Vec::new(),
+        topology: TopologyCache::default(),
     }
 }
\ No newline at end of file
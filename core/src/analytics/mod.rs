@@ -0,0 +1,290 @@
+//! World statistics and gazetteer queries
+//!
+//! Totals of area and population by state, culture, religion and biome, for
+//! info panels and almanac pages, and rankings of the largest burgs, longest
+//! rivers and highest peaks. Areas are in the map's `MapSettings::area_unit`,
+//! elevations in its `MapSettings::height_unit` and populations in people.
+
+use crate::{AreaUnit, BiomeType, CellId, CultureId, River, Settlement, StateId, WorldMap};
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Totals over a group of cells and the burgs on them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AreaStatistics {
+    pub cells: usize,
+    /// Area in the map's area unit
+    pub area: f32,
+    /// Rural population, in people
+    pub rural: f32,
+    /// Urban population, in people
+    pub urban: f32,
+    pub burgs: usize,
+}
+
+/// Statistics of a whole world
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldStatistics {
+    pub area_unit: AreaUnit,
+    pub land: AreaStatistics,
+    /// Sea and lake cells
+    pub water: AreaStatistics,
+    /// Cells owned by each state; burgs of state 0 count as neutral
+    pub states: BTreeMap<StateId, AreaStatistics>,
+    pub cultures: BTreeMap<CultureId, AreaStatistics>,
+    pub religions: BTreeMap<u32, AreaStatistics>,
+    /// Biomes present on the map, largest first
+    pub biomes: Vec<(BiomeType, AreaStatistics)>,
+}
+
+/// Cell higher than all its neighbours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Peak {
+    pub cell: CellId,
+    pub position: Point2<f32>,
+    /// Normalized height
+    pub height: f32,
+    /// Elevation in the map's height unit
+    pub elevation: f32,
+}
+
+impl AreaStatistics {
+    /// Rural and urban population together
+    pub fn population(&self) -> f32 {
+        self.rural + self.urban
+    }
+    
+    /// People per unit of area; 0 for an empty area
+    pub fn density(&self) -> f32 {
+        if self.area > 0.0 {
+            self.population() / self.area
+        } else {
+            0.0
+        }
+    }
+    
+    fn add_cell(&mut self, area: f32, rural: f32) {
+        self.cells += 1;
+        self.area += area;
+        self.rural += rural;
+    }
+    
+    fn add_burg(&mut self, population: f32) {
+        self.burgs += 1;
+        self.urban += population;
+    }
+}
+
+impl WorldStatistics {
+    pub fn total_area(&self) -> f32 {
+        self.land.area + self.water.area
+    }
+    
+    pub fn total_population(&self) -> f32 {
+        self.land.population() + self.water.population()
+    }
+    
+    /// Share of the map area that is land, from 0 to 1
+    pub fn land_ratio(&self) -> f32 {
+        let total = self.total_area();
+        if total > 0.0 {
+            self.land.area / total
+        } else {
+            0.0
+        }
+    }
+    
+    /// Share of the map area covered by each biome, largest first; the shares add up to 1
+    pub fn biome_distribution(&self) -> Vec<(BiomeType, f32)> {
+        let total = self.total_area();
+        self.biomes
+            .iter()
+            .map(|(biome, statistics)| (*biome, if total > 0.0 { statistics.area / total } else { 0.0 }))
+            .collect()
+    }
+}
+
+impl WorldMap {
+    /// Area and population totals of the world and its states, cultures,
+    /// religions and biomes
+    pub fn statistics(&self) -> WorldStatistics {
+        let topology = self.topology();
+        let settings = &self.metadata.settings;
+        let mut statistics = WorldStatistics {
+            area_unit: settings.area_unit.clone(),
+            land: AreaStatistics::default(),
+            water: AreaStatistics::default(),
+            states: BTreeMap::new(),
+            cultures: BTreeMap::new(),
+            religions: BTreeMap::new(),
+            biomes: Vec::new(),
+        };
+        let mut biomes: HashMap<BiomeType, AreaStatistics> = HashMap::new();
+        
+        for (index, cell) in self.cells.iter().enumerate() {
+            let area = settings.area(topology.areas[index]);
+            let rural = cell.population as f32;
+            let surface = if cell.biome.is_water() { &mut statistics.water } else { &mut statistics.land };
+            surface.add_cell(area, rural);
+            biomes.entry(cell.biome).or_default().add_cell(area, rural);
+            if let Some(state) = cell.state {
+                statistics.states.entry(state).or_default().add_cell(area, rural);
+            }
+            if let Some(culture) = cell.culture {
+                statistics.cultures.entry(culture).or_default().add_cell(area, rural);
+            }
+            if let Some(religion) = cell.religion {
+                statistics.religions.entry(religion).or_default().add_cell(area, rural);
+            }
+        }
+        
+        for burg in &self.burgs {
+            let cell = topology.index_of(burg.cell).map(|index| &self.cells[index]);
+            let on_water = cell.is_some_and(|cell| cell.biome.is_water());
+            let surface = if on_water { &mut statistics.water } else { &mut statistics.land };
+            surface.add_burg(burg.population);
+            statistics.states.entry(burg.state).or_default().add_burg(burg.population);
+            statistics.cultures.entry(burg.culture).or_default().add_burg(burg.population);
+            if let Some(cell) = cell {
+                biomes.entry(cell.biome).or_default().add_burg(burg.population);
+                if let Some(religion) = cell.religion {
+                    statistics.religions.entry(religion).or_default().add_burg(burg.population);
+                }
+            }
+        }
+        
+        statistics.biomes = biomes.into_iter().collect();
+        statistics.biomes.sort_by(|a, b| b.1.area.total_cmp(&a.1.area));
+        statistics
+    }
+    
+    /// Most populous burgs, largest first
+    pub fn largest_burgs(&self, count: usize) -> Vec<&Settlement> {
+        let mut burgs: Vec<&Settlement> = self.burgs.iter().collect();
+        burgs.sort_by(|a, b| b.population.total_cmp(&a.population));
+        burgs.truncate(count);
+        burgs
+    }
+    
+    /// Longest rivers, longest first
+    pub fn longest_rivers(&self, count: usize) -> Vec<&River> {
+        let mut rivers: Vec<&River> = self.rivers.iter().collect();
+        rivers.sort_by(|a, b| b.length.total_cmp(&a.length));
+        rivers.truncate(count);
+        rivers
+    }
+    
+    /// Highest land cells that are higher than all their neighbours, highest first
+    ///
+    /// Of neighbouring cells at the same height, only the first counts as a peak.
+    pub fn highest_peaks(&self, count: usize) -> Vec<Peak> {
+        let topology = self.topology();
+        let mut peaks: Vec<Peak> = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(index, cell)| {
+                !cell.biome.is_water()
+                    && topology.neighbours[*index].iter().all(|&other| {
+                        let neighbour = self.cells[other].height;
+                        cell.height > neighbour || (cell.height == neighbour && *index < other)
+                    })
+            })
+            .map(|(_, cell)| Peak {
+                cell: cell.id,
+                position: cell.coordinates,
+                height: cell.height,
+                elevation: self.metadata.settings.elevation(cell.height),
+            })
+            .collect();
+        peaks.sort_by(|a, b| b.height.total_cmp(&a.height));
+        peaks.truncate(count);
+        peaks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::{BurgId, DistanceUnit, RiverId, RiverType};
+    
+    fn world() -> WorldMap {
+        let mut world_map = grid_world(4);
+        let settings = &mut world_map.metadata.settings;
+        settings.distance_unit = DistanceUnit::Kilometre;
+        settings.distance_scale = 2.0;
+        settings.area_unit = AreaUnit::SquareKilometre;
+        for cell in world_map.cells.iter_mut() {
+            cell.population = 10;
+            cell.religion = (cell.coordinates.y < 20.0).then_some(1);
+        }
+        for burg in world_map.burgs.iter_mut() {
+            burg.population = burg.id.0 as f32 * 100.0;
+        }
+        world_map
+    }
+    
+    #[test]
+    fn totals_add_up_over_land_water_and_groups() {
+        let world_map = world();
+        let statistics = world_map.statistics();
+        
+        // 16 cells of 10 × 10 map units, each 2 km long
+        assert_eq!(statistics.area_unit, AreaUnit::SquareKilometre);
+        assert!((statistics.total_area() - 6400.0).abs() < 0.1);
+        assert!((statistics.land_ratio() - 0.75).abs() < 1e-4);
+        assert_eq!((statistics.land.cells, statistics.water.cells), (12, 4));
+        assert_eq!(statistics.land.burgs + statistics.water.burgs, 8);
+        assert_eq!(statistics.total_population(), 160.0 + 3600.0);
+        
+        // Burgs at sea belong to no state and count as neutral
+        assert_eq!(statistics.states.len(), 5);
+        assert_eq!(statistics.states[&StateId::NEUTRAL].cells, 0);
+        assert_eq!(statistics.states[&StateId::NEUTRAL].burgs, statistics.water.burgs);
+        let state = &statistics.states[&StateId(4)];
+        assert_eq!((state.cells, state.rural), (4, 40.0));
+        assert!((state.density() - state.population() / 1600.0).abs() < 1e-4);
+        assert_eq!(statistics.cultures[&CultureId(0)].urban, 3600.0);
+        assert_eq!(statistics.religions[&1].cells, 8);
+        assert_eq!(AreaStatistics::default().density(), 0.0);
+        
+        assert_eq!(statistics.biomes[0].0, BiomeType::Temperate);
+        let distribution = statistics.biome_distribution();
+        assert!((distribution.iter().map(|(_, share)| share).sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!((distribution[1].1 - 0.25).abs() < 1e-4);
+    }
+    
+    #[test]
+    fn rankings_put_the_largest_first() {
+        let mut world_map = world();
+        for (id, length) in [(1, 30.0), (2, 50.0), (3, 10.0)] {
+            world_map.rivers.push(River {
+                id: RiverId(id),
+                source: CellId(1),
+                mouth: CellId(2),
+                discharge: 1.0,
+                length,
+                width: 1.0,
+                cells: vec![CellId(1), CellId(2)],
+                basin: RiverId(id),
+                name: format!("River {}", id),
+                type_: RiverType::River,
+                note: None,
+                attributes: Default::default(),
+            });
+        }
+        
+        let burgs: Vec<BurgId> = world_map.largest_burgs(3).iter().map(|burg| burg.id).collect();
+        assert_eq!(burgs, [BurgId(8), BurgId(7), BurgId(6)]);
+        let rivers: Vec<RiverId> = world_map.longest_rivers(5).iter().map(|river| river.id).collect();
+        assert_eq!(rivers, [RiverId(2), RiverId(1), RiverId(3)]);
+        
+        // Heights rise eastwards, so the east column ties and its first cell is the peak
+        let peaks = world_map.highest_peaks(5);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].cell, CellId(3));
+        assert_eq!(peaks[0].elevation, world_map.metadata.settings.elevation(peaks[0].height));
+    }
+}
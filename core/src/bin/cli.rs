@@ -0,0 +1,78 @@
+//! Command line tools for World Foundry maps
+
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use world_foundry_core::{ExporterRegistry, ImporterRegistry, Result, WorldMap};
+
+#[derive(Parser)]
+#[command(name = "world-foundry-cli", version, about = "Command line tools for World Foundry maps")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the changes between two versions of a map
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Print the diff as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Merge two versions of a map edited from a common base
+    ///
+    /// Conflicting edits keep the value from OURS and are listed; the exit
+    /// status is 1 when there were any.
+    Merge {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        /// File to write the merged map to; the format follows its extension
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    match cli.command {
+        Command::Diff { old, new, json } => {
+            let diff = load(&old)?.diff(&load(&new)?)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print!("{}", diff);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Merge { base, ours, theirs, output } => {
+            let result = WorldMap::merge(&load(&base)?, &load(&ours)?, &load(&theirs)?)?;
+            ExporterRegistry::new().export(&result.world_map, &output)?;
+            for conflict in &result.conflicts {
+                println!("conflict: {}", conflict);
+            }
+            if result.is_clean() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                eprintln!("{} conflicts, resolved in favour of {}", result.conflicts.len(), ours.display());
+                Ok(ExitCode::from(1))
+            }
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<WorldMap> {
+    ImporterRegistry::new().import(path)
+}
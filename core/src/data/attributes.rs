@@ -0,0 +1,180 @@
+//! User attributes and notes attached to map entities
+//!
+//! Campaign writers keep lore, stat blocks and secrets on the entities they
+//! describe. Attributes are free-form key/value data; notes are titled rich
+//! text, like the legends Azgaar shows for map objects.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Titled rich-text note
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    pub title: String,
+    /// Body of the note, as HTML
+    pub legend: String,
+}
+
+/// Key/value data added by users, sorted by key
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Attributes(BTreeMap<String, AttributeValue>);
+
+/// Value of an attribute; lists and tables can nest, for stat blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    List(Vec<AttributeValue>),
+    Table(BTreeMap<String, AttributeValue>),
+}
+
+impl Note {
+    pub fn new(title: impl Into<String>, legend: impl Into<String>) -> Self {
+        Self { title: title.into(), legend: legend.into() }
+    }
+}
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn get(&self, key: &str) -> Option<&AttributeValue> {
+        self.0.get(key)
+    }
+    
+    /// Set an attribute, returning the value it replaces
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<AttributeValue>) -> Option<AttributeValue> {
+        self.0.insert(key.into(), value.into())
+    }
+    
+    pub fn remove(&mut self, key: &str) -> Option<AttributeValue> {
+        self.0.remove(key)
+    }
+    
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AttributeValue)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value))
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl AttributeValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            AttributeValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+    
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            AttributeValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+    
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            AttributeValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(value: f64) -> Self {
+        AttributeValue::Number(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Number(value as f64)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::Text(value)
+    }
+}
+
+impl From<Vec<AttributeValue>> for AttributeValue {
+    fn from(values: Vec<AttributeValue>) -> Self {
+        AttributeValue::List(values)
+    }
+}
+
+impl<K: Into<String>, V: Into<AttributeValue>> FromIterator<(K, V)> for Attributes {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        Self(entries.into_iter().map(|(key, value)| (key.into(), value.into())).collect())
+    }
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::Bool(value) => write!(f, "{}", value),
+            AttributeValue::Number(value) => write!(f, "{}", value),
+            AttributeValue::Text(value) => f.write_str(value),
+            AttributeValue::List(values) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            AttributeValue::Table(entries) => {
+                let entries: Vec<String> = entries.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn attributes_serialize_as_plain_json() {
+        let mut attributes: Attributes =
+            [("hp", AttributeValue::from(12i64)), ("secret", "cursed".into())].into_iter().collect();
+        assert_eq!(attributes.set("hp", 14.5), Some(AttributeValue::Number(12.0)));
+        attributes.set("tags", vec![AttributeValue::from(true), "old".into()]);
+        
+        let json = serde_json::to_string(&attributes).unwrap();
+        assert_eq!(json, r#"{"hp":14.5,"secret":"cursed","tags":[true,"old"]}"#);
+        let read: Attributes = serde_json::from_str(r#"{"stats":{"str":3,"alive":false},"hp":14.5}"#).unwrap();
+        assert_eq!(read.get("hp").and_then(AttributeValue::as_number), Some(14.5));
+        assert_eq!(read.get("stats").unwrap().to_string(), "{alive: false, str: 3}");
+        assert_eq!(read.iter().map(|(key, _)| key).collect::<Vec<_>>(), ["hp", "stats"]);
+        
+        assert_eq!(attributes.get("secret").and_then(AttributeValue::as_text), Some("cursed"));
+        assert_eq!(attributes.get("secret").and_then(AttributeValue::as_bool), None);
+        assert_eq!(attributes.get("tags").unwrap().to_string(), "[true, old]");
+        assert_eq!(attributes.remove("secret"), Some(AttributeValue::from("cursed")));
+        assert_eq!(attributes.len(), 2);
+        assert!(Attributes::new().is_empty());
+    }
+}
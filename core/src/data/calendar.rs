@@ -0,0 +1,302 @@
+//! In-world calendar and dated events
+//!
+//! Years are counted on one continuous reckoning shared with the simulated
+//! history; eras only change how a year is written. Days are numbered from the
+//! first day of year 0, which falls on the first weekday and at new moon for
+//! every moon with no phase offset.
+
+use super::{Attributes, EntityRef, EventId, Note, WorldMap};
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Calendar of a world
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calendar {
+    /// Eras in order of their start year
+    pub eras: Vec<Era>,
+    /// Present year of the world
+    pub year: i32,
+    pub months: Vec<Month>,
+    pub weekdays: Vec<String>,
+    pub moons: Vec<Moon>,
+}
+
+/// Period a year is counted in, such as "Before Darkness"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Era {
+    pub name: String,
+    /// Abbreviation written after years, such as "BD"
+    pub short: String,
+    /// First year of the era; it is year 1 of the era
+    pub start_year: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Month {
+    pub name: String,
+    pub days: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Moon {
+    pub name: String,
+    /// Days from one new moon to the next
+    pub period: f32,
+    /// Phase on day 0, from 0 (new) through 0.5 (full) to 1
+    #[serde(default)]
+    pub offset: f32,
+}
+
+/// Day of the calendar; months and days count from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct WorldDate {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// Something that happened on a given day, tied to places and entities of the map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatedEvent {
+    pub id: EventId,
+    pub date: WorldDate,
+    pub note: Note,
+    /// States, burgs, markers and other entities involved
+    #[serde(default)]
+    pub subjects: Vec<EntityRef>,
+    /// Where it happened, in map coordinates
+    #[serde(default)]
+    pub location: Option<Point2<f32>>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+impl Calendar {
+    /// Days in a year
+    pub fn year_length(&self) -> u32 {
+        self.months.iter().map(|month| month.days).sum()
+    }
+    
+    /// Era a year is counted in: the latest era started by then
+    pub fn era_of(&self, year: i32) -> Option<&Era> {
+        self.eras.iter().filter(|era| era.start_year <= year).max_by_key(|era| era.start_year)
+    }
+    
+    /// The present era
+    pub fn current_era(&self) -> Option<&Era> {
+        self.era_of(self.year)
+    }
+    
+    /// Whether the month and day exist in the calendar
+    pub fn is_valid(&self, date: WorldDate) -> bool {
+        date.month >= 1
+            && self.months.get(date.month as usize - 1).is_some_and(|month| date.day >= 1 && date.day <= month.days)
+    }
+    
+    /// Day number of a date counted from the first day of year 0; `None` for
+    /// a date not in the calendar
+    pub fn day_number(&self, date: WorldDate) -> Option<i64> {
+        if !self.is_valid(date) {
+            return None;
+        }
+        let before: u32 = self.months[..date.month as usize - 1].iter().map(|month| month.days).sum();
+        Some(date.year as i64 * self.year_length() as i64 + before as i64 + date.day as i64 - 1)
+    }
+    
+    /// Date of a day number; `None` for a calendar without days
+    pub fn date_of(&self, day_number: i64) -> Option<WorldDate> {
+        let length = self.year_length() as i64;
+        if length == 0 {
+            return None;
+        }
+        let year = day_number.div_euclid(length);
+        let mut day = day_number.rem_euclid(length) as u32;
+        for (index, month) in self.months.iter().enumerate() {
+            if day < month.days {
+                return Some(WorldDate { year: year as i32, month: index as u32 + 1, day: day + 1 });
+            }
+            day -= month.days;
+        }
+        None
+    }
+    
+    /// Date a number of days after another, or before for a negative count
+    pub fn add_days(&self, date: WorldDate, days: i64) -> Option<WorldDate> {
+        self.date_of(self.day_number(date)? + days)
+    }
+    
+    /// Days from one date to another, negative if `to` comes first
+    pub fn days_between(&self, from: WorldDate, to: WorldDate) -> Option<i64> {
+        Some(self.day_number(to)? - self.day_number(from)?)
+    }
+    
+    /// Name of the weekday a date falls on
+    pub fn weekday(&self, date: WorldDate) -> Option<&str> {
+        let count = self.weekdays.len() as i64;
+        if count == 0 {
+            return None;
+        }
+        let index = self.day_number(date)?.rem_euclid(count);
+        Some(&self.weekdays[index as usize])
+    }
+    
+    /// Phase of a moon on a date, from 0 (new) through 0.5 (full) to 1
+    pub fn moon_phase(&self, moon: &Moon, date: WorldDate) -> Option<f32> {
+        if moon.period <= 0.0 {
+            return None;
+        }
+        let cycles = self.day_number(date)? as f64 / moon.period as f64 + moon.offset as f64;
+        Some(cycles.rem_euclid(1.0) as f32)
+    }
+    
+    /// Year as written in its era, such as "1024 BD"
+    pub fn format_year(&self, year: i32) -> String {
+        match self.era_of(year) {
+            Some(era) => format!("{} {}", year - era.start_year + 1, era.short),
+            None => year.to_string(),
+        }
+    }
+    
+    /// Date as written in the calendar, such as "3 Harvest 1024 BD"
+    pub fn format(&self, date: WorldDate) -> String {
+        let month = date.month.checked_sub(1).and_then(|index| self.months.get(index as usize));
+        match month {
+            Some(month) => format!("{} {} {}", date.day, month.name, self.format_year(date.year)),
+            None => format!("{}-{} {}", date.month, date.day, self.format_year(date.year)),
+        }
+    }
+}
+
+impl WorldDate {
+    pub fn new(year: i32, month: u32, day: u32) -> Self {
+        Self { year, month, day }
+    }
+}
+
+impl DatedEvent {
+    pub fn new(id: EventId, date: WorldDate, note: Note) -> Self {
+        Self { id, date, note, subjects: Vec::new(), location: None, attributes: Attributes::new() }
+    }
+}
+
+impl WorldMap {
+    /// Events involving an entity, in date order
+    pub fn events_of(&self, entity: EntityRef) -> Vec<&DatedEvent> {
+        let mut events: Vec<&DatedEvent> = self.events.iter().filter(|event| event.subjects.contains(&entity)).collect();
+        events.sort_by_key(|event| event.date);
+        events
+    }
+    
+    /// Events from one date to another, both included, in date order
+    pub fn events_between(&self, from: WorldDate, to: WorldDate) -> Vec<&DatedEvent> {
+        let mut events: Vec<&DatedEvent> =
+            self.events.iter().filter(|event| event.date >= from && event.date <= to).collect();
+        events.sort_by_key(|event| event.date);
+        events
+    }
+    
+    /// Add an event with the next free id, returning the id
+    pub fn add_event(&mut self, date: WorldDate, note: Note, subjects: Vec<EntityRef>) -> EventId {
+        let id = EventId(self.events.iter().map(|event| event.id.0 + 1).max().unwrap_or(0));
+        let mut event = DatedEvent::new(id, date, note);
+        event.subjects = subjects;
+        self.events.push(event);
+        id
+    }
+}
+
+/// Twelve months of 30 or 31 days, a seven-day week and one moon
+impl Default for Calendar {
+    fn default() -> Self {
+        let months = [
+            ("January", 31), ("February", 28), ("March", 31), ("April", 30), ("May", 31), ("June", 30),
+            ("July", 31), ("August", 31), ("September", 30), ("October", 31), ("November", 30), ("December", 31),
+        ];
+        let weekdays = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        Self {
+            eras: Vec::new(),
+            year: 0,
+            months: months.iter().map(|&(name, days)| Month { name: name.to_string(), days }).collect(),
+            weekdays: weekdays.iter().map(|name| name.to_string()).collect(),
+            moons: vec![Moon { name: "Moon".to_string(), period: 29.53, offset: 0.0 }],
+        }
+    }
+}
+
+impl fmt::Display for WorldDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{BurgId, IssueKind, MarkerId, StateId};
+    
+    #[test]
+    fn dates_count_across_years_both_ways() {
+        let calendar = Calendar::default();
+        assert_eq!(calendar.year_length(), 365);
+        assert_eq!(calendar.day_number(WorldDate::new(1, 1, 1)), Some(365));
+        assert_eq!(calendar.date_of(-1), Some(WorldDate::new(-1, 12, 31)));
+        assert_eq!(calendar.add_days(WorldDate::new(0, 2, 28), 1), Some(WorldDate::new(0, 3, 1)));
+        assert_eq!(calendar.days_between(WorldDate::new(3, 1, 10), WorldDate::new(2, 12, 31)), Some(-10));
+        assert_eq!(calendar.day_number(WorldDate::new(0, 2, 29)), None);
+        assert_eq!(calendar.day_number(WorldDate::new(0, 13, 1)), None);
+        assert_eq!(Calendar { months: Vec::new(), ..Calendar::default() }.date_of(5), None);
+        
+        assert_eq!(calendar.weekday(WorldDate::new(0, 1, 1)), Some("Monday"));
+        assert_eq!(calendar.weekday(WorldDate::new(1, 1, 1)), Some("Tuesday"));
+        assert_eq!(calendar.weekday(WorldDate::new(-1, 12, 31)), Some("Sunday"));
+        let moon = &calendar.moons[0];
+        assert_eq!(calendar.moon_phase(moon, WorldDate::new(0, 1, 1)), Some(0.0));
+        assert!((calendar.moon_phase(moon, WorldDate::new(0, 1, 16)).unwrap() - 0.508).abs() < 1e-3);
+    }
+    
+    #[test]
+    fn years_are_written_in_their_era() {
+        let calendar = Calendar {
+            eras: vec![
+                Era { name: "Age of Ash".to_string(), short: "AA".to_string(), start_year: -100 },
+                Era { name: "Bright Age".to_string(), short: "BA".to_string(), start_year: 1 },
+            ],
+            year: 1024,
+            ..Calendar::default()
+        };
+        assert_eq!(calendar.current_era().map(|era| era.short.as_str()), Some("BA"));
+        assert_eq!(calendar.format_year(0), "101 AA");
+        assert_eq!(calendar.format_year(-200), "-200");
+        assert_eq!(calendar.format(WorldDate::new(1024, 3, 5)), "5 March 1024 BA");
+        assert_eq!(calendar.format(WorldDate::new(1, 14, 2)), "14-2 1 BA");
+        assert_eq!(WorldDate::new(7, 3, 5).to_string(), "7-03-05");
+    }
+    
+    #[test]
+    fn events_are_found_by_subject_and_date() {
+        let mut world_map = grid_world(4);
+        let burg = EntityRef::Settlement(BurgId(2));
+        let first = world_map.add_event(WorldDate::new(5, 6, 1), Note::new("Siege", ""), vec![burg]);
+        let second = world_map.add_event(
+            WorldDate::new(5, 2, 1),
+            Note::new("Treaty", ""),
+            vec![EntityRef::State(StateId(1)), burg],
+        );
+        assert_eq!((first, second), (EventId(0), EventId(1)));
+        
+        let of_burg: Vec<EventId> = world_map.events_of(burg).iter().map(|event| event.id).collect();
+        assert_eq!(of_burg, [second, first]);
+        assert_eq!(world_map.events_between(WorldDate::new(5, 3, 1), WorldDate::new(5, 6, 1)).len(), 1);
+        assert!(world_map.validate().is_valid());
+        
+        world_map.add_event(WorldDate::new(6, 1, 1), Note::new("Omen", ""), vec![EntityRef::Marker(MarkerId(99))]);
+        let report = world_map.validate();
+        assert!(report.errors().any(|issue| matches!(
+            &issue.kind,
+            IssueKind::DanglingReference { target: EntityRef::Marker(MarkerId(99)), .. }
+        )));
+    }
+}
@@ -0,0 +1,126 @@
+//! Colours of map entities
+//!
+//! Colours are stored as CSS colour strings, like Azgaar's. Hex colours written
+//! as `#rrggbb` or `#rrggbbaa` in lower case are parsed so renderers can use the
+//! channels directly; anything else, such as `#ABC` or a hatching pattern
+//! reference, is kept verbatim so it is saved exactly as it was read.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Color {
+    /// Red, green, blue and alpha channels, written as `#rrggbb` or `#rrggbbaa`
+    Rgba([u8; 4]),
+    /// Any other CSS paint, kept as it was read
+    Other(String),
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color::Rgba([r, g, b, 255])
+    }
+    
+    /// Parse a colour written as `Display` writes it; other strings become
+    /// `Other`, so the colour writes back exactly as it was read
+    pub fn parse(value: &str) -> Self {
+        match hex_channels(value).map(Color::Rgba) {
+            Some(color) if color.to_string() == value => color,
+            _ => Color::Other(value.to_string()),
+        }
+    }
+    
+    /// Channels of the colour, if it is a plain colour, read from any `#rgb`,
+    /// `#rrggbb` or `#rrggbbaa` text kept in `Other`
+    pub fn rgba(&self) -> Option<[u8; 4]> {
+        match self {
+            Color::Rgba(channels) => Some(*channels),
+            Color::Other(value) => hex_channels(value),
+        }
+    }
+    
+    /// Mix with white; `amount` 0 keeps the colour, 1 gives white
+    pub fn lighten(&self, amount: f32) -> Color {
+        match self.rgba() {
+            Some([r, g, b, a]) => {
+                let mix = |channel: u8| (channel as f32 + (255.0 - channel as f32) * amount.clamp(0.0, 1.0)).round() as u8;
+                Color::Rgba([mix(r), mix(g), mix(b), a])
+            }
+            None => self.clone(),
+        }
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::rgb(128, 128, 128)
+    }
+}
+
+impl From<&str> for Color {
+    fn from(value: &str) -> Self {
+        Color::parse(value)
+    }
+}
+
+impl From<String> for Color {
+    fn from(value: String) -> Self {
+        Color::parse(&value)
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_string()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Rgba([r, g, b, 255]) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            Color::Rgba([r, g, b, a]) => write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            Color::Other(value) => f.write_str(value),
+        }
+    }
+}
+
+/// Channels of `#rgb`, `#rrggbb` or `#rrggbbaa` in either case
+fn hex_channels(value: &str) -> Option<[u8; 4]> {
+    let hex = value.trim().strip_prefix('#').filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let channel = |index: usize| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap_or(0);
+    match hex.len() {
+        3 => {
+            let short = |index: usize| u8::from_str_radix(&hex[index..index + 1], 16).unwrap_or(0) * 17;
+            Some([short(0), short(1), short(2), 255])
+        }
+        6 => Some([channel(0), channel(1), channel(2), 255]),
+        8 => Some([channel(0), channel(1), channel(2), channel(3)]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn colours_round_trip_through_serde() {
+        for text in ["#ff0000", "#12345678", "#ABC", "#FF0000", " #ff0000 ", "#ff0000ff", "url(#hatch3)", ""] {
+            let json = serde_json::to_string(text).unwrap();
+            let color: Color = serde_json::from_str(&json).unwrap();
+            assert_eq!(serde_json::to_string(&color).unwrap(), json);
+        }
+    }
+    
+    #[test]
+    fn channels_are_read_from_any_hex_spelling() {
+        assert_eq!(Color::parse("#ff0000"), Color::rgb(255, 0, 0));
+        assert_eq!(Color::parse("#F00"), Color::Other("#F00".to_string()));
+        assert_eq!(Color::parse("#F00").rgba(), Some([255, 0, 0, 255]));
+        assert_eq!(Color::parse("#FF000080").rgba(), Some([255, 0, 0, 128]));
+        assert_eq!(Color::parse("url(#hatch3)").rgba(), None);
+        assert_eq!(Color::parse("#000").lighten(1.0), Color::rgb(255, 255, 255));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,433 @@
+//! Differences between two versions of a world map
+//!
+//! Entities are matched by id and compared field by field in their serialized
+//! form, so diffs follow the data structures without listing their fields.
+
+use super::{
+    BurgId, Calendar, Cell, CellId, Culture, CultureId, DatedEvent, DiplomacyMatrix, EntityRef, EventId, Feature, FeatureId,
+    Grid, MapMetadata, Marker, MarkerId, Province, ProvinceId, River, RiverId, Route, RouteId, Settlement, State, StateId,
+    Timeline, WorldMap, Zone,
+};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Heights closer than this count as unchanged
+pub(crate) const HEIGHT_TOLERANCE: f32 = 1e-4;
+
+/// Reference to an entity of some kind, given its id
+pub(crate) type EntityOf = fn(u32) -> EntityRef;
+
+/// Collections of entities matched by id, with the kind of entity they hold
+pub(crate) const COLLECTIONS: &[(&str, EntityOf)] = &[
+    ("cells", |id| EntityRef::Cell(CellId(id))),
+    ("features", |id| EntityRef::Feature(FeatureId(id))),
+    ("cultures", |id| EntityRef::Culture(CultureId(id))),
+    ("states", |id| EntityRef::State(StateId(id))),
+    ("provinces", |id| EntityRef::Province(ProvinceId(id))),
+    ("burgs", |id| EntityRef::Settlement(BurgId(id))),
+    ("rivers", |id| EntityRef::River(RiverId(id))),
+    ("routes", |id| EntityRef::Route(RouteId(id))),
+    ("markers", |id| EntityRef::Marker(MarkerId(id))),
+    ("events", |id| EntityRef::Event(EventId(id))),
+];
+
+/// Parts of a world map compared as a whole
+pub(crate) const SECTIONS: &[&str] = &["zones", "diplomacy", "history", "calendar"];
+
+/// Metadata fields that change on every save and are not worth reporting
+pub(crate) const VOLATILE_METADATA: &[&str] = &["modified_at"];
+
+/// Serialized form of a world map without its heightmap, which is compared as a grid
+#[derive(Serialize)]
+struct Document<'a> {
+    metadata: &'a MapMetadata,
+    cells: &'a [Cell],
+    features: &'a [Feature],
+    cultures: &'a [Culture],
+    states: &'a [State],
+    provinces: &'a [Province],
+    burgs: &'a [Settlement],
+    rivers: &'a [River],
+    routes: &'a [Route],
+    markers: &'a [Marker],
+    zones: &'a [Zone],
+    diplomacy: &'a DiplomacyMatrix,
+    history: &'a Timeline,
+    calendar: &'a Calendar,
+    events: &'a [DatedEvent],
+}
+
+/// Structured difference from one world map to another
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MapDiff {
+    pub metadata: Vec<FieldChange>,
+    /// Entities added, removed or changed, cells only when added or removed
+    pub entities: Vec<EntityChange>,
+    /// Changed attributes of cells present in both maps
+    pub cells: Vec<CellChange>,
+    pub heightmap: Vec<HeightmapChange>,
+    /// Sections without ids that differ, such as "diplomacy"
+    pub sections: Vec<String>,
+    /// Ids held by more than one entity in either map; those entities are not compared
+    #[serde(default)]
+    pub duplicates: Vec<EntityRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EntityChange {
+    Added { entity: EntityRef },
+    Removed { entity: EntityRef },
+    Changed { entity: EntityRef, fields: Vec<FieldChange> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellChange {
+    pub cell: CellId,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HeightmapChange {
+    /// The grid changed size, so heights were not compared
+    Resized { from: (u32, u32), to: (u32, u32) },
+    /// Connected heights that changed
+    Region(HeightmapRegion),
+}
+
+/// Bounding box of connected heightmap values, in grid coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeightmapRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Number of values in the region that differ
+    pub count: u32,
+    pub max_delta: f32,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+            && self.entities.is_empty()
+            && self.cells.is_empty()
+            && self.heightmap.is_empty()
+            && self.sections.is_empty()
+            && self.duplicates.is_empty()
+    }
+}
+
+impl WorldMap {
+    /// Changes that turn this map into `other`
+    pub fn diff(&self, other: &WorldMap) -> Result<MapDiff> {
+        let old = document(self)?;
+        let new = document(other)?;
+        let mut diff = MapDiff {
+            heightmap: heightmap_changes(&self.heightmap, &other.heightmap),
+            ..Default::default()
+        };
+        
+        diff.metadata = field_changes(&old["metadata"], &new["metadata"]);
+        diff.metadata.retain(|change| !VOLATILE_METADATA.contains(&change.field.as_str()));
+        
+        for &(key, entity) in COLLECTIONS {
+            let old_items = ById::new(&old[key]);
+            let new_items = ById::new(&new[key]);
+            let duplicates: BTreeSet<u32> = old_items.duplicates.union(&new_items.duplicates).copied().collect();
+            diff.duplicates.extend(duplicates.iter().map(|&id| entity(id)));
+            
+            for (id, old_item) in old_items.iter().filter(|&(id, _)| !duplicates.contains(&id)) {
+                let Some(new_item) = new_items.get(id) else {
+                    diff.entities.push(EntityChange::Removed { entity: entity(id) });
+                    continue;
+                };
+                let fields = field_changes(old_item, new_item);
+                if fields.is_empty() {
+                    continue;
+                }
+                match entity(id) {
+                    EntityRef::Cell(cell) => diff.cells.push(CellChange { cell, fields }),
+                    entity => diff.entities.push(EntityChange::Changed { entity, fields }),
+                }
+            }
+            let added = new_items.iter().filter(|&(id, _)| !duplicates.contains(&id) && old_items.get(id).is_none());
+            for (id, _) in added {
+                diff.entities.push(EntityChange::Added { entity: entity(id) });
+            }
+        }
+        
+        diff.sections = SECTIONS.iter().filter(|&&key| old[key] != new[key]).map(|key| key.to_string()).collect();
+        Ok(diff)
+    }
+}
+
+/// Serialize the parts of a world map compared as values
+pub(crate) fn document(world_map: &WorldMap) -> Result<Value> {
+    Ok(serde_json::to_value(Document {
+        metadata: &world_map.metadata,
+        cells: &world_map.cells,
+        features: &world_map.features,
+        cultures: &world_map.cultures,
+        states: &world_map.states,
+        provinces: &world_map.provinces,
+        burgs: &world_map.burgs,
+        rivers: &world_map.rivers,
+        routes: &world_map.routes,
+        markers: &world_map.markers,
+        zones: &world_map.zones,
+        diplomacy: &world_map.diplomacy,
+        history: &world_map.history,
+        calendar: &world_map.calendar,
+        events: &world_map.events,
+    })?)
+}
+
+/// Positions of the entities of a serialized collection by id
+pub(crate) struct ById<'a> {
+    pub items: &'a [Value],
+    positions: BTreeMap<u32, usize>,
+    /// Ids held by more than one entity, which are left out of `positions`
+    pub duplicates: BTreeSet<u32>,
+}
+
+impl<'a> ById<'a> {
+    pub fn new(items: &'a Value) -> Self {
+        let items = items.as_array().map(Vec::as_slice).unwrap_or_default();
+        let mut positions = BTreeMap::new();
+        let mut duplicates = BTreeSet::new();
+        for (position, item) in items.iter().enumerate() {
+            let Some(id) = id_of(item) else {
+                continue;
+            };
+            if positions.insert(id, position).is_some() {
+                duplicates.insert(id);
+            }
+        }
+        positions.retain(|id, _| !duplicates.contains(id));
+        ById { items, positions, duplicates }
+    }
+    
+    pub fn position(&self, id: u32) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+    
+    pub fn get(&self, id: u32) -> Option<&'a Value> {
+        self.position(id).map(|position| &self.items[position])
+    }
+    
+    /// Entities with a unique id, in id order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &'a Value)> + '_ {
+        self.positions.iter().map(|(&id, &position)| (id, &self.items[position]))
+    }
+}
+
+pub(crate) fn id_of(item: &Value) -> Option<u32> {
+    item.get("id")?.as_u64().map(|id| id as u32)
+}
+
+/// Top-level fields of two serialized entities that differ
+fn field_changes(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    let fields: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let old = old.get(field).unwrap_or(&Value::Null);
+            let new = new.get(field).unwrap_or(&Value::Null);
+            (old != new).then(|| FieldChange { field: field.clone(), old: old.clone(), new: new.clone() })
+        })
+        .collect()
+}
+
+fn heightmap_changes(old: &Grid<f32>, new: &Grid<f32>) -> Vec<HeightmapChange> {
+    if !same_size(old, new) {
+        return vec![HeightmapChange::Resized { from: (old.width, old.height), to: (new.width, new.height) }];
+    }
+    delta_regions(old.width, old.height, |index| (new.data[index] - old.data[index]).abs())
+        .into_iter()
+        .map(HeightmapChange::Region)
+        .collect()
+}
+
+/// Whether two grids have the same size and a full set of values
+pub(crate) fn same_size(a: &Grid<f32>, b: &Grid<f32>) -> bool {
+    (a.width, a.height) == (b.width, b.height)
+        && a.data.len() == b.data.len()
+        && a.data.len() as u64 == a.width as u64 * a.height as u64
+}
+
+/// Regions of 8-connected grid values whose delta exceeds `HEIGHT_TOLERANCE`
+pub(crate) fn delta_regions(width: u32, height: u32, delta: impl Fn(usize) -> f32) -> Vec<HeightmapRegion> {
+    let (w, h) = (width as usize, height as usize);
+    let mut visited = vec![false; w * h];
+    let mut regions = Vec::new();
+    
+    for start in 0..w * h {
+        if visited[start] || delta(start) <= HEIGHT_TOLERANCE {
+            continue;
+        }
+        visited[start] = true;
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (start % w, start / w, start % w, start / w);
+        let mut region = HeightmapRegion { x: 0, y: 0, width: 0, height: 0, count: 0, max_delta: 0.0 };
+        let mut stack = vec![start];
+        
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % w, index / w);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            region.count += 1;
+            region.max_delta = region.max_delta.max(delta(index));
+            
+            for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+                    let neighbour = ny * w + nx;
+                    if !visited[neighbour] && delta(neighbour) > HEIGHT_TOLERANCE {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        
+        region.x = min_x as u32;
+        region.y = min_y as u32;
+        region.width = (max_x - min_x + 1) as u32;
+        region.height = (max_y - min_y + 1) as u32;
+        regions.push(region);
+    }
+    regions
+}
+
+impl fmt::Display for EntityChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityChange::Added { entity } => write!(f, "added {}", entity),
+            EntityChange::Removed { entity } => write!(f, "removed {}", entity),
+            EntityChange::Changed { entity, fields } => write!(f, "changed {}: {}", entity, field_names(fields)),
+        }
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+impl fmt::Display for CellChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "changed cell {}: {}", self.cell, field_names(&self.fields))
+    }
+}
+
+impl fmt::Display for HeightmapChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeightmapChange::Resized { from, to } => {
+                write!(f, "resized heightmap from {}x{} to {}x{}", from.0, from.1, to.0, to.1)
+            }
+            HeightmapChange::Region(region) => write!(f, "changed heights in {}", region),
+        }
+    }
+}
+
+impl fmt::Display for HeightmapRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} region at ({}, {}): {} values, up to {:.3}",
+            self.width, self.height, self.x, self.y, self.count, self.max_delta
+        )
+    }
+}
+
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.metadata {
+            writeln!(f, "metadata {}", change)?;
+        }
+        for change in &self.entities {
+            writeln!(f, "{}", change)?;
+        }
+        for change in &self.cells {
+            writeln!(f, "{}", change)?;
+        }
+        for change in &self.heightmap {
+            writeln!(f, "{}", change)?;
+        }
+        for section in &self.sections {
+            writeln!(f, "changed {}", section)?;
+        }
+        for entity in &self.duplicates {
+            writeln!(f, "duplicate {}", entity)?;
+        }
+        Ok(())
+    }
+}
+
+fn field_names(fields: &[FieldChange]) -> String {
+    fields.iter().map(|change| change.field.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::DiplomaticStatus;
+    
+    #[test]
+    fn diffs_list_entities_cells_and_heights() {
+        let old = grid_world(8);
+        assert!(old.diff(&old.clone()).unwrap().is_empty());
+        
+        let mut new = old.clone();
+        new.states[0].name = "Renamed".to_string();
+        new.burgs.retain(|burg| burg.id != BurgId(3));
+        new.cells[10].height = 0.9;
+        new.heightmap.data[5] += 0.5;
+        new.diplomacy = DiplomacyMatrix::default();
+        new.diplomacy.set(StateId(1), StateId(2), DiplomaticStatus::Enemy);
+        
+        let diff = old.diff(&new).unwrap();
+        assert_eq!(diff.entities, vec![
+            EntityChange::Changed {
+                entity: EntityRef::State(StateId(1)),
+                fields: vec![FieldChange { field: "name".to_string(), old: "State 1".into(), new: "Renamed".into() }],
+            },
+            EntityChange::Removed { entity: EntityRef::Settlement(BurgId(3)) },
+        ]);
+        assert_eq!(diff.cells.len(), 1);
+        assert_eq!(diff.cells[0].cell, CellId(10));
+        assert_eq!(diff.cells[0].fields[0].field, "height");
+        assert!(matches!(diff.heightmap[..], [HeightmapChange::Region(HeightmapRegion { x: 5, y: 0, count: 1, .. })]));
+        assert_eq!(diff.sections, vec!["diplomacy".to_string()]);
+    }
+    
+    #[test]
+    fn duplicate_ids_are_reported_instead_of_compared() {
+        let old = grid_world(8);
+        let mut new = old.clone();
+        let mut copy = new.burgs[1].clone();
+        copy.name = "Copy".to_string();
+        new.burgs.push(copy);
+        
+        let diff = old.diff(&new).unwrap();
+        assert_eq!(diff.duplicates, vec![EntityRef::Settlement(BurgId(2))]);
+        assert!(diff.entities.is_empty());
+        assert!(!diff.is_empty());
+    }
+}
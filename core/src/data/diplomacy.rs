@@ -0,0 +1,228 @@
+//! Diplomatic relations between political states
+
+use super::StateId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Stance of one state towards another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiplomaticStatus {
+    Ally,
+    Friendly,
+    Neutral,
+    Suspicion,
+    Rival,
+    Enemy,
+    /// The state is a vassal of the other state
+    Vassal,
+    /// The state is the overlord of the other state
+    Suzerain,
+    /// The states have no contact with each other
+    Unknown,
+}
+
+impl DiplomaticStatus {
+    /// Status the other state holds in return
+    pub fn reciprocal(self) -> Self {
+        match self {
+            DiplomaticStatus::Vassal => DiplomaticStatus::Suzerain,
+            DiplomaticStatus::Suzerain => DiplomaticStatus::Vassal,
+            other => other,
+        }
+    }
+    
+    /// Parse Azgaar's diplomacy labels ("Ally", "Enemy", ...)
+    pub fn from_azgaar(label: &str) -> Option<Self> {
+        match label {
+            "Ally" => Some(DiplomaticStatus::Ally),
+            "Friendly" => Some(DiplomaticStatus::Friendly),
+            "Neutral" => Some(DiplomaticStatus::Neutral),
+            "Suspicion" => Some(DiplomaticStatus::Suspicion),
+            "Rival" => Some(DiplomaticStatus::Rival),
+            "Enemy" => Some(DiplomaticStatus::Enemy),
+            "Vassal" => Some(DiplomaticStatus::Vassal),
+            "Suzerain" => Some(DiplomaticStatus::Suzerain),
+            "Unknown" => Some(DiplomaticStatus::Unknown),
+            _ => None,
+        }
+    }
+    
+    pub fn is_hostile(self) -> bool {
+        matches!(self, DiplomaticStatus::Rival | DiplomaticStatus::Enemy)
+    }
+}
+
+/// Directed relation from one state to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiplomaticRelation {
+    pub from: StateId,
+    pub to: StateId,
+    pub status: DiplomaticStatus,
+}
+
+/// Diplomacy matrix between all states of a world
+///
+/// Relations are directed, like Azgaar's `states[].diplomacy`: a state may
+/// regard its neighbour with suspicion while the neighbour considers it friendly.
+/// Pairs without an entry are treated as neutral.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<DiplomaticRelation>", into = "Vec<DiplomaticRelation>")]
+pub struct DiplomacyMatrix {
+    /// Relations sorted by `(from, to)`
+    relations: Vec<DiplomaticRelation>,
+}
+
+impl DiplomacyMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the stance of `from` towards `to`
+    pub fn status(&self, from: StateId, to: StateId) -> DiplomaticStatus {
+        match self.find(from, to) {
+            Ok(index) => self.relations[index].status,
+            Err(_) => DiplomaticStatus::Neutral,
+        }
+    }
+    
+    /// Set the stance of `from` towards `to` only
+    pub fn set(&mut self, from: StateId, to: StateId, status: DiplomaticStatus) {
+        if from == to {
+            return;
+        }
+        
+        let relation = DiplomaticRelation { from, to, status };
+        match self.find(from, to) {
+            Ok(index) => self.relations[index] = relation,
+            Err(index) => self.relations.insert(index, relation),
+        }
+    }
+    
+    /// Set the stance of `a` towards `b` and the reciprocal stance of `b` towards `a`
+    pub fn set_mutual(&mut self, a: StateId, b: StateId, status: DiplomaticStatus) {
+        self.set(a, b, status);
+        self.set(b, a, status.reciprocal());
+    }
+    
+    /// Remove every relation involving the given state
+    pub fn remove_state(&mut self, state: StateId) {
+        self.relations.retain(|relation| relation.from != state && relation.to != state);
+    }
+    
+    /// All relations held by the given state
+    pub fn relations_of(&self, state: StateId) -> impl Iterator<Item = &DiplomaticRelation> {
+        let start = self.relations.partition_point(|relation| relation.from < state);
+        self.relations[start..]
+            .iter()
+            .take_while(move |relation| relation.from == state)
+    }
+    
+    /// Pairs of states at war, each pair reported once with the lower id first
+    pub fn wars(&self) -> Vec<(StateId, StateId)> {
+        self.relations
+            .iter()
+            .filter(|relation| relation.status == DiplomaticStatus::Enemy)
+            .map(|relation| (relation.from.min(relation.to), relation.from.max(relation.to)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+    
+    /// States the given state is at war with
+    pub fn enemies_of(&self, state: StateId) -> Vec<StateId> {
+        self.relations_of(state)
+            .filter(|relation| relation.status == DiplomaticStatus::Enemy)
+            .map(|relation| relation.to)
+            .collect()
+    }
+    
+    /// Overlord of the given state, if it is a vassal
+    pub fn suzerain_of(&self, state: StateId) -> Option<StateId> {
+        self.relations_of(state)
+            .find(|relation| relation.status == DiplomaticStatus::Vassal)
+            .map(|relation| relation.to)
+    }
+    
+    pub fn iter(&self) -> impl Iterator<Item = &DiplomaticRelation> {
+        self.relations.iter()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.relations.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.relations.is_empty()
+    }
+    
+    fn find(&self, from: StateId, to: StateId) -> std::result::Result<usize, usize> {
+        self.relations
+            .binary_search_by(|relation| (relation.from, relation.to).cmp(&(from, to)))
+    }
+}
+
+impl From<Vec<DiplomaticRelation>> for DiplomacyMatrix {
+    fn from(mut relations: Vec<DiplomaticRelation>) -> Self {
+        relations.retain(|relation| relation.from != relation.to);
+        relations.sort_by_key(|relation| (relation.from, relation.to));
+        relations.dedup_by_key(|relation| (relation.from, relation.to));
+        Self { relations }
+    }
+}
+
+impl From<DiplomacyMatrix> for Vec<DiplomaticRelation> {
+    fn from(matrix: DiplomacyMatrix) -> Self {
+        matrix.relations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiplomaticStatus::*;
+    
+    #[test]
+    fn relations_are_directed_and_default_to_neutral() {
+        let mut matrix = DiplomacyMatrix::new();
+        matrix.set(StateId(2), StateId(1), Suspicion);
+        matrix.set_mutual(StateId(3), StateId(1), Vassal);
+        matrix.set_mutual(StateId(4), StateId(1), Enemy);
+        matrix.set(StateId(1), StateId(1), Enemy);
+        
+        assert_eq!(matrix.status(StateId(2), StateId(1)), Suspicion);
+        assert_eq!(matrix.status(StateId(1), StateId(2)), Neutral);
+        assert_eq!(matrix.status(StateId(1), StateId(3)), Suzerain);
+        assert_eq!(matrix.suzerain_of(StateId(3)), Some(StateId(1)));
+        assert_eq!(matrix.suzerain_of(StateId(1)), None);
+        assert_eq!(matrix.wars(), vec![(StateId(1), StateId(4))]);
+        assert_eq!(matrix.enemies_of(StateId(1)), vec![StateId(4)]);
+        assert_eq!(matrix.relations_of(StateId(1)).count(), 2);
+        
+        matrix.remove_state(StateId(3));
+        assert_eq!(matrix.len(), 3);
+        assert!(matrix.iter().all(|relation| relation.from != StateId(3) && relation.to != StateId(3)));
+    }
+    
+    #[test]
+    fn loading_drops_self_relations_and_repeated_pairs() {
+        let json = r#"[
+            { "from": 2, "to": 1, "status": "Enemy" },
+            { "from": 1, "to": 1, "status": "Ally" },
+            { "from": 2, "to": 1, "status": "Ally" },
+            { "from": 1, "to": 2, "status": "Rival" }
+        ]"#;
+        let matrix: DiplomacyMatrix = serde_json::from_str(json).unwrap();
+        let pairs: Vec<_> = matrix.iter().map(|relation| (relation.from, relation.to, relation.status)).collect();
+        assert_eq!(pairs, [(StateId(1), StateId(2), Rival), (StateId(2), StateId(1), Enemy)]);
+        
+        let reloaded: DiplomacyMatrix = serde_json::from_str(&serde_json::to_string(&matrix).unwrap()).unwrap();
+        assert_eq!(reloaded.iter().collect::<Vec<_>>(), matrix.iter().collect::<Vec<_>>());
+    }
+    
+    #[test]
+    fn azgaar_labels_are_parsed() {
+        assert_eq!(DiplomaticStatus::from_azgaar("Suzerain"), Some(Suzerain));
+        assert_eq!(DiplomaticStatus::from_azgaar("x"), None);
+        assert!(Enemy.is_hostile() && Rival.is_hostile() && !Suspicion.is_hostile());
+    }
+}
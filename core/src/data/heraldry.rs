@@ -0,0 +1,214 @@
+//! Coat of arms descriptions for states, provinces and settlements
+//!
+//! Names follow Azgaar's heraldry vocabulary ("gules", "perBend", "lionRampant"...)
+//! so imported arms round-trip without loss. Values the engine has no dedicated
+//! variant for are kept verbatim in the `Other` variants.
+
+use serde::{Deserialize, Serialize};
+
+named_terms! {
+    /// Heraldic colours, metals and furs
+    Tincture {
+        Argent => "argent",
+        Or => "or",
+        Gules => "gules",
+        Azure => "azure",
+        Sable => "sable",
+        Vert => "vert",
+        Purpure => "purpure",
+        Murrey => "murrey",
+        Sanguine => "sanguine",
+        Tenne => "tenné",
+        Ermine => "ermine",
+        Vair => "vair",
+    }
+}
+
+named_terms! {
+    /// Outline of the escutcheon
+    #[derive(Default)]
+    ShieldShape {
+        #[default]
+        Heater => "heater",
+        Spanish => "spanish",
+        French => "french",
+        Horsehead => "horsehead",
+        Swiss => "swiss",
+        Kite => "kite",
+        Round => "round",
+        Oval => "oval",
+        Square => "square",
+        Diamond => "diamond",
+        Banner => "banner",
+        Pennon => "pennon",
+    }
+}
+
+named_terms! {
+    /// Partitions of the field
+    DivisionKind {
+        PerPale => "perPale",
+        PerFess => "perFess",
+        PerBend => "perBend",
+        PerBendSinister => "perBendSinister",
+        PerChevron => "perChevron",
+        PerCross => "perCross",
+        PerSaltire => "perSaltire",
+        Gyronny => "gyronny",
+    }
+}
+
+named_terms! {
+    /// Geometric charges crossing the field
+    OrdinaryKind {
+        Pale => "pale",
+        Fess => "fess",
+        Bend => "bend",
+        BendSinister => "bendSinister",
+        Chief => "chief",
+        Base => "base",
+        Chevron => "chevron",
+        Cross => "cross",
+        Saltire => "saltire",
+        Pile => "pile",
+        Bordure => "bordure",
+        Canton => "canton",
+    }
+}
+
+named_terms! {
+    /// Partition and ordinary edge lines
+    #[derive(Default)]
+    LineStyle {
+        #[default]
+        Straight => "straight",
+        Wavy => "wavy",
+        Indented => "indented",
+        Embattled => "embattled",
+        Engrailed => "engrailed",
+        Invected => "invected",
+    }
+}
+
+impl Tincture {
+    pub const METALS: [Tincture; 2] = [Tincture::Argent, Tincture::Or];
+    pub const COLOURS: [Tincture; 5] = [
+        Tincture::Gules,
+        Tincture::Azure,
+        Tincture::Sable,
+        Tincture::Vert,
+        Tincture::Purpure,
+    ];
+    
+    pub fn is_metal(&self) -> bool {
+        matches!(self, Tincture::Argent | Tincture::Or)
+    }
+    
+    pub fn is_fur(&self) -> bool {
+        matches!(self, Tincture::Ermine | Tincture::Vair)
+    }
+    
+    /// Display colour as `#rrggbb`, using Azgaar's palette
+    pub fn hex_color(&self) -> &'static str {
+        match self {
+            Tincture::Argent => "#fafafa",
+            Tincture::Or => "#ffe066",
+            Tincture::Gules => "#d7374a",
+            Tincture::Azure => "#377cd7",
+            Tincture::Sable => "#333333",
+            Tincture::Vert => "#26c061",
+            Tincture::Purpure => "#522d5b",
+            Tincture::Murrey => "#85185b",
+            Tincture::Sanguine => "#b63a3a",
+            Tincture::Tenne => "#cc7f19",
+            Tincture::Ermine => "#fafafa",
+            Tincture::Vair => "#377cd7",
+            Tincture::Other(_) => "#808080",
+        }
+    }
+}
+
+/// Complete blazon of a coat of arms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoatOfArms {
+    pub shield: ShieldShape,
+    /// Tincture of the field
+    pub field: Tincture,
+    pub division: Option<Division>,
+    pub ordinaries: Vec<Ordinary>,
+    pub charges: Vec<Charge>,
+}
+
+/// Field partition painted in a second tincture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Division {
+    pub kind: DivisionKind,
+    pub tincture: Tincture,
+    #[serde(default)]
+    pub line: LineStyle,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ordinary {
+    pub kind: OrdinaryKind,
+    pub tincture: Tincture,
+    #[serde(default)]
+    pub line: LineStyle,
+}
+
+/// Figure placed on the field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Charge {
+    /// Azgaar charge name, e.g. "mullet", "crescent" or "lionRampant"
+    pub charge: String,
+    pub tincture: Tincture,
+    /// Positions on Azgaar's 3×3 field grid, "a" (dexter chief) to "i" (sinister base)
+    pub positions: String,
+    /// Scale relative to a single central charge
+    pub size: f32,
+    #[serde(default)]
+    pub sinister: bool,
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+impl CoatOfArms {
+    /// Plain field of a single tincture
+    pub fn plain(shield: ShieldShape, field: Tincture) -> Self {
+        Self {
+            shield,
+            field,
+            division: None,
+            ordinaries: Vec::new(),
+            charges: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn terms_round_trip_including_unknown_ones() {
+        assert_eq!(Tincture::from("tenné"), Tincture::Tenne);
+        assert_eq!(DivisionKind::from("perBendSinister"), DivisionKind::PerBendSinister);
+        
+        let mut arms = CoatOfArms::plain(ShieldShape::from("noldor"), Tincture::from("celestialAzure"));
+        let line = LineStyle::from("dovetailed");
+        arms.ordinaries.push(Ordinary { kind: OrdinaryKind::Bend, tincture: Tincture::Or, line });
+        arms.charges.push(Charge {
+            charge: "lionRampant".to_string(),
+            tincture: Tincture::Or,
+            positions: "e".to_string(),
+            size: 1.5,
+            sinister: true,
+            reversed: false,
+        });
+        
+        let json = serde_json::to_string(&arms).unwrap();
+        assert!(json.contains("\"noldor\"") && json.contains("\"dovetailed\""));
+        assert_eq!(serde_json::from_str::<CoatOfArms>(&json).unwrap(), arms);
+        assert_eq!(arms.field.hex_color(), "#808080");
+    }
+}
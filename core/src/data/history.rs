@@ -0,0 +1,90 @@
+//! Political history of a world
+
+use super::{CellId, StateId};
+use serde::{Deserialize, Serialize};
+
+/// Simulated history: dated events and periodic snapshots of cell ownership
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timeline {
+    pub start_year: i32,
+    pub end_year: i32,
+    pub events: Vec<HistoricalEvent>,
+    pub snapshots: Vec<StateSnapshot>,
+}
+
+/// Something that happened to one or more states
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalEvent {
+    pub year: i32,
+    pub kind: HistoricalEventKind,
+    /// States involved, the acting state first
+    pub states: Vec<StateId>,
+    /// Cells that changed hands, if any
+    pub cells: Vec<CellId>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoricalEventKind {
+    WarDeclared,
+    PeaceSigned,
+    BorderShift,
+    Annexation,
+    DynasticSplit,
+}
+
+/// Owner of every cell at a given year
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub year: i32,
+    /// `Cell::state` values, in the order of `WorldMap::cells`
+    pub cell_states: Vec<Option<StateId>>,
+}
+
+impl Timeline {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.snapshots.is_empty()
+    }
+    
+    /// Events involving the given state, in chronological order
+    pub fn events_of(&self, state: StateId) -> impl Iterator<Item = &HistoricalEvent> {
+        self.events.iter().filter(move |event| event.states.contains(&state))
+    }
+    
+    /// Latest snapshot taken at or before the given year
+    pub fn snapshot_at(&self, year: i32) -> Option<&StateSnapshot> {
+        self.snapshots
+            .iter()
+            .filter(|snapshot| snapshot.year <= year)
+            .max_by_key(|snapshot| snapshot.year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn snapshots_and_events_are_found_by_year_and_state() {
+        let event = |year, states: &[u32]| HistoricalEvent {
+            year,
+            kind: HistoricalEventKind::WarDeclared,
+            states: states.iter().copied().map(StateId).collect(),
+            cells: Vec::new(),
+            description: String::new(),
+        };
+        let snapshot = |year| StateSnapshot { year, cell_states: vec![Some(StateId(year as u32))] };
+        let timeline = Timeline {
+            start_year: 10,
+            end_year: 30,
+            events: vec![event(12, &[1, 2]), event(15, &[3, 2]), event(20, &[3, 4])],
+            snapshots: vec![snapshot(10), snapshot(20), snapshot(30)],
+        };
+        
+        assert!(!timeline.is_empty() && Timeline::default().is_empty());
+        assert_eq!(timeline.events_of(StateId(2)).map(|event| event.year).collect::<Vec<_>>(), [12, 15]);
+        assert_eq!(timeline.snapshot_at(25).map(|snapshot| snapshot.year), Some(20));
+        assert_eq!(timeline.snapshot_at(30).map(|snapshot| snapshot.year), Some(30));
+        assert!(timeline.snapshot_at(9).is_none());
+    }
+}
@@ -0,0 +1,81 @@
+//! Typed entity ids
+//!
+//! Every entity kind has its own id type so a burg id cannot be passed where a
+//! cell id is expected. Ids serialize as plain numbers, exactly like the `u32`
+//! they wrap.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Declares an id newtype around `u32`
+macro_rules! entity_ids {
+    ($($(#[$meta:meta])* $name:ident),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+            #[serde(transparent)]
+            pub struct $name(pub u32);
+            
+            impl From<u32> for $name {
+                fn from(id: u32) -> Self {
+                    $name(id)
+                }
+            }
+            
+            impl From<$name> for u32 {
+                fn from(id: $name) -> Self {
+                    id.0
+                }
+            }
+            
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{}", self.0)
+                }
+            }
+        )*
+    };
+}
+
+entity_ids! {
+    /// Id of a `Cell`
+    CellId,
+    /// Id of a `Feature`
+    FeatureId,
+    /// Id of a `Culture`
+    CultureId,
+    /// Id of a `State`; burgs of state 0 belong to no state
+    StateId,
+    /// Id of a `Province`
+    ProvinceId,
+    /// Id of a `Settlement`
+    BurgId,
+    /// Id of a `River`
+    RiverId,
+    /// Id of a `Route`
+    RouteId,
+    /// Id of a `Marker`
+    MarkerId,
+    /// Id of a `DatedEvent`
+    EventId,
+    /// Id of a `Zone`; zones count from 0
+    ZoneId,
+}
+
+impl StateId {
+    /// Owner of burgs that belong to no state
+    pub const NEUTRAL: StateId = StateId(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn ids_serialize_as_plain_numbers() {
+        assert_eq!(serde_json::to_string(&BurgId(7)).unwrap(), "7");
+        assert_eq!(serde_json::from_str::<Vec<CellId>>("[3, 1]").unwrap(), [CellId(3), CellId(1)]);
+        assert_eq!(u32::from(StateId::from(4)), 4);
+        assert_eq!(RiverId(12).to_string(), "12");
+    }
+}
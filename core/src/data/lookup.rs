@@ -0,0 +1,125 @@
+//! Entity lookups by typed id
+//!
+//! Entity ids are usually dense and match the entity's position, as in Azgaar
+//! maps and generated worlds, so lookups try that position first and only scan
+//! the collection when ids have gaps.
+
+use super::*;
+
+impl WorldMap {
+    pub fn cell(&self, id: CellId) -> Option<&Cell> {
+        position(&self.cells, id, |cell| cell.id).map(|index| &self.cells[index])
+    }
+    
+    pub fn cell_mut(&mut self, id: CellId) -> Option<&mut Cell> {
+        position(&self.cells, id, |cell| cell.id).map(|index| &mut self.cells[index])
+    }
+    
+    pub fn feature(&self, id: FeatureId) -> Option<&Feature> {
+        position(&self.features, id, |feature| feature.id).map(|index| &self.features[index])
+    }
+    
+    pub fn culture(&self, id: CultureId) -> Option<&Culture> {
+        position(&self.cultures, id, |culture| culture.id).map(|index| &self.cultures[index])
+    }
+    
+    pub fn state(&self, id: StateId) -> Option<&State> {
+        position(&self.states, id, |state| state.id).map(|index| &self.states[index])
+    }
+    
+    pub fn state_mut(&mut self, id: StateId) -> Option<&mut State> {
+        position(&self.states, id, |state| state.id).map(|index| &mut self.states[index])
+    }
+    
+    pub fn province(&self, id: ProvinceId) -> Option<&Province> {
+        position(&self.provinces, id, |province| province.id).map(|index| &self.provinces[index])
+    }
+    
+    pub fn burg(&self, id: BurgId) -> Option<&Settlement> {
+        position(&self.burgs, id, |burg| burg.id).map(|index| &self.burgs[index])
+    }
+    
+    pub fn burg_mut(&mut self, id: BurgId) -> Option<&mut Settlement> {
+        position(&self.burgs, id, |burg| burg.id).map(|index| &mut self.burgs[index])
+    }
+    
+    pub fn river(&self, id: RiverId) -> Option<&River> {
+        position(&self.rivers, id, |river| river.id).map(|index| &self.rivers[index])
+    }
+    
+    pub fn route(&self, id: RouteId) -> Option<&Route> {
+        position(&self.routes, id, |route| route.id).map(|index| &self.routes[index])
+    }
+    
+    pub fn marker(&self, id: MarkerId) -> Option<&Marker> {
+        position(&self.markers, id, |marker| marker.id).map(|index| &self.markers[index])
+    }
+    
+    /// Capital burg of a state
+    pub fn capital_of(&self, state: StateId) -> Option<&Settlement> {
+        self.state(state).and_then(|state| self.burg(state.capital))
+    }
+    
+    /// Burgs belonging to a state; `StateId::NEUTRAL` gives the stateless ones
+    pub fn burgs_in_state(&self, state: StateId) -> impl Iterator<Item = &Settlement> {
+        self.burgs.iter().filter(move |burg| burg.state == state)
+    }
+    
+    /// Cells owned by a state, according to `Cell::state`
+    pub fn cells_of_state(&self, state: StateId) -> impl Iterator<Item = &Cell> {
+        self.cells.iter().filter(move |cell| cell.state == Some(state))
+    }
+    
+    pub fn cells_of_culture(&self, culture: CultureId) -> impl Iterator<Item = &Cell> {
+        self.cells.iter().filter(move |cell| cell.culture == Some(culture))
+    }
+}
+
+/// Index of the item with the given id
+fn position<T, I>(items: &[T], id: I, id_of: impl Fn(&T) -> I) -> Option<usize>
+where
+    I: Copy + Eq + Into<u32>,
+{
+    // Dense ids start at 0 or 1
+    let guess = id.into() as usize;
+    [guess, guess.wrapping_sub(1)]
+        .into_iter()
+        .find(|&index| items.get(index).is_some_and(|item| id_of(item) == id))
+        .or_else(|| items.iter().position(|item| id_of(item) == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    #[test]
+    fn lookups_find_entities_with_dense_and_sparse_ids() {
+        let mut world_map = grid_world(4);
+        assert_eq!(world_map.cell(CellId(5)).unwrap().id, CellId(5));
+        assert_eq!(world_map.state(StateId(2)).unwrap().name, "State 2");
+        assert!(world_map.state(StateId(9)).is_none());
+        
+        world_map.burgs.retain(|burg| burg.id != BurgId(2));
+        world_map.cells.swap(3, 9);
+        assert_eq!(world_map.burg(BurgId(6)).unwrap().id, BurgId(6));
+        assert!(world_map.burg(BurgId(2)).is_none());
+        assert_eq!(world_map.cell(CellId(3)).unwrap().id, CellId(3));
+        world_map.cell_mut(CellId(9)).unwrap().height = 0.9;
+        assert_eq!(world_map.cells[3].height, 0.9);
+    }
+    
+    #[test]
+    fn related_entities_are_found_through_their_ids() {
+        let world_map = grid_world(4);
+        let state = StateId(1);
+        assert_eq!(world_map.capital_of(state).unwrap().id, world_map.state(state).unwrap().capital);
+        assert!(world_map.burgs_in_state(state).all(|burg| burg.state == state));
+        assert_eq!(
+            world_map.cells_of_state(state).count(),
+            world_map.cells.iter().filter(|cell| cell.state == Some(state)).count()
+        );
+        let culture = world_map.cultures[0].id;
+        assert!(world_map.cells_of_culture(culture).all(|cell| cell.culture == Some(culture)));
+    }
+}
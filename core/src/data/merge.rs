@@ -0,0 +1,446 @@
+//! Three-way merge of world maps
+//!
+//! Two versions edited from a common base are combined field by field: a field
+//! changed on one side only takes that side's value. Colliding edits are
+//! reported as conflicts and resolved in favour of "ours", so the merged map is
+//! always complete and can be reviewed, validated and repaired afterwards.
+
+use super::diff::{delta_regions, document, id_of, same_size, ById, EntityOf, COLLECTIONS, HEIGHT_TOLERANCE, VOLATILE_METADATA};
+use super::{EntityRef, Grid, HeightmapRegion, WorldMap};
+use crate::{Result, WorldFoundryError};
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Merged map with the conflicts met on the way
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub world_map: WorldMap,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// One of the two versions being merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MergeConflict {
+    /// Both sides set a metadata field to different values; ours was kept
+    Metadata { field: String, ours: Value, theirs: Value },
+    /// Both sides set a field to different values; ours was kept
+    Field { entity: EntityRef, field: String, ours: Value, theirs: Value },
+    /// Both sides added an entity with this id but different contents; ours was kept
+    BothAdded { entity: EntityRef },
+    /// One side removed an entity the other changed; the changed entity was kept
+    RemovedAndChanged { entity: EntityRef, removed_by: Side },
+    /// Both sides changed heights in the region differently; ours were kept
+    Heightmap { region: HeightmapRegion },
+    /// Both sides changed a section without ids, or resized the heightmap; ours was kept
+    Section { section: String },
+    /// More than one entity holds this id in some version, so they were not
+    /// merged; ours were kept, or theirs when we have none
+    DuplicateId { entity: EntityRef },
+    /// Fields changed on each side do not make a valid entity together, for
+    /// instance because of non-finite numbers; ours was kept
+    Inseparable { entity: EntityRef },
+}
+
+/// Entity of a merged collection
+enum Merged {
+    Ours(usize),
+    Theirs(usize),
+    /// Fields of both sides combined, with the position of ours
+    Combined { id: u32, ours: usize, value: Value },
+}
+
+impl MergeResult {
+    /// True when both versions merged without conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl WorldMap {
+    /// Combine the changes made from `base` in `ours` and in `theirs`
+    ///
+    /// Values are compared in their serialized form, but entities taken whole
+    /// from one side are cloned, so numbers JSON cannot hold survive the merge.
+    pub fn merge(base: &WorldMap, ours: &WorldMap, theirs: &WorldMap) -> Result<MergeResult> {
+        let documents = [document(base)?, document(ours)?, document(theirs)?];
+        let [base_value, ours_value, theirs_value] = &documents;
+        let mut conflicts = Vec::new();
+        
+        let (metadata, fields) =
+            merge_fields(&base_value["metadata"], &ours_value["metadata"], &theirs_value["metadata"]);
+        for (field, ours, theirs) in fields {
+            if !VOLATILE_METADATA.contains(&field.as_str()) {
+                conflicts.push(MergeConflict::Metadata { field, ours, theirs });
+            }
+        }
+        let mut metadata = if metadata == ours_value["metadata"] {
+            ours.metadata.clone()
+        } else if metadata == theirs_value["metadata"] {
+            theirs.metadata.clone()
+        } else {
+            serde_json::from_value(metadata).unwrap_or_else(|_| {
+                conflicts.push(MergeConflict::Section { section: "metadata".to_string() });
+                ours.metadata.clone()
+            })
+        };
+        metadata.modified_at = Utc::now();
+        
+        macro_rules! collection {
+            ($key:ident) => {
+                merge_entities(stringify!($key), &documents, &ours.$key, &theirs.$key, &mut conflicts)?
+            };
+        }
+        macro_rules! section {
+            ($key:ident) => {{
+                let key = stringify!($key);
+                let (side, conflict) = pick_side(&base_value[key], &ours_value[key], &theirs_value[key]);
+                if conflict {
+                    conflicts.push(MergeConflict::Section { section: key.to_string() });
+                }
+                match side {
+                    Side::Ours => ours.$key.clone(),
+                    Side::Theirs => theirs.$key.clone(),
+                }
+            }};
+        }
+        
+        let world_map = WorldMap {
+            metadata,
+            heightmap: merge_heightmaps(&base.heightmap, &ours.heightmap, &theirs.heightmap, &mut conflicts),
+            cells: collection!(cells),
+            features: collection!(features),
+            cultures: collection!(cultures),
+            states: collection!(states),
+            provinces: collection!(provinces),
+            burgs: collection!(burgs),
+            rivers: collection!(rivers),
+            routes: collection!(routes),
+            markers: collection!(markers),
+            zones: section!(zones),
+            diplomacy: section!(diplomacy),
+            history: section!(history),
+            calendar: section!(calendar),
+            events: collection!(events),
+            topology: Default::default(),
+        };
+        
+        Ok(MergeResult { world_map, conflicts })
+    }
+}
+
+/// Three-way choice of a side; true when both sides changed the value differently
+fn pick_side<T: PartialEq>(base: T, ours: T, theirs: T) -> (Side, bool) {
+    if ours == theirs || theirs == base {
+        (Side::Ours, false)
+    } else if ours == base {
+        (Side::Theirs, false)
+    } else {
+        (Side::Ours, true)
+    }
+}
+
+/// Merge the top-level fields of a serialized entity, returning the fields in
+/// conflict with our and their values
+fn merge_fields(base: &Value, ours: &Value, theirs: &Value) -> (Value, Vec<(String, Value, Value)>) {
+    let empty = Map::new();
+    let base = base.as_object().unwrap_or(&empty);
+    let ours = ours.as_object().unwrap_or(&empty);
+    let theirs = theirs.as_object().unwrap_or(&empty);
+    let fields: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+    
+    let mut merged = Map::new();
+    let mut conflicts = Vec::new();
+    for field in fields {
+        let (side, conflict) = pick_side(base.get(field), ours.get(field), theirs.get(field));
+        if conflict {
+            let value = |side: &Map<String, Value>| side.get(field).cloned().unwrap_or(Value::Null);
+            conflicts.push((field.clone(), value(ours), value(theirs)));
+        }
+        let value = match side {
+            Side::Ours => ours.get(field),
+            Side::Theirs => theirs.get(field),
+        };
+        if let Some(value) = value {
+            merged.insert(field.clone(), value.clone());
+        }
+    }
+    (Value::Object(merged), conflicts)
+}
+
+/// Merge the collection `key` of three serialized maps and build its entities,
+/// cloning those taken whole from one side
+fn merge_entities<T: Clone + DeserializeOwned>(
+    key: &str,
+    [base, ours, theirs]: &[Value; 3],
+    ours_items: &[T],
+    theirs_items: &[T],
+    conflicts: &mut Vec<MergeConflict>,
+) -> Result<Vec<T>> {
+    let &(_, entity) = COLLECTIONS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .ok_or_else(|| WorldFoundryError::Merge(format!("`{}` is not a collection of entities", key)))?;
+    Ok(merge_collection(&base[key], &ours[key], &theirs[key], entity, conflicts)
+        .into_iter()
+        .map(|merged| match merged {
+            Merged::Ours(position) => ours_items[position].clone(),
+            Merged::Theirs(position) => theirs_items[position].clone(),
+            Merged::Combined { id, ours, value } => serde_json::from_value(value).unwrap_or_else(|_| {
+                conflicts.push(MergeConflict::Inseparable { entity: entity(id) });
+                ours_items[ours].clone()
+            }),
+        })
+        .collect())
+}
+
+/// Merge a collection of entities matched by id, in our order followed by the
+/// entities only they added
+fn merge_collection(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    entity: EntityOf,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Merged> {
+    let base_items = ById::new(base);
+    let ours_items = ById::new(ours);
+    let theirs_items = ById::new(theirs);
+    let duplicates: BTreeSet<u32> = [&base_items, &ours_items, &theirs_items]
+        .into_iter()
+        .flat_map(|items| items.duplicates.iter().copied())
+        .collect();
+    conflicts.extend(duplicates.iter().map(|&id| MergeConflict::DuplicateId { entity: entity(id) }));
+    let ours_ids: BTreeSet<u32> = ours_items.items.iter().filter_map(id_of).collect();
+    let mut merged = Vec::new();
+    
+    for (position, item) in ours_items.items.iter().enumerate() {
+        let Some(id) = id_of(item).filter(|id| !duplicates.contains(id)) else {
+            merged.push(Merged::Ours(position));
+            continue;
+        };
+        match (base_items.get(id), theirs_items.position(id)) {
+            (Some(base), Some(theirs)) => {
+                let theirs_item = &theirs_items.items[theirs];
+                let (value, fields) = merge_fields(base, item, theirs_item);
+                conflicts.extend(fields.into_iter().map(|(field, ours, theirs)| MergeConflict::Field {
+                    entity: entity(id),
+                    field,
+                    ours,
+                    theirs,
+                }));
+                merged.push(if value == *item {
+                    Merged::Ours(position)
+                } else if value == *theirs_item {
+                    Merged::Theirs(theirs)
+                } else {
+                    Merged::Combined { id, ours: position, value }
+                });
+            }
+            (Some(base), None) => {
+                if item != base {
+                    conflicts.push(MergeConflict::RemovedAndChanged { entity: entity(id), removed_by: Side::Theirs });
+                    merged.push(Merged::Ours(position));
+                }
+            }
+            (None, Some(theirs)) => {
+                if *item != theirs_items.items[theirs] {
+                    conflicts.push(MergeConflict::BothAdded { entity: entity(id) });
+                }
+                merged.push(Merged::Ours(position));
+            }
+            (None, None) => merged.push(Merged::Ours(position)),
+        }
+    }
+    
+    for (position, item) in theirs_items.items.iter().enumerate() {
+        let Some(id) = id_of(item).filter(|id| !ours_ids.contains(id)) else {
+            continue;
+        };
+        match base_items.get(id) {
+            _ if duplicates.contains(&id) => merged.push(Merged::Theirs(position)),
+            Some(base) if item == base => {}
+            Some(_) => {
+                conflicts.push(MergeConflict::RemovedAndChanged { entity: entity(id), removed_by: Side::Ours });
+                merged.push(Merged::Theirs(position));
+            }
+            None => merged.push(Merged::Theirs(position)),
+        }
+    }
+    merged
+}
+
+/// Merge heights value by value; grids that changed size are merged whole
+fn merge_heightmaps(
+    base: &Grid<f32>,
+    ours: &Grid<f32>,
+    theirs: &Grid<f32>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Grid<f32> {
+    if !same_size(base, ours) || !same_size(base, theirs) {
+        let unchanged = |grid: &Grid<f32>| same_size(base, grid) && grid.data == base.data;
+        if !unchanged(ours) && !unchanged(theirs) {
+            conflicts.push(MergeConflict::Section { section: "heightmap".to_string() });
+        }
+        return if unchanged(ours) { theirs.clone() } else { ours.clone() };
+    }
+    
+    let changed = |grid: &Grid<f32>, index: usize| (grid.data[index] - base.data[index]).abs() > HEIGHT_TOLERANCE;
+    let mut merged = ours.clone();
+    for (index, height) in merged.data.iter_mut().enumerate() {
+        if !changed(ours, index) {
+            *height = theirs.data[index];
+        }
+    }
+    
+    let clash = |index: usize| {
+        if changed(ours, index) && changed(theirs, index) {
+            (ours.data[index] - theirs.data[index]).abs()
+        } else {
+            0.0
+        }
+    };
+    conflicts.extend(
+        delta_regions(base.width, base.height, clash)
+            .into_iter()
+            .map(|region| MergeConflict::Heightmap { region }),
+    );
+    merged
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Ours => write!(f, "ours"),
+            Side::Theirs => write!(f, "theirs"),
+        }
+    }
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeConflict::Metadata { field, ours, theirs } => {
+                write!(f, "metadata {} set to {} in ours and {} in theirs", field, ours, theirs)
+            }
+            MergeConflict::Field { entity, field, ours, theirs } => {
+                write!(f, "{} {} set to {} in ours and {} in theirs", entity, field, ours, theirs)
+            }
+            MergeConflict::BothAdded { entity } => write!(f, "{} added differently on both sides", entity),
+            MergeConflict::RemovedAndChanged { entity, removed_by } => {
+                write!(f, "{} removed in {} but changed on the other side", entity, removed_by)
+            }
+            MergeConflict::Heightmap { region } => write!(f, "heights changed on both sides in {}", region),
+            MergeConflict::Section { section } => write!(f, "{} changed on both sides", section),
+            MergeConflict::DuplicateId { entity } => write!(f, "{} held by more than one entity", entity),
+            MergeConflict::Inseparable { entity } => {
+                write!(f, "{} changed on both sides in ways that cannot be combined", entity)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{BiomeType, BurgId, CellId, Color, StateId};
+    use nalgebra::Point2;
+    
+    #[test]
+    fn changes_on_either_side_are_combined() {
+        let base = grid_world(8);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.states[0].name = "Ours".to_string();
+        ours.heightmap.data[3] = 0.9;
+        theirs.states[0].color = Color::parse("#123456");
+        theirs.burgs.retain(|burg| burg.id != BurgId(4));
+        theirs.cells[20].biome = BiomeType::Dry;
+        theirs.heightmap.data[70] = 0.2;
+        
+        let result = WorldMap::merge(&base, &ours, &theirs).unwrap();
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        let merged = result.world_map;
+        assert_eq!(merged.states[0].name, "Ours");
+        assert_eq!(merged.states[0].color, Color::parse("#123456"));
+        assert!(merged.burgs.iter().all(|burg| burg.id != BurgId(4)));
+        assert_eq!(merged.cells[20].biome, BiomeType::Dry);
+        assert_eq!((merged.heightmap.data[3], merged.heightmap.data[70]), (0.9, 0.2));
+    }
+    
+    #[test]
+    fn colliding_changes_keep_ours() {
+        let base = grid_world(8);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.states[1].name = "Ours".to_string();
+        theirs.states[1].name = "Theirs".to_string();
+        ours.burgs.retain(|burg| burg.id != BurgId(2));
+        theirs.burgs[1].population += 100.0;
+        
+        let result = WorldMap::merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, vec![
+            MergeConflict::Field {
+                entity: EntityRef::State(StateId(2)),
+                field: "name".to_string(),
+                ours: "Ours".into(),
+                theirs: "Theirs".into(),
+            },
+            MergeConflict::RemovedAndChanged { entity: EntityRef::Settlement(BurgId(2)), removed_by: Side::Ours },
+        ]);
+        assert_eq!(result.world_map.states[1].name, "Ours");
+        assert_eq!(result.world_map.burgs.last().unwrap().population, theirs.burgs[1].population);
+    }
+    
+    #[test]
+    fn non_finite_numbers_survive_merging() {
+        let mut base = grid_world(8);
+        base.cells[5].coordinates = Point2::new(f32::NAN, 3.0);
+        base.cells[6].temperature = f32::INFINITY;
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+        ours.cells[6].height = 0.7;
+        theirs.cells[7].height = 0.8;
+        theirs.cells[5].biome = BiomeType::Dry;
+        
+        let result = WorldMap::merge(&base, &ours, &theirs).unwrap();
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        let cells = &result.world_map.cells;
+        assert!(cells[5].coordinates.x.is_nan());
+        assert_eq!(cells[5].biome, BiomeType::Dry);
+        assert_eq!((cells[6].temperature, cells[6].height, cells[7].height), (f32::INFINITY, 0.7, 0.8));
+        
+        // Combining fields of both sides goes through JSON, which cannot hold NaN
+        ours.cells[5].height = 0.6;
+        let result = WorldMap::merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, vec![MergeConflict::Inseparable { entity: EntityRef::Cell(CellId(5)) }]);
+        assert_eq!(result.world_map.cells[5].height, 0.6);
+        assert_eq!(result.world_map.cells[5].biome, base.cells[5].biome);
+    }
+    
+    #[test]
+    fn duplicate_ids_are_conflicts() {
+        let base = grid_world(8);
+        let ours = base.clone();
+        let mut theirs = base.clone();
+        let mut copy = theirs.burgs[2].clone();
+        copy.name = "Copy".to_string();
+        theirs.burgs.push(copy);
+        theirs.burgs[2].name = "Changed".to_string();
+        
+        let result = WorldMap::merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, vec![MergeConflict::DuplicateId { entity: EntityRef::Settlement(BurgId(3)) }]);
+        let names = |world_map: &WorldMap| world_map.burgs.iter().map(|burg| burg.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&result.world_map), names(&ours));
+    }
+}
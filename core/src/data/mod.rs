@@ -1,9 +1,51 @@
 //! Core data structures for World Foundry
 
-use serde::{Deserialize, Serialize};
+#[macro_use]
+mod terms;
+
+pub mod attributes;
+pub mod calendar;
+pub mod color;
+pub mod diff;
+pub mod diplomacy;
+pub mod heraldry;
+pub mod history;
+pub mod ids;
+mod lookup;
+pub mod merge;
+pub mod raster;
+mod rasterize;
+pub mod repair;
+pub mod schema;
+pub mod stitch;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod topology;
+pub mod units;
+pub mod validation;
+
+pub use attributes::*;
+pub use calendar::*;
+pub use color::*;
+pub use diff::*;
+pub use diplomacy::*;
+pub use heraldry::*;
+pub use history::*;
+pub use ids::*;
+pub use merge::*;
+pub use raster::*;
+pub use repair::*;
+pub use schema::*;
+pub use stitch::*;
+pub use topology::*;
+pub use units::*;
+pub use validation::*;
+
+use serde::{Deserialize, Deserializer, Serialize};
 use nalgebra::Point2;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 
 /// Main world map data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,11 +56,25 @@ pub struct WorldMap {
     pub features: Vec<Feature>,
     pub cultures: Vec<Culture>,
     pub states: Vec<State>,
+    #[serde(default)]
+    pub provinces: Vec<Province>,
     pub burgs: Vec<Settlement>,
     pub rivers: Vec<River>,
     pub routes: Vec<Route>,
     pub markers: Vec<Marker>,
     pub zones: Vec<Zone>,
+    #[serde(default)]
+    pub diplomacy: DiplomacyMatrix,
+    #[serde(default)]
+    pub history: Timeline,
+    #[serde(default)]
+    pub calendar: Calendar,
+    /// Dated events of campaigns and chronicles, see `Calendar`
+    #[serde(default)]
+    pub events: Vec<DatedEvent>,
+    /// Derived cell adjacency and spatial index, see `WorldMap::topology`
+    #[serde(skip)]
+    pub topology: TopologyCache,
 }
 
 /// Map metadata and settings
@@ -27,23 +83,32 @@ pub struct MapMetadata {
     pub id: Uuid,
     pub name: String,
     pub version: String,
+    /// Layout of the saved data, see `SCHEMA_VERSION`
+    #[serde(default)]
+    pub schema_version: u32,
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
     pub width: u32,
     pub height: u32,
     pub seed: u64,
     pub settings: MapSettings,
+    /// Imported notes about elements the map does not hold, keyed by the
+    /// element id they had, such as "marker4"
+    #[serde(default)]
+    pub notes: BTreeMap<String, Note>,
 }
 
 /// Map generation and display settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapSettings {
-    pub distance_unit: String,
+    pub distance_unit: DistanceUnit,
+    /// Distance units per map unit
     pub distance_scale: f32,
-    pub area_unit: String,
-    pub height_unit: String,
+    pub area_unit: AreaUnit,
+    pub height_unit: HeightUnit,
+    /// Steepness of the curve from normalized height to elevation
     pub height_exponent: f32,
-    pub temperature_scale: String,
+    pub temperature_scale: TemperatureScale,
     pub population_rate: f32,
     pub urbanization: f32,
     pub latitude: f32,
@@ -92,21 +157,24 @@ impl<T> Grid<T> {
 /// Individual map cell (Voronoi cell or grid cell)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cell {
-    pub id: u32,
+    pub id: CellId,
     pub coordinates: Point2<f32>,
+    /// Normalized height from 0 to 1, with the coastline at `SEA_LEVEL`
     pub height: f32,
     pub biome: BiomeType,
+    /// Mean temperature, in °C
     pub temperature: f32,
     pub precipitation: f32,
+    /// Rural population, in people
     pub population: u32,
-    pub culture: Option<u32>,
-    pub state: Option<u32>,
-    pub province: Option<u32>,
+    pub culture: Option<CultureId>,
+    pub state: Option<StateId>,
+    pub province: Option<ProvinceId>,
     pub religion: Option<u32>,
 }
 
 /// Biome types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BiomeType {
     Marine,
     Hot,
@@ -118,14 +186,25 @@ pub enum BiomeType {
     // Add more biome types as needed
 }
 
+impl BiomeType {
+    /// Sea and lake biomes
+    pub fn is_water(self) -> bool {
+        matches!(self, BiomeType::Marine | BiomeType::Freshwater)
+    }
+}
+
 /// Geographic features (mountains, forests, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Feature {
-    pub id: u32,
+    pub id: FeatureId,
     pub name: String,
     pub feature_type: FeatureType,
-    pub cells: Vec<u32>,
+    pub cells: Vec<CellId>,
     pub group: Option<u32>,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -142,105 +221,238 @@ pub enum FeatureType {
 /// Cultural groups
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Culture {
-    pub id: u32,
+    pub id: CultureId,
     pub name: String,
-    pub color: String,
+    pub color: Color,
     pub base: u32,
-    pub origins: Vec<u32>,
+    pub origins: Vec<CultureId>,
     pub shield: String,
     pub center: Point2<f32>,
+    /// Area in square map units
     pub area: f32,
+    /// Rural population, in people
     pub rural: f32,
+    /// Urban population, in people
     pub urban: f32,
     pub expansionism: f32,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
 }
 
 /// Political states
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
-    pub id: u32,
+    pub id: StateId,
     pub name: String,
     pub full_name: String,
-    pub color: String,
-    pub capital: u32,
+    pub color: Color,
+    pub capital: BurgId,
     pub center: Point2<f32>,
+    /// Area in square map units
     pub area: f32,
+    /// Total population, in people
     pub population: u32,
     pub rural: f32,
     pub urban: f32,
     pub burgs: u32,
-    pub culture: u32,
-    pub type_: String,
+    pub culture: CultureId,
+    #[serde(rename = "type")]
+    pub type_: StateType,
     pub expansionism: f32,
-    pub cells: Vec<u32>,
+    pub cells: Vec<CellId>,
+    #[serde(default)]
+    pub coa: Option<CoatOfArms>,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+named_terms! {
+    /// Character of a state, after Azgaar's state types
+    StateType {
+        Generic => "Generic",
+        River => "River",
+        Lake => "Lake",
+        Naval => "Naval",
+        Nomadic => "Nomadic",
+        Hunting => "Hunting",
+        Highland => "Highland",
+    }
+}
+
+/// Administrative divisions of a state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Province {
+    pub id: ProvinceId,
+    pub name: String,
+    pub full_name: String,
+    pub color: Color,
+    pub state: StateId,
+    pub burg: Option<BurgId>,
+    pub center: Point2<f32>,
+    pub cells: Vec<CellId>,
+    pub coa: Option<CoatOfArms>,
 }
 
 /// Settlements (cities, towns, villages)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settlement {
-    pub id: u32,
+    pub id: BurgId,
     pub name: String,
-    pub cell: u32,
+    pub cell: CellId,
     pub x: f32,
     pub y: f32,
-    pub state: u32,
+    pub state: StateId,
     pub i: u32,
-    pub culture: u32,
-    pub feature: u32,
-    pub capital: u32,
+    pub culture: CultureId,
+    pub feature: FeatureId,
+    /// Whether the burg is the capital of its state
+    #[serde(deserialize_with = "flag")]
+    pub capital: bool,
     pub port: u32,
     pub population: f32,
-    pub type_: String,
+    #[serde(rename = "type")]
+    pub type_: SettlementType,
+    #[serde(default)]
+    pub coa: Option<CoatOfArms>,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+/// Flag saved as a boolean, or as 0 or 1 by Azgaar and older worlds
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Number(u32),
+    }
+    Ok(match Flag::deserialize(deserializer)? {
+        Flag::Bool(flag) => flag,
+        Flag::Number(number) => number != 0,
+    })
+}
+
+named_terms! {
+    /// Character of a settlement, after Azgaar's burg types
+    SettlementType {
+        Generic => "Generic",
+        River => "River",
+        Lake => "Lake",
+        Naval => "Naval",
+        Nomadic => "Nomadic",
+        Hunting => "Hunting",
+        Highland => "Highland",
+    }
 }
 
 /// Rivers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct River {
-    pub id: u32,
-    pub source: u32,
-    pub mouth: u32,
+    pub id: RiverId,
+    pub source: CellId,
+    pub mouth: CellId,
     pub discharge: f32,
+    /// Length in map units
     pub length: f32,
     pub width: f32,
-    pub cells: Vec<u32>,
-    pub basin: u32,
+    pub cells: Vec<CellId>,
+    pub basin: RiverId,
     pub name: String,
-    pub type_: String,
+    #[serde(rename = "type")]
+    pub type_: RiverType,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+named_terms! {
+    /// Kind of river, after Azgaar's river types
+    RiverType {
+        River => "River",
+        Creek => "Creek",
+        Brook => "Brook",
+        Stream => "Stream",
+        Fork => "Fork",
+        Branch => "Branch",
+    }
 }
 
 /// Trade routes and roads
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Route {
-    pub id: u32,
+    pub id: RouteId,
     pub group: u32,
-    pub cells: Vec<u32>,
-    pub feature: u32,
+    pub cells: Vec<CellId>,
+    pub feature: FeatureId,
+    /// Length in map units
     pub length: f32,
 }
 
 /// Map markers and labels
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Marker {
-    pub id: u32,
+    pub id: MarkerId,
     pub icon: String,
-    pub type_: String,
+    #[serde(rename = "type")]
+    pub type_: MarkerType,
     pub dx: f32,
     pub dy: f32,
     pub x: f32,
     pub y: f32,
-    pub cell: u32,
+    pub cell: CellId,
     pub i: u32,
     pub size: f32,
-    pub fill: String,
-    pub stroke: String,
-    pub note: String,
+    pub fill: Color,
+    pub stroke: Color,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+named_terms! {
+    /// Kind of point of interest, after Azgaar's marker types
+    MarkerType {
+        Volcanoes => "volcanoes",
+        HotSprings => "hot-springs",
+        WaterSources => "water-sources",
+        Mines => "mines",
+        Bridges => "bridges",
+        Inns => "inns",
+        Lighthouses => "lighthouses",
+        Waterfalls => "waterfalls",
+        Battlefields => "battlefields",
+        Dungeons => "dungeons",
+        Ruins => "ruins",
+        LakeMonsters => "lake-monsters",
+        SeaMonsters => "sea-monsters",
+        HillMonsters => "hill-monsters",
+        SacredMountains => "sacred-mountains",
+        SacredForests => "sacred-forests",
+        Brigands => "brigands",
+        Pirates => "pirates",
+        Statues => "statues",
+        Portals => "portals",
+        Caves => "caves",
+    }
 }
 
 /// Zones (climate, political, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Zone {
-    pub id: u32,
+    pub id: ZoneId,
     pub name: String,
-    pub cells: Vec<u32>,
-    pub color: String,
+    pub cells: Vec<CellId>,
+    pub color: Color,
+    #[serde(default)]
+    pub note: Option<Note>,
+    #[serde(default)]
+    pub attributes: Attributes,
 }
\ No newline at end of file
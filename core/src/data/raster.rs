@@ -0,0 +1,388 @@
+//! Raster operations on `Grid`
+//!
+//! Sampling, resampling, cropping, convolution and slopes for heightmaps and
+//! other raster layers. Value `(x, y)` sits at integer coordinates; reads past
+//! an edge take the nearest edge value.
+
+use super::Grid;
+use nalgebra::Vector2;
+
+/// How values between grid points are sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Catmull-Rom spline through the 4×4 surrounding values
+    Bicubic,
+}
+
+/// Square convolution kernel with an odd side, weights in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    size: usize,
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    /// Kernel from row-major weights; `None` unless their count is an odd square
+    pub fn new(weights: Vec<f32>) -> Option<Self> {
+        let size = (weights.len() as f64).sqrt().round() as usize;
+        (size * size == weights.len() && size % 2 == 1).then_some(Self { size, weights })
+    }
+    
+    /// Plain average of the values within `radius` steps
+    pub fn box_blur(radius: usize) -> Self {
+        let size = 2 * radius + 1;
+        let weight = 1.0 / (size * size) as f32;
+        Self { size, weights: vec![weight; size * size] }
+    }
+    
+    /// Gaussian blur reaching `radius` steps, with a standard deviation of half the radius
+    pub fn gaussian(radius: usize) -> Self {
+        let size = 2 * radius + 1;
+        let sigma = (radius as f32 / 2.0).max(0.5);
+        let mut weights: Vec<f32> = (0..size * size)
+            .map(|index| {
+                let dx = (index % size) as f32 - radius as f32;
+                let dy = (index / size) as f32 - radius as f32;
+                (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        weights.iter_mut().for_each(|weight| *weight /= total);
+        Self { size, weights }
+    }
+    
+    /// Sharpen by `amount` times the difference from the four neighbours
+    pub fn sharpen(amount: f32) -> Self {
+        let a = -amount;
+        Self { size: 3, weights: vec![0.0, a, 0.0, a, 1.0 + 4.0 * amount, a, 0.0, a, 0.0] }
+    }
+    
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+}
+
+impl<T> Grid<T> {
+    /// Grid with each value computed from its coordinates
+    pub fn from_fn(width: u32, height: u32, mut value: impl FnMut(u32, u32) -> T) -> Self {
+        let data = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| value(x, y)).collect();
+        Self { width, height, data }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0 || self.data.is_empty()
+    }
+    
+    /// Position of a value in `data`
+    pub fn index(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| (y * self.width + x) as usize)
+    }
+    
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.data.chunks(self.width.max(1) as usize).take(self.height as usize)
+    }
+    
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> {
+        self.data.chunks_mut(self.width.max(1) as usize).take(self.height as usize)
+    }
+    
+    /// Values of column `x`, top to bottom
+    pub fn column(&self, x: u32) -> impl Iterator<Item = &T> {
+        let height = if x < self.width { self.height as usize } else { 0 };
+        self.data.iter().skip(x as usize).step_by(self.width.max(1) as usize).take(height)
+    }
+    
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |x| self.column(x))
+    }
+    
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid { width: self.width, height: self.height, data: self.data.iter().map(f).collect() }
+    }
+    
+    /// Combine with a grid of the same size value by value; `None` if sizes differ
+    pub fn zip_with<U, V>(&self, other: &Grid<U>, mut f: impl FnMut(&T, &U) -> V) -> Option<Grid<V>> {
+        if (self.width, self.height) != (other.width, other.height) || self.data.len() != other.data.len() {
+            return None;
+        }
+        let data = self.data.iter().zip(&other.data).map(|(a, b)| f(a, b)).collect();
+        Some(Grid { width: self.width, height: self.height, data })
+    }
+    
+    /// Value at `(x, y)`, taking the nearest edge value outside the grid;
+    /// `None` for an empty grid
+    pub fn get_clamped(&self, x: i64, y: i64) -> Option<&T> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.data.get(y * self.width as usize + x)
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    /// Copy of a rectangle, clipped to the grid
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Grid<T> {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let (x, y) = (x.min(x_end), y.min(y_end));
+        Grid::from_fn(x_end - x, y_end - y, |cx, cy| self.data[((y + cy) * self.width + x + cx) as usize].clone())
+    }
+    
+    /// Copy another grid in with its top-left corner at `(x, y)`; parts outside
+    /// this grid are dropped
+    pub fn paste(&mut self, other: &Grid<T>, x: i64, y: i64) {
+        for (row, values) in other.rows().enumerate() {
+            let target_y = y + row as i64;
+            if target_y < 0 || target_y >= self.height as i64 {
+                continue;
+            }
+            for (column, value) in values.iter().enumerate() {
+                let target_x = x + column as i64;
+                if target_x >= 0 && target_x < self.width as i64 {
+                    self.data[(target_y as u32 * self.width + target_x as u32) as usize] = value.clone();
+                }
+            }
+        }
+    }
+}
+
+impl<T: PartialOrd + Copy> Grid<T> {
+    pub fn min(&self) -> Option<T> {
+        self.min_max().map(|(min, _)| min)
+    }
+    
+    pub fn max(&self) -> Option<T> {
+        self.min_max().map(|(_, max)| max)
+    }
+    
+    /// Smallest and largest value; incomparable values such as NaN are skipped
+    pub fn min_max(&self) -> Option<(T, T)> {
+        let mut values = self.data.iter().copied().filter(|value| value.partial_cmp(value).is_some());
+        let first = values.next()?;
+        Some(values.fold((first, first), |(min, max), value| {
+            (if value < min { value } else { min }, if value > max { value } else { max })
+        }))
+    }
+}
+
+impl Grid<f32> {
+    /// Value at fractional coordinates; 0 on an empty grid
+    pub fn sample(&self, x: f32, y: f32, interpolation: Interpolation) -> f32 {
+        match interpolation {
+            Interpolation::Nearest => self.clamped(x.round() as i64, y.round() as i64),
+            Interpolation::Bilinear => self.sample_bilinear(x, y),
+            Interpolation::Bicubic => self.sample_bicubic(x, y),
+        }
+    }
+    
+    pub fn sample_bilinear(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let value = |dx: i64, dy: i64| self.clamped(x0 + dx, y0 + dy);
+        let top = value(0, 0) + (value(1, 0) - value(0, 0)) * tx;
+        let bottom = value(0, 1) + (value(1, 1) - value(0, 1)) * tx;
+        top + (bottom - top) * ty
+    }
+    
+    pub fn sample_bicubic(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let row = |dy: i64| {
+            let value = |dx: i64| self.clamped(x0 + dx, y0 + dy);
+            catmull_rom(value(-1), value(0), value(1), value(2), tx)
+        };
+        catmull_rom(row(-1), row(0), row(1), row(2), ty)
+    }
+    
+    /// Grid of a new size covering the same extent
+    pub fn resample(&self, width: u32, height: u32, interpolation: Interpolation) -> Grid<f32> {
+        if self.is_empty() {
+            return Grid::new(width, height, 0.0);
+        }
+        // Align pixel centres rather than corners
+        let scale_x = self.width as f32 / width.max(1) as f32;
+        let scale_y = self.height as f32 / height.max(1) as f32;
+        Grid::from_fn(width, height, |x, y| {
+            let source_x = (x as f32 + 0.5) * scale_x - 0.5;
+            let source_y = (y as f32 + 0.5) * scale_y - 0.5;
+            self.sample(source_x, source_y, interpolation)
+        })
+    }
+    
+    pub fn convolve(&self, kernel: &Kernel) -> Grid<f32> {
+        if self.is_empty() {
+            return self.clone();
+        }
+        let radius = (kernel.size / 2) as i64;
+        Grid::from_fn(self.width, self.height, |x, y| {
+            kernel
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(index, weight)| {
+                    let dx = (index % kernel.size) as i64 - radius;
+                    let dy = (index / kernel.size) as i64 - radius;
+                    weight * self.clamped(x as i64 + dx, y as i64 + dy)
+                })
+                .sum()
+        })
+    }
+    
+    /// Gaussian blur reaching `radius` values away
+    pub fn blur(&self, radius: usize) -> Grid<f32> {
+        self.convolve(&Kernel::gaussian(radius))
+    }
+    
+    pub fn sharpen(&self, amount: f32) -> Grid<f32> {
+        self.convolve(&Kernel::sharpen(amount))
+    }
+    
+    /// Rescale values linearly to the range 0..=1; a flat grid becomes all 0
+    pub fn normalize(&mut self) {
+        let Some((min, max)) = self.min_max() else {
+            return;
+        };
+        let range = max - min;
+        for value in self.data.iter_mut() {
+            *value = if range > 0.0 { (*value - min) / range } else { 0.0 };
+        }
+    }
+    
+    /// Rate of change per grid step along x and y, from central differences
+    /// (one-sided at the edges)
+    pub fn gradient(&self) -> Grid<Vector2<f32>> {
+        if self.is_empty() {
+            return Grid { width: self.width, height: self.height, data: Vec::new() };
+        }
+        let (width, height) = (self.width as i64, self.height as i64);
+        let difference = |low: (i64, i64), high: (i64, i64), steps: i64| {
+            if steps == 0 {
+                0.0
+            } else {
+                (self.clamped(high.0, high.1) - self.clamped(low.0, low.1)) / steps as f32
+            }
+        };
+        Grid::from_fn(self.width, self.height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            let (left, right) = ((x - 1).max(0), (x + 1).min(width - 1));
+            let (up, down) = ((y - 1).max(0), (y + 1).min(height - 1));
+            Vector2::new(difference((left, y), (right, y), right - left), difference((x, up), (x, down), down - up))
+        })
+    }
+    
+    /// Steepness, as the length of the gradient
+    pub fn slope(&self) -> Grid<f32> {
+        self.gradient().map(|gradient| gradient.norm())
+    }
+    
+    /// `get_clamped` for sampling, 0 on an empty grid
+    fn clamped(&self, x: i64, y: i64) -> f32 {
+        self.get_clamped(x, y).copied().unwrap_or(0.0)
+    }
+}
+
+/// Catmull-Rom interpolation between `p1` and `p2`
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+    let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+    let c = -0.5 * p0 + 0.5 * p2;
+    ((a * t + b) * t + c) * t + p1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn ramp() -> Grid<f32> {
+        Grid::from_fn(4, 3, |x, y| (x + 10 * y) as f32)
+    }
+    
+    #[test]
+    fn sampling_interpolates_between_values() {
+        let grid = ramp();
+        assert_eq!(grid.sample(1.0, 2.0, Interpolation::Nearest), 21.0);
+        assert_eq!(grid.sample(1.5, 0.5, Interpolation::Bilinear), 6.5);
+        assert!((grid.sample(1.5, 1.0, Interpolation::Bicubic) - 11.5).abs() < 1e-4);
+        assert_eq!(grid.sample(-3.0, 9.0, Interpolation::Bilinear), 20.0);
+        
+        let resampled = grid.resample(8, 6, Interpolation::Bilinear);
+        assert_eq!((resampled.width, resampled.height), (8, 6));
+        assert_eq!(grid.resample(4, 3, Interpolation::Bicubic).data, grid.data);
+    }
+    
+    #[test]
+    fn empty_grids_sample_as_zero() {
+        let empty: Grid<f32> = Grid::new(0, 3, 1.0);
+        assert_eq!(empty.get_clamped(0, 0), None);
+        for interpolation in [Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic] {
+            assert_eq!(empty.sample(0.5, 0.5, interpolation), 0.0);
+        }
+        assert_eq!(empty.sample_bilinear(1.0, 1.0), 0.0);
+    }
+    
+    #[test]
+    fn crop_paste_and_iterators_agree() {
+        let grid = ramp();
+        let cropped = grid.crop(1, 1, 10, 1);
+        assert_eq!(cropped.data, [11.0, 12.0, 13.0]);
+        
+        let mut target = Grid::new(3, 2, 0.0);
+        target.paste(&cropped, -1, 1);
+        assert_eq!(target.data, [0.0, 0.0, 0.0, 12.0, 13.0, 0.0]);
+        
+        assert_eq!(grid.rows().nth(1).unwrap(), [10.0, 11.0, 12.0, 13.0]);
+        assert_eq!(grid.column(2).copied().collect::<Vec<_>>(), [2.0, 12.0, 22.0]);
+        assert_eq!(grid.column(4).count(), 0);
+        assert_eq!(grid.columns().count(), 4);
+        assert!(grid.zip_with(&cropped, |a, b| a + b).is_none());
+        assert_eq!(grid.zip_with(&grid, |a, b| a - b).unwrap().max(), Some(0.0));
+    }
+    
+    #[test]
+    fn kernels_keep_flat_grids_flat() {
+        assert!(Kernel::new(vec![1.0; 4]).is_none());
+        assert_eq!(Kernel::new(vec![1.0; 9]).unwrap().size(), 3);
+        assert!((Kernel::gaussian(2).weights().iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        
+        let flat = Grid::new(5, 5, 3.0);
+        for blurred in [flat.blur(2), flat.sharpen(0.5), flat.convolve(&Kernel::box_blur(1))] {
+            assert!(blurred.data.iter().all(|value| (value - 3.0).abs() < 1e-5));
+        }
+        
+        let mut spike = Grid::new(5, 5, 0.0);
+        spike.data[12] = 1.0;
+        let blurred = spike.blur(1);
+        assert!(blurred.data[12] < 1.0 && blurred.data[13] > 0.0);
+        assert!(spike.sharpen(1.0).data[12] > 1.0);
+    }
+    
+    #[test]
+    fn normalize_gradient_and_slope() {
+        let mut grid = ramp();
+        grid.data[0] = f32::NAN;
+        assert_eq!(grid.min_max(), Some((1.0, 23.0)));
+        
+        let mut grid = ramp();
+        grid.normalize();
+        assert_eq!((grid.min(), grid.max()), (Some(0.0), Some(1.0)));
+        let mut flat = Grid::new(2, 2, 5.0);
+        flat.normalize();
+        assert_eq!(flat.data, [0.0; 4]);
+        
+        let gradient = ramp().gradient();
+        assert!(gradient.data.iter().all(|step| *step == Vector2::new(1.0, 10.0)));
+        assert!((ramp().slope().data[5] - 101f32.sqrt()).abs() < 1e-4);
+        assert_eq!(Grid::new(1, 1, 2.0).slope().data, [0.0]);
+    }
+}
@@ -0,0 +1,351 @@
+//! Conversion between cells and rasters
+//!
+//! A raster of any size covers the whole map: value `(x, y)` of a `width` ×
+//! `height` grid describes the point at the centre of its pixel. Rasterizing
+//! gives every pixel the value of the cell containing that point; sampling
+//! reads a raster back at the cell centres.
+
+use super::{Cell, Grid, Interpolation, WorldMap};
+use nalgebra::Point2;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+impl WorldMap {
+    /// Raster of a cell attribute, such as biome or state, taking the value of
+    /// the cell under each pixel
+    pub fn rasterize<T: Clone + Default>(&self, width: u32, height: u32, value: impl Fn(&Cell) -> T) -> Grid<T> {
+        if self.cells.is_empty() {
+            return Grid::new(width, height, T::default());
+        }
+        let topology = self.topology();
+        Grid::from_fn(width, height, |x, y| {
+            topology
+                .cell_at(self.pixel_centre(x, y, width, height))
+                .map_or_else(T::default, |index| value(&self.cells[index]))
+        })
+    }
+    
+    /// Raster of a numeric cell attribute, interpolated linearly between
+    /// neighbouring cell centres
+    ///
+    /// Pixels outside the triangulation of the centres, near the map border,
+    /// take the value of the cell under them.
+    pub fn rasterize_smooth(&self, width: u32, height: u32, value: impl Fn(&Cell) -> f32) -> Grid<f32> {
+        let mut grid = Grid::new(width, height, f32::NAN);
+        if self.cells.is_empty() {
+            grid.data.fill(0.0);
+            return grid;
+        }
+        let values: Vec<f32> = self.cells.iter().map(&value).collect();
+        let topology = self.topology();
+        for triangle in &topology.triangles {
+            fill_triangle(&mut grid, self.raster_corners(*triangle, &values, width, height, (0, 0)));
+        }
+        
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if grid.data[index].is_nan() {
+                    let cell = topology.cell_at(self.pixel_centre(x, y, width, height));
+                    grid.data[index] = cell.map_or(0.0, |cell| values[cell]);
+                }
+            }
+        }
+        grid
+    }
+    
+    /// Cell heights as a smooth raster
+    pub fn rasterize_heights(&self, width: u32, height: u32) -> Grid<f32> {
+        self.rasterize_smooth(width, height, |cell| cell.height)
+    }
+    
+    /// Replace `heightmap` with the cell heights, at one value per map unit
+    pub fn rebuild_heightmap(&mut self) {
+        self.heightmap = self.rasterize_heights(self.metadata.width, self.metadata.height);
+    }
+    
+    /// Bring `heightmap` up to date after the heights of the cells at `indices`
+    /// changed, rasterizing again only the pixels those heights reach
+    ///
+    /// Gives the same values as `rasterize_heights` over the whole map.
+    pub(crate) fn refresh_heightmap(&mut self, indices: &[usize]) {
+        let Some((x0, y0, width, height)) = self.heightmap_extent(indices) else {
+            return;
+        };
+        let topology = self.topology();
+        let changed: HashSet<usize> = indices.iter().copied().collect();
+        let heights: Vec<f32> = self.cells.iter().map(|cell| cell.height).collect();
+        let (grid_width, grid_height) = (self.heightmap.width, self.heightmap.height);
+        
+        // Pixels take the value of the first triangle covering them, so every
+        // triangle is filled in order, marking those that touch a changed cell
+        let mut values = Grid::new(width, height, f32::NAN);
+        let mut marks = Grid::new(width, height, f32::NAN);
+        for triangle in &topology.triangles {
+            let corners = self.raster_corners(*triangle, &heights, grid_width, grid_height, (x0, y0));
+            if !bounds_meet(corners, width, height) {
+                continue;
+            }
+            fill_triangle(&mut values, corners);
+            let touched = if triangle.iter().any(|index| changed.contains(index)) { 1.0 } else { 0.0 };
+            fill_triangle(&mut marks, corners.map(|(x, y, _)| (x, y, touched)));
+        }
+        
+        for y in 0..height {
+            for x in 0..width {
+                let local_index = (y * width + x) as usize;
+                let value = if values.data[local_index].is_nan() {
+                    // Pixels outside every triangle, along the map border, take
+                    // the height of the cell under them
+                    match topology.cell_at(self.pixel_centre(x0 + x, y0 + y, grid_width, grid_height)) {
+                        Some(cell) if changed.contains(&cell) => heights[cell],
+                        _ => continue,
+                    }
+                } else if marks.data[local_index] > 0.5 {
+                    values.data[local_index]
+                } else {
+                    continue;
+                };
+                self.heightmap.data[((y0 + y) * grid_width + x0 + x) as usize] = value;
+            }
+        }
+    }
+    
+    /// Rectangle of `heightmap` as `(x, y, width, height)` covering the cells at
+    /// `indices`, reaching to the centres of their neighbours since a cell's
+    /// area spreads halfway to them; `None` if it is empty
+    ///
+    /// Cells on the map border also reach the map edge, where pixels outside
+    /// every triangle take the height of the cell under them, so their whole
+    /// clipped Voronoi region is covered.
+    pub(crate) fn heightmap_extent(&self, indices: &[usize]) -> Option<(u32, u32, u32, u32)> {
+        let heightmap = &self.heightmap;
+        if indices.is_empty() || heightmap.is_empty() || heightmap.data.len() != (heightmap.width * heightmap.height) as usize {
+            return None;
+        }
+        let topology = self.topology();
+        let (mut min, mut max) = (Point2::new(f32::MAX, f32::MAX), Point2::new(f32::MIN, f32::MIN));
+        let mut include = |position: Point2<f32>| {
+            if position.x.is_finite() && position.y.is_finite() {
+                min = min.inf(&position);
+                max = max.sup(&position);
+            }
+        };
+        for &index in indices {
+            for &other in std::iter::once(&index).chain(&topology.neighbours[index]) {
+                include(self.cells[other].coordinates);
+            }
+        }
+        let border: HashSet<usize> = indices.iter().copied().filter(|&index| topology.border[index]).collect();
+        if !border.is_empty() {
+            for edge in topology.edges.iter().filter(|edge| edge.cells.iter().any(|cell| border.contains(cell))) {
+                edge.vertices.iter().for_each(|&vertex| include(vertex));
+            }
+            // A border cell without neighbours covers the whole map
+            if border.iter().any(|&index| topology.neighbours[index].is_empty()) {
+                include(Point2::new(0.0, 0.0));
+                include(Point2::new(self.metadata.width as f32, self.metadata.height as f32));
+            }
+        }
+        
+        let scale_x = heightmap.width as f32 / self.metadata.width.max(1) as f32;
+        let scale_y = heightmap.height as f32 / self.metadata.height.max(1) as f32;
+        let x = (min.x * scale_x - 0.5).floor().clamp(0.0, heightmap.width as f32) as u32;
+        let y = (min.y * scale_y - 0.5).floor().clamp(0.0, heightmap.height as f32) as u32;
+        let x_end = ((max.x * scale_x - 0.5).ceil() + 1.0).clamp(0.0, heightmap.width as f32) as u32;
+        let y_end = ((max.y * scale_y - 0.5).ceil() + 1.0).clamp(0.0, heightmap.height as f32) as u32;
+        (x_end > x && y_end > y).then_some((x, y, x_end - x, y_end - y))
+    }
+    
+    /// `heightmap` if it holds one value per map unit, otherwise the cell
+    /// heights rasterized at that size
+    pub(crate) fn heightmap_at_map_size(&self) -> Cow<'_, Grid<f32>> {
+        let (width, height) = (self.metadata.width, self.metadata.height);
+        if self.heightmap.width == width && self.heightmap.height == height && !self.heightmap.is_empty() {
+            Cow::Borrowed(&self.heightmap)
+        } else {
+            Cow::Owned(self.rasterize_heights(width, height))
+        }
+    }
+    
+    /// Value of a raster at every cell centre, in the order of `cells`; empty
+    /// for an empty raster or one missing values
+    pub fn sample_cells<T: Clone>(&self, grid: &Grid<T>) -> Vec<T> {
+        self.cells
+            .iter()
+            .map(|cell| {
+                let (x, y) = self.grid_position(cell.coordinates, grid);
+                grid.get_clamped(x.round() as i64, y.round() as i64).cloned()
+            })
+            .collect::<Option<Vec<T>>>()
+            .unwrap_or_default()
+    }
+    
+    /// Value of a numeric raster at every cell centre, in the order of `cells`;
+    /// empty for an empty raster
+    pub fn sample_cells_interpolated(&self, grid: &Grid<f32>, interpolation: Interpolation) -> Vec<f32> {
+        if grid.is_empty() {
+            return Vec::new();
+        }
+        self.cells
+            .iter()
+            .map(|cell| {
+                let (x, y) = self.grid_position(cell.coordinates, grid);
+                grid.sample(x, y, interpolation)
+            })
+            .collect()
+    }
+    
+    /// Set cell heights from `heightmap`
+    pub fn apply_heightmap(&mut self, interpolation: Interpolation) {
+        let heights = self.sample_cells_interpolated(&self.heightmap, interpolation);
+        for (cell, height) in self.cells.iter_mut().zip(heights) {
+            cell.height = height;
+        }
+    }
+    
+    /// Map position of the centre of pixel `(x, y)` of a raster covering the map
+    fn pixel_centre(&self, x: u32, y: u32, width: u32, height: u32) -> Point2<f32> {
+        Point2::new(
+            (x as f32 + 0.5) * self.metadata.width as f32 / width.max(1) as f32,
+            (y as f32 + 0.5) * self.metadata.height as f32 / height.max(1) as f32,
+        )
+    }
+    
+    /// Corners of a triangle of cell centres in the coordinates of a `width` ×
+    /// `height` raster shifted by `origin`, where pixel centres are whole numbers
+    fn raster_corners(
+        &self,
+        triangle: [usize; 3],
+        values: &[f32],
+        width: u32,
+        height: u32,
+        origin: (u32, u32),
+    ) -> [(f64, f64, f32); 3] {
+        let scale_x = width as f64 / self.metadata.width.max(1) as f64;
+        let scale_y = height as f64 / self.metadata.height.max(1) as f64;
+        triangle.map(|index| {
+            let position = self.cells[index].coordinates;
+            (
+                position.x as f64 * scale_x - 0.5 - origin.0 as f64,
+                position.y as f64 * scale_y - 0.5 - origin.1 as f64,
+                values[index],
+            )
+        })
+    }
+    
+    /// Fractional raster coordinates of a map position
+    fn grid_position<T>(&self, point: Point2<f32>, grid: &Grid<T>) -> (f32, f32) {
+        (
+            point.x * grid.width as f32 / self.metadata.width.max(1) as f32 - 0.5,
+            point.y * grid.height as f32 / self.metadata.height.max(1) as f32 - 0.5,
+        )
+    }
+}
+
+/// Whether the bounding box of raster corners reaches into a `width` ×
+/// `height` raster
+fn bounds_meet(corners: [(f64, f64, f32); 3], width: u32, height: u32) -> bool {
+    let [(x0, y0, _), (x1, y1, _), (x2, y2, _)] = corners;
+    x0.max(x1).max(x2) >= 0.0
+        && y0.max(y1).max(y2) >= 0.0
+        && x0.min(x1).min(x2) <= width as f64 - 1.0
+        && y0.min(y1).min(y2) <= height as f64 - 1.0
+}
+
+/// Interpolate linearly across a triangle the pixels whose centres it covers
+/// and that have no value yet
+fn fill_triangle(grid: &mut Grid<f32>, corners: [(f64, f64, f32); 3]) {
+    let [(x0, y0, v0), (x1, y1, v1), (x2, y2, v2)] = corners;
+    let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+    if area.abs() < f64::EPSILON || grid.is_empty() {
+        return;
+    }
+    
+    let (width, height) = (grid.width as f64, grid.height as f64);
+    let min_x = x0.min(x1).min(x2).ceil().max(0.0);
+    let max_x = x0.max(x1).max(x2).floor().min(width - 1.0);
+    let min_y = y0.min(y1).min(y2).ceil().max(0.0);
+    let max_y = y0.max(y1).max(y2).floor().min(height - 1.0);
+    if min_x > max_x || min_y > max_y {
+        return;
+    }
+    
+    // Points on an edge count as inside, so no pixel falls between triangles
+    let tolerance = -1e-9;
+    for y in min_y as u32..=max_y as u32 {
+        for x in min_x as u32..=max_x as u32 {
+            let index = (y * grid.width + x) as usize;
+            if !grid.data[index].is_nan() {
+                continue;
+            }
+            let (px, py) = (x as f64, y as f64);
+            let w0 = ((x1 - px) * (y2 - py) - (x2 - px) * (y1 - py)) / area;
+            let w1 = ((x2 - px) * (y0 - py) - (x0 - px) * (y2 - py)) / area;
+            let w2 = 1.0 - w0 - w1;
+            if w0 >= tolerance && w1 >= tolerance && w2 >= tolerance {
+                grid.data[index] = (w0 * v0 as f64 + w1 * v1 as f64 + w2 * v2 as f64) as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::StateId;
+    
+    #[test]
+    fn rasters_at_cell_resolution_hold_the_cell_values() {
+        let world_map = grid_world(6);
+        let states = world_map.rasterize(6, 6, |cell| cell.state);
+        assert_eq!(states.data, world_map.cells.iter().map(|cell| cell.state).collect::<Vec<_>>());
+        assert_eq!(world_map.rasterize(60, 60, |cell| cell.state).get(59, 59), Some(&Some(StateId(4))));
+        
+        let heights = world_map.rasterize_heights(6, 6);
+        for (value, cell) in heights.data.iter().zip(&world_map.cells) {
+            assert!((value - cell.height).abs() < 1e-5);
+        }
+        assert_eq!(world_map.sample_cells(&states), states.data);
+    }
+    
+    #[test]
+    fn sampling_a_fine_raster_returns_the_cell_values() {
+        let world_map = grid_world(6);
+        assert_eq!((world_map.heightmap.width, world_map.heightmap.height), (60, 60));
+        
+        let heights = world_map.sample_cells_interpolated(&world_map.heightmap, Interpolation::Bilinear);
+        for (height, cell) in heights.iter().zip(&world_map.cells) {
+            assert!((height - cell.height).abs() < 0.02, "{} against {}", height, cell.height);
+        }
+        
+        let mut copy = world_map.clone();
+        copy.cells.iter_mut().for_each(|cell| cell.height = 0.0);
+        copy.apply_heightmap(Interpolation::Nearest);
+        assert!(copy.cells.iter().zip(&world_map.cells).all(|(a, b)| (a.height - b.height).abs() < 0.05));
+        assert!(world_map.sample_cells(&Grid::<f32>::new(0, 0, 0.0)).is_empty());
+    }
+    
+    #[test]
+    fn refreshed_heightmaps_match_a_full_rebuild() {
+        let mut world_map = grid_world(6);
+        for index in [0, 14, 15, 35] {
+            world_map.cells[index].height = 0.95;
+        }
+        world_map.refresh_heightmap(&[0, 14, 15, 35]);
+        assert_eq!(world_map.heightmap.data, world_map.rasterize_heights(60, 60).data);
+        
+        assert_eq!(world_map.heightmap_extent(&[]), None);
+        let (x, y, width, height) = world_map.heightmap_extent(&[14]).unwrap();
+        assert!(x <= 15 && y <= 15 && x + width >= 35 && y + height >= 35);
+    }
+    
+    #[test]
+    fn empty_worlds_give_blank_rasters() {
+        let mut world_map = grid_world(2);
+        world_map.cells.clear();
+        assert_eq!(world_map.rasterize(3, 2, |cell| cell.state).data, [None; 6]);
+        assert_eq!(world_map.rasterize_heights(2, 2).data, [0.0; 4]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Automatic fixes for common integrity problems
+//!
+//! `WorldMap::repair` addresses the issues `WorldMap::validate` reports most
+//! often for imported maps, and lists every change so it can be reviewed.
+
+use super::{BurgId, CellId, CultureId, EntityRef, RiverId, StateId, WorldMap, ZoneId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+/// Aggregates closer than this to the recomputed value are left alone
+const AGGREGATE_TOLERANCE: f32 = 0.5;
+
+/// Changes made by a repair pass, in the order they were made
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub changes: Vec<RepairChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RepairChange {
+    /// An entity sharing its id with an earlier one was given a fresh id;
+    /// references to the old id keep pointing at the earlier entity
+    IdRenumbered { entity: EntityRef, new_id: u32 },
+    /// A zone sharing its id with an earlier one was given a fresh id
+    ZoneRenumbered { zone: ZoneId, new_id: u32 },
+    /// A river with fewer than two cells was removed
+    RiverRemoved { river: RiverId },
+    /// `State::cells` was rebuilt from `Cell::state`
+    StateCellsResynced { state: StateId, added: Vec<CellId>, removed: Vec<CellId> },
+    /// A burg was moved to the state owning its cell
+    BurgReassigned { burg: BurgId, from: StateId, to: StateId },
+    /// An aggregate of a state or culture was recomputed from its cells and
+    /// burgs
+    AggregateUpdated { entity: EntityRef, field: String, old: f32, new: f32 },
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+}
+
+impl WorldMap {
+    /// Fix duplicate ids, empty rivers, stale state cell lists, orphaned burgs
+    /// and state and culture aggregates
+    ///
+    /// Cell ownership (`Cell::state`) is taken as the truth wherever the data
+    /// disagrees.
+    pub fn repair(&mut self) -> RepairReport {
+        let mut report = RepairReport::default();
+        
+        self.renumber_duplicates(&mut report);
+        self.remove_empty_rivers(&mut report);
+        self.resync_state_cells(&mut report);
+        self.reassign_orphaned_burgs(&mut report);
+        self.recompute_state_aggregates(&mut report);
+        self.recompute_culture_aggregates(&mut report);
+        
+        report
+    }
+    
+    fn renumber_duplicates(&mut self, report: &mut RepairReport) {
+        let changes = report.changes.len();
+        renumber(self.cells.iter_mut().map(|cell| &mut cell.id), EntityRef::Cell, report);
+        if report.changes.len() > changes {
+            self.invalidate_topology();
+        }
+        renumber(self.features.iter_mut().map(|feature| &mut feature.id), EntityRef::Feature, report);
+        renumber(self.cultures.iter_mut().map(|culture| &mut culture.id), EntityRef::Culture, report);
+        renumber(self.states.iter_mut().map(|state| &mut state.id), EntityRef::State, report);
+        renumber(self.provinces.iter_mut().map(|province| &mut province.id), EntityRef::Province, report);
+        renumber(self.burgs.iter_mut().map(|burg| &mut burg.id), EntityRef::Settlement, report);
+        renumber(self.rivers.iter_mut().map(|river| &mut river.id), EntityRef::River, report);
+        renumber(self.routes.iter_mut().map(|route| &mut route.id), EntityRef::Route, report);
+        renumber(self.markers.iter_mut().map(|marker| &mut marker.id), EntityRef::Marker, report);
+        for (zone, new_id) in renumber_ids(self.zones.iter_mut().map(|zone| &mut zone.id)) {
+            report.changes.push(RepairChange::ZoneRenumbered { zone, new_id });
+        }
+    }
+    
+    fn remove_empty_rivers(&mut self, report: &mut RepairReport) {
+        self.rivers.retain(|river| {
+            let keep = river.cells.len() >= 2;
+            if !keep {
+                report.changes.push(RepairChange::RiverRemoved { river: river.id });
+            }
+            keep
+        });
+    }
+    
+    fn resync_state_cells(&mut self, report: &mut RepairReport) {
+        let mut owned: BTreeMap<StateId, Vec<CellId>> = BTreeMap::new();
+        for cell in &self.cells {
+            if let Some(state) = cell.state {
+                owned.entry(state).or_default().push(cell.id);
+            }
+        }
+        
+        for state in self.states.iter_mut() {
+            let cells = owned.remove(&state.id).unwrap_or_default();
+            let old: BTreeSet<CellId> = state.cells.iter().copied().collect();
+            let new: BTreeSet<CellId> = cells.iter().copied().collect();
+            if old != new {
+                report.changes.push(RepairChange::StateCellsResynced {
+                    state: state.id,
+                    added: new.difference(&old).copied().collect(),
+                    removed: old.difference(&new).copied().collect(),
+                });
+            }
+            state.cells = cells;
+        }
+    }
+    
+    /// Burgs follow the owner of their cell; burgs of missing states on
+    /// unowned cells become neutral (state 0)
+    fn reassign_orphaned_burgs(&mut self, report: &mut RepairReport) {
+        let states: HashSet<StateId> = self.states.iter().map(|state| state.id).collect();
+        let cell_states: HashMap<CellId, Option<StateId>> = self.cells.iter().map(|cell| (cell.id, cell.state)).collect();
+        
+        for burg in self.burgs.iter_mut() {
+            let owner = cell_states.get(&burg.cell).copied().flatten();
+            let state_missing = !states.contains(&burg.state) && burg.state != StateId::NEUTRAL;
+            let target = match owner {
+                Some(owner) if owner != burg.state => owner,
+                None if state_missing => StateId::NEUTRAL,
+                _ => continue,
+            };
+            report.changes.push(RepairChange::BurgReassigned { burg: burg.id, from: burg.state, to: target });
+            burg.state = target;
+        }
+    }
+    
+    fn recompute_state_aggregates(&mut self, report: &mut RepairReport) {
+        let topology = self.topology();
+        let mut totals: HashMap<StateId, (f32, f32, f32)> = HashMap::new();
+        for (index, cell) in self.cells.iter().enumerate() {
+            if let Some(state) = cell.state {
+                let total = totals.entry(state).or_default();
+                total.0 += topology.areas[index];
+                total.1 += cell.population as f32;
+            }
+        }
+        let mut burg_counts: HashMap<StateId, u32> = HashMap::new();
+        for burg in &self.burgs {
+            totals.entry(burg.state).or_default().2 += burg.population;
+            *burg_counts.entry(burg.state).or_default() += 1;
+        }
+        
+        for state in self.states.iter_mut() {
+            let (area, rural, urban) = totals.get(&state.id).copied().unwrap_or_default();
+            let burgs = burg_counts.get(&state.id).copied().unwrap_or(0);
+            let population = (rural + urban).round();
+            
+            let mut update = |field: &str, old: f32, new: f32| -> bool {
+                update_aggregate(report, EntityRef::State(state.id), field, old, new)
+            };
+            if update("area", state.area, area) {
+                state.area = area;
+            }
+            // Totals are always taken over, only reported past the tolerance
+            update("rural", state.rural, rural);
+            update("urban", state.urban, urban);
+            (state.rural, state.urban) = (rural, urban);
+            if update("population", state.population as f32, population) {
+                state.population = population as u32;
+            }
+            if update("burgs", state.burgs as f32, burgs as f32) {
+                state.burgs = burgs;
+            }
+        }
+    }
+    
+    fn recompute_culture_aggregates(&mut self, report: &mut RepairReport) {
+        let mut totals: HashMap<CultureId, (f32, f32)> = HashMap::new();
+        for cell in &self.cells {
+            if let Some(culture) = cell.culture {
+                totals.entry(culture).or_default().0 += cell.population as f32;
+            }
+        }
+        for burg in &self.burgs {
+            totals.entry(burg.culture).or_default().1 += burg.population;
+        }
+        
+        for culture in self.cultures.iter_mut() {
+            let (rural, urban) = totals.get(&culture.id).copied().unwrap_or_default();
+            let entity = EntityRef::Culture(culture.id);
+            update_aggregate(report, entity, "rural", culture.rural, rural);
+            update_aggregate(report, entity, "urban", culture.urban, urban);
+            (culture.rural, culture.urban) = (rural, urban);
+        }
+    }
+}
+
+/// Report an aggregate that is missing or strays from its recomputed value by
+/// more than the tolerance, returning whether it did
+fn update_aggregate(report: &mut RepairReport, entity: EntityRef, field: &str, old: f32, new: f32) -> bool {
+    let changed = old.is_nan() || (old - new).abs() > AGGREGATE_TOLERANCE;
+    if changed {
+        report.changes.push(RepairChange::AggregateUpdated { entity, field: field.to_string(), old, new });
+    }
+    changed
+}
+
+/// Renumber repeated ids of entities of one kind, reporting each
+fn renumber<'a, T>(ids: impl Iterator<Item = &'a mut T>, entity: fn(T) -> EntityRef, report: &mut RepairReport)
+where
+    T: Copy + Eq + Hash + From<u32> + Into<u32> + 'a,
+{
+    for (id, new_id) in renumber_ids(ids) {
+        report.changes.push(RepairChange::IdRenumbered { entity: entity(id), new_id });
+    }
+}
+
+/// Give every repeated id a fresh one past the largest id in use, returning
+/// the repeated ids with the ones they were given
+fn renumber_ids<'a, T>(ids: impl Iterator<Item = &'a mut T>) -> Vec<(T, u32)>
+where
+    T: Copy + Eq + Hash + From<u32> + Into<u32> + 'a,
+{
+    let mut ids: Vec<&mut T> = ids.collect();
+    let mut next = ids.iter().map(|id| (**id).into()).max().map_or(0, |max: u32| max + 1);
+    let mut seen = HashSet::new();
+    let mut renumbered = Vec::new();
+    
+    for id in ids.iter_mut() {
+        if !seen.insert(**id) {
+            renumbered.push((**id, next));
+            **id = T::from(next);
+            next += 1;
+        }
+    }
+    renumbered
+}
+
+impl fmt::Display for RepairChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairChange::IdRenumbered { entity, new_id } => write!(f, "renumbered duplicate {} to {}", entity, new_id),
+            RepairChange::ZoneRenumbered { zone, new_id } => write!(f, "renumbered duplicate zone {} to {}", zone, new_id),
+            RepairChange::RiverRemoved { river } => write!(f, "removed river {} with fewer than two cells", river),
+            RepairChange::StateCellsResynced { state, added, removed } => write!(
+                f,
+                "resynced cells of state {}: {} added, {} removed",
+                state,
+                added.len(),
+                removed.len()
+            ),
+            RepairChange::BurgReassigned { burg, from, to } => {
+                write!(f, "moved settlement {} from state {} to state {}", burg, from, to)
+            }
+            RepairChange::AggregateUpdated { entity, field, old, new } => {
+                write!(f, "updated {} of {} from {} to {}", field, entity, old, new)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{Color, Feature, FeatureId, FeatureType, Zone};
+    
+    #[test]
+    fn duplicate_features_and_zones_are_renumbered() {
+        let mut world_map = grid_world(4);
+        let feature = Feature {
+            id: FeatureId(0),
+            name: "Isle".to_string(),
+            feature_type: FeatureType::Island,
+            cells: vec![CellId(5)],
+            group: None,
+            note: None,
+            attributes: Default::default(),
+        };
+        world_map.features = vec![feature.clone(), feature];
+        let zone = Zone {
+            id: ZoneId(3),
+            name: "Plague".to_string(),
+            cells: vec![CellId(6)],
+            color: Color::default(),
+            note: None,
+            attributes: Default::default(),
+        };
+        world_map.zones = vec![zone.clone(), zone];
+        
+        let report = world_map.repair();
+        assert_eq!(world_map.features[1].id, FeatureId(1));
+        assert_eq!(world_map.zones[1].id, ZoneId(4));
+        assert!(report.changes.contains(&RepairChange::IdRenumbered { entity: EntityRef::Feature(FeatureId(0)), new_id: 1 }));
+        assert!(report.changes.contains(&RepairChange::ZoneRenumbered { zone: ZoneId(3), new_id: 4 }));
+    }
+    
+    #[test]
+    fn stale_state_areas_are_recomputed() {
+        let mut world_map = grid_world(6);
+        let (area, population) = (world_map.states[0].area, world_map.states[0].population);
+        world_map.states[0].area *= 2.0;
+        world_map.states[1].area = f32::NAN;
+        
+        let report = world_map.repair();
+        assert_eq!(world_map.states[0].area, area);
+        assert!(world_map.states[1].area.is_finite());
+        assert_eq!(world_map.states[0].population, population);
+        assert_eq!(report.changes.iter().filter(|change| matches!(change, RepairChange::AggregateUpdated { .. })).count(), 2);
+        
+        // Repairing again changes nothing
+        assert!(world_map.repair().is_empty());
+    }
+    
+    #[test]
+    fn moving_cells_between_states_moves_their_population() {
+        let mut world_map = grid_world(6);
+        for cell in world_map.cells.iter_mut() {
+            cell.population = 100;
+        }
+        world_map.repair();
+        let (first, second) = (world_map.states[0].rural, world_map.states[1].rural);
+        
+        // Cell 7 lies in state 1; hand it to state 2 behind repair's back
+        world_map.cells[7].state = Some(StateId(2));
+        let report = world_map.repair();
+        assert_eq!(world_map.states[0].rural, first - 100.0);
+        assert_eq!(world_map.states[1].rural, second + 100.0);
+        assert_eq!(world_map.states[1].population as f32, world_map.states[1].rural + world_map.states[1].urban);
+        let entity = EntityRef::State(StateId(2));
+        let rural = RepairChange::AggregateUpdated { entity, field: "rural".to_string(), old: second, new: second + 100.0 };
+        assert!(report.changes.contains(&rural));
+        assert!(world_map.states[1].cells.contains(&CellId(7)));
+        
+        let culture_total: f32 = world_map.cultures.iter().map(|culture| culture.rural).sum();
+        let cells_total = world_map.cells.iter().filter(|cell| cell.culture.is_some()).count() as f32 * 100.0;
+        assert_eq!(culture_total, cells_total);
+    }
+}
@@ -0,0 +1,186 @@
+//! Versioning of the saved `WorldMap` layout
+//!
+//! Every saved world records the schema version it was written with in
+//! `MapMetadata::schema_version`. Loading goes through `migrate`, which
+//! upgrades older documents one version at a time before they are
+//! deserialized, and refuses documents written by a newer engine.
+
+use super::WorldMap;
+use crate::{Result, WorldFoundryError};
+use serde_json::{Map, Value};
+
+/// Schema version written by this build
+///
+/// Bump it whenever a change to the data model would stop older files from
+/// deserializing, and add the matching step to `MIGRATIONS`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Upgrade of a document from one schema version to the next
+struct Migration {
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut Map<String, Value>) -> Result<()>,
+}
+
+/// Every migration, ordered by the version it upgrades from
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "rename `type_` fields of states, burgs, rivers and markers to `type`",
+        apply: rename_type_fields,
+    },
+    Migration {
+        from: 1,
+        description: "turn the plain-text `note` of markers into a titled note",
+        apply: convert_marker_notes,
+    },
+];
+
+/// Schema version of a serialized world; files from before versioning are 0
+pub fn schema_version(document: &Value) -> u32 {
+    document
+        .get("metadata")
+        .and_then(|metadata| metadata.get("schema_version"))
+        .and_then(Value::as_u64)
+        .map_or(0, |version| version as u32)
+}
+
+/// Fail if a document of this schema version is too new to be read
+pub fn check_schema_version(version: u32) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        return Err(WorldFoundryError::UnsupportedSchema { found: version, supported: SCHEMA_VERSION });
+    }
+    Ok(())
+}
+
+/// Upgrade a serialized world to `SCHEMA_VERSION` in place
+///
+/// Returns the description of each migration applied, oldest first.
+pub fn migrate(document: &mut Value) -> Result<Vec<&'static str>> {
+    let mut version = schema_version(document);
+    check_schema_version(version)?;
+    
+    let root = document
+        .as_object_mut()
+        .ok_or_else(|| WorldFoundryError::Import("World document is not an object".to_string()))?;
+    let mut applied = Vec::new();
+    while version < SCHEMA_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| WorldFoundryError::Import(format!("No migration from schema version {}", version)))?;
+        (migration.apply)(root)?;
+        applied.push(migration.description);
+        version += 1;
+    }
+    
+    if let Some(Value::Object(metadata)) = root.get_mut("metadata") {
+        metadata.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    }
+    Ok(applied)
+}
+
+impl WorldMap {
+    /// Load a world saved as JSON, upgrading it from older schema versions
+    pub fn from_json(json: &str) -> Result<WorldMap> {
+        Self::from_json_value(serde_json::from_str(json)?)
+    }
+    
+    /// Deserialize a world, upgrading it from older schema versions
+    pub fn from_json_value(mut document: Value) -> Result<WorldMap> {
+        migrate(&mut document)?;
+        Ok(serde_json::from_value(document)?)
+    }
+}
+
+/// Objects of a top-level array such as `states`
+fn entities<'a>(root: &'a mut Map<String, Value>, collection: &str) -> impl Iterator<Item = &'a mut Map<String, Value>> {
+    root.get_mut(collection)
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn rename_field(object: &mut Map<String, Value>, from: &str, to: &str) {
+    if let Some(value) = object.remove(from) {
+        object.insert(to.to_string(), value);
+    }
+}
+
+fn rename_type_fields(root: &mut Map<String, Value>) -> Result<()> {
+    for collection in ["states", "burgs", "rivers", "markers"] {
+        for entity in entities(root, collection) {
+            rename_field(entity, "type_", "type");
+        }
+    }
+    Ok(())
+}
+
+fn convert_marker_notes(root: &mut Map<String, Value>) -> Result<()> {
+    for marker in entities(root, "markers") {
+        let note = match marker.remove("note") {
+            Some(Value::String(text)) if !text.is_empty() => {
+                let mut note = Map::new();
+                note.insert("title".to_string(), Value::from(""));
+                note.insert("legend".to_string(), Value::from(text));
+                Value::Object(note)
+            }
+            _ => Value::Null,
+        };
+        marker.insert("note".to_string(), note);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use serde_json::json;
+    
+    /// Document of a world as it was saved before schema versioning
+    fn unversioned_document() -> Value {
+        let mut document = serde_json::to_value(grid_world(4)).unwrap();
+        document["metadata"].as_object_mut().unwrap().remove("schema_version");
+        for (index, burg) in document["burgs"].as_array_mut().unwrap().iter_mut().enumerate() {
+            rename_field(burg.as_object_mut().unwrap(), "type", "type_");
+            // Capitals were flagged with 0 or 1
+            burg["capital"] = json!((index == 0) as u32);
+        }
+        document["markers"] = json!([{
+            "id": 0, "icon": "🏺", "type_": "Ruins", "dx": 50.0, "dy": 50.0, "x": 15.0, "y": 15.0,
+            "cell": 5, "i": 0, "size": 30.0, "fill": "#c9b79c", "stroke": "#000000", "note": "Ruins of a temple"
+        }]);
+        document
+    }
+    
+    #[test]
+    fn old_documents_are_migrated_one_version_at_a_time() {
+        let mut document = unversioned_document();
+        assert_eq!(schema_version(&document), 0);
+        
+        let applied = migrate(&mut document).unwrap();
+        assert_eq!(applied, MIGRATIONS.iter().map(|migration| migration.description).collect::<Vec<_>>());
+        assert_eq!(schema_version(&document), SCHEMA_VERSION);
+        assert_eq!(document["markers"][0]["note"], json!({ "title": "", "legend": "Ruins of a temple" }));
+        assert!(migrate(&mut document).unwrap().is_empty());
+        
+        let world_map = WorldMap::from_json_value(unversioned_document()).unwrap();
+        assert_eq!(world_map.metadata.schema_version, SCHEMA_VERSION);
+        assert_eq!(world_map.burgs.len(), 8);
+        assert!(world_map.burgs[0].capital && !world_map.burgs[1].capital);
+        assert_eq!(world_map.markers[0].note.as_ref().unwrap().legend, "Ruins of a temple");
+    }
+    
+    #[test]
+    fn documents_from_newer_engines_are_refused() {
+        let mut document = unversioned_document();
+        document["metadata"]["schema_version"] = json!(SCHEMA_VERSION + 1);
+        assert!(matches!(
+            WorldMap::from_json_value(document),
+            Err(WorldFoundryError::UnsupportedSchema { found, supported: SCHEMA_VERSION }) if found == SCHEMA_VERSION + 1
+        ));
+        assert!(migrate(&mut json!([])).is_err());
+    }
+}
@@ -0,0 +1,534 @@
+//! Combining two world maps into one
+//!
+//! The second map is placed to the right of or below the first, either edge to
+//! edge or overlapping by a band in which their heights blend. Each map keeps
+//! its own cells up to the middle of the band. Ids of the second map are moved
+//! past those of the first, except for cultures and states folded into one of
+//! the first map's.
+
+use super::{
+    BurgId, CellId, CultureId, EntityRef, EventId, FeatureId, Grid, MarkerId, ProvinceId, RiverId, RouteId, StateId,
+    StateSnapshot, WorldMap, ZoneId,
+};
+use crate::{Result, WorldFoundryError};
+use chrono::Utc;
+use nalgebra::{Point2, Vector2};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Where the second map goes relative to the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    #[default]
+    Right,
+    Below,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StitchOptions {
+    pub placement: Placement,
+    /// Width of the band, in map units, where the maps overlap and their
+    /// heights blend; 0 joins them edge to edge
+    pub overlap: u32,
+    /// Cultures of the second map folded into a culture of the first, as
+    /// `(first, second)` pairs
+    pub merge_cultures: Vec<(CultureId, CultureId)>,
+    /// States of the second map folded into a state of the first, as
+    /// `(first, second)` pairs
+    pub merge_states: Vec<(StateId, StateId)>,
+}
+
+/// New ids for the entities of the second map
+///
+/// Ids are moved past the largest id of the first map. Id 0 of states,
+/// cultures and the like stays 0, since references use it for "none"; cells,
+/// zones and events have no "none" and move past the largest id plus one.
+struct Renumbering {
+    cells: u32,
+    features: u32,
+    cultures: u32,
+    states: u32,
+    provinces: u32,
+    burgs: u32,
+    rivers: u32,
+    routes: u32,
+    markers: u32,
+    zones: u32,
+    events: u32,
+    merged_cultures: HashMap<CultureId, CultureId>,
+    merged_states: HashMap<StateId, StateId>,
+}
+
+impl StitchOptions {
+    /// Second map joined edge to edge
+    pub fn side_by_side(placement: Placement) -> Self {
+        Self { placement, ..Self::default() }
+    }
+    
+    /// Second map overlapping the first by `overlap` map units, heights blended
+    pub fn stitched(placement: Placement, overlap: u32) -> Self {
+        Self { placement, overlap, ..Self::default() }
+    }
+}
+
+impl Renumbering {
+    fn new(first: &WorldMap, options: &StitchOptions) -> Self {
+        fn max_id<T>(items: &[T], id: impl Fn(&T) -> u32) -> u32 {
+            items.iter().map(id).max().unwrap_or(0)
+        }
+        Self {
+            cells: first.cells.iter().map(|cell| cell.id.0 + 1).max().unwrap_or(0),
+            features: max_id(&first.features, |feature| feature.id.0),
+            cultures: max_id(&first.cultures, |culture| culture.id.0),
+            states: max_id(&first.states, |state| state.id.0),
+            provinces: max_id(&first.provinces, |province| province.id.0),
+            burgs: max_id(&first.burgs, |burg| burg.id.0),
+            rivers: max_id(&first.rivers, |river| river.id.0),
+            routes: max_id(&first.routes, |route| route.id.0),
+            markers: max_id(&first.markers, |marker| marker.id.0),
+            zones: first.zones.iter().map(|zone| zone.id.0 + 1).max().unwrap_or(0),
+            events: first.events.iter().map(|event| event.id.0 + 1).max().unwrap_or(0),
+            merged_cultures: options.merge_cultures.iter().map(|&(first, second)| (second, first)).collect(),
+            merged_states: options.merge_states.iter().map(|&(first, second)| (second, first)).collect(),
+        }
+    }
+    
+    fn cell(&self, id: CellId) -> CellId {
+        CellId(id.0 + self.cells)
+    }
+    
+    fn feature(&self, id: FeatureId) -> FeatureId {
+        FeatureId(shift(id.0, self.features))
+    }
+    
+    fn culture(&self, id: CultureId) -> CultureId {
+        self.merged_cultures.get(&id).copied().unwrap_or(CultureId(shift(id.0, self.cultures)))
+    }
+    
+    fn state(&self, id: StateId) -> StateId {
+        self.merged_states.get(&id).copied().unwrap_or(StateId(shift(id.0, self.states)))
+    }
+    
+    fn province(&self, id: ProvinceId) -> ProvinceId {
+        ProvinceId(shift(id.0, self.provinces))
+    }
+    
+    fn burg(&self, id: BurgId) -> BurgId {
+        BurgId(shift(id.0, self.burgs))
+    }
+    
+    fn river(&self, id: RiverId) -> RiverId {
+        RiverId(shift(id.0, self.rivers))
+    }
+    
+    fn route(&self, id: RouteId) -> RouteId {
+        RouteId(shift(id.0, self.routes))
+    }
+    
+    fn marker(&self, id: MarkerId) -> MarkerId {
+        MarkerId(shift(id.0, self.markers))
+    }
+    
+    fn zone(&self, id: ZoneId) -> ZoneId {
+        ZoneId(id.0 + self.zones)
+    }
+    
+    fn entity(&self, entity: EntityRef) -> EntityRef {
+        match entity {
+            EntityRef::Cell(id) => EntityRef::Cell(self.cell(id)),
+            EntityRef::Feature(id) => EntityRef::Feature(self.feature(id)),
+            EntityRef::Culture(id) => EntityRef::Culture(self.culture(id)),
+            EntityRef::State(id) => EntityRef::State(self.state(id)),
+            EntityRef::Province(id) => EntityRef::Province(self.province(id)),
+            EntityRef::Settlement(id) => EntityRef::Settlement(self.burg(id)),
+            EntityRef::River(id) => EntityRef::River(self.river(id)),
+            EntityRef::Route(id) => EntityRef::Route(self.route(id)),
+            EntityRef::Marker(id) => EntityRef::Marker(self.marker(id)),
+            EntityRef::Event(id) => EntityRef::Event(EventId(id.0 + self.events)),
+        }
+    }
+    
+    fn cells(&self, cells: &mut [CellId]) {
+        cells.iter_mut().for_each(|cell| *cell = self.cell(*cell));
+    }
+    
+    /// Renumber every id in the map and the references to them
+    fn apply(&self, world_map: &mut WorldMap) {
+        for cell in world_map.cells.iter_mut() {
+            cell.id = self.cell(cell.id);
+            cell.culture = cell.culture.map(|culture| self.culture(culture));
+            cell.state = cell.state.map(|state| self.state(state));
+            cell.province = cell.province.map(|province| self.province(province));
+        }
+        for feature in world_map.features.iter_mut() {
+            feature.id = self.feature(feature.id);
+            self.cells(&mut feature.cells);
+        }
+        for culture in world_map.cultures.iter_mut() {
+            culture.id = self.culture(culture.id);
+            culture.origins.iter_mut().for_each(|origin| *origin = self.culture(*origin));
+        }
+        for state in world_map.states.iter_mut() {
+            state.id = self.state(state.id);
+            state.capital = self.burg(state.capital);
+            state.culture = self.culture(state.culture);
+            self.cells(&mut state.cells);
+        }
+        for province in world_map.provinces.iter_mut() {
+            province.id = self.province(province.id);
+            province.state = self.state(province.state);
+            province.burg = province.burg.map(|burg| self.burg(burg));
+            self.cells(&mut province.cells);
+        }
+        for burg in world_map.burgs.iter_mut() {
+            burg.id = self.burg(burg.id);
+            burg.cell = self.cell(burg.cell);
+            burg.state = self.state(burg.state);
+            burg.culture = self.culture(burg.culture);
+            burg.feature = self.feature(burg.feature);
+        }
+        for river in world_map.rivers.iter_mut() {
+            river.id = self.river(river.id);
+            river.basin = self.river(river.basin);
+            river.source = self.cell(river.source);
+            river.mouth = self.cell(river.mouth);
+            self.cells(&mut river.cells);
+        }
+        for route in world_map.routes.iter_mut() {
+            route.id = self.route(route.id);
+            route.feature = self.feature(route.feature);
+            self.cells(&mut route.cells);
+        }
+        for marker in world_map.markers.iter_mut() {
+            marker.id = self.marker(marker.id);
+            marker.cell = self.cell(marker.cell);
+        }
+        for zone in world_map.zones.iter_mut() {
+            zone.id = self.zone(zone.id);
+            self.cells(&mut zone.cells);
+        }
+        
+        let relations = world_map
+            .diplomacy
+            .iter()
+            .map(|relation| (self.state(relation.from), self.state(relation.to), relation.status))
+            .collect::<Vec<_>>();
+        world_map.diplomacy = Default::default();
+        for (from, to, status) in relations {
+            world_map.diplomacy.set(from, to, status);
+        }
+        for event in world_map.history.events.iter_mut() {
+            event.states.iter_mut().for_each(|state| *state = self.state(*state));
+            self.cells(&mut event.cells);
+        }
+        for snapshot in world_map.history.snapshots.iter_mut() {
+            snapshot.cell_states.iter_mut().flatten().for_each(|state| *state = self.state(*state));
+        }
+        for event in world_map.events.iter_mut() {
+            event.id = EventId(event.id.0 + self.events);
+            event.subjects.iter_mut().for_each(|subject| *subject = self.entity(*subject));
+        }
+    }
+}
+
+impl WorldMap {
+    /// Combine this map with another placed beside it
+    ///
+    /// Metadata, settings and the calendar come from this map. Entities
+    /// folded into one of this map's by `options`, or sharing an id 0 with
+    /// one of them, are dropped in favour of it.
+    pub fn stitch(&self, other: &WorldMap, options: &StitchOptions) -> Result<WorldMap> {
+        let (first_size, second_size) = match options.placement {
+            Placement::Right => (self.metadata.width, other.metadata.width),
+            Placement::Below => (self.metadata.height, other.metadata.height),
+        };
+        if options.overlap > first_size.min(second_size) {
+            return Err(WorldFoundryError::Edit(format!(
+                "overlap of {} is wider than the maps ({} and {})",
+                options.overlap, first_size, second_size
+            )));
+        }
+        
+        // Axis along which the maps are joined, and where the second one starts on it
+        let axis = match options.placement {
+            Placement::Right => Vector2::new(1.0, 0.0),
+            Placement::Below => Vector2::new(0.0, 1.0),
+        };
+        let start = (first_size - options.overlap) as f32;
+        let seam = start + options.overlap as f32 / 2.0;
+        let offset = axis * start;
+        let along = |point: Point2<f32>| point.coords.dot(&axis);
+        let (width, height) = match options.placement {
+            Placement::Right => (
+                self.metadata.width + other.metadata.width - options.overlap,
+                self.metadata.height.max(other.metadata.height),
+            ),
+            Placement::Below => (
+                self.metadata.width.max(other.metadata.width),
+                self.metadata.height + other.metadata.height - options.overlap,
+            ),
+        };
+        
+        let mut second = other.clone();
+        Renumbering::new(self, options).apply(&mut second);
+        translate(&mut second, offset);
+        let heightmap = blend_heightmaps(self, other, options, width, height);
+        
+        // Cells given up to the other map at the seam, with their positions
+        let removed: HashMap<CellId, Point2<f32>> = self
+            .cells
+            .iter()
+            .filter(|cell| along(cell.coordinates) >= seam)
+            .chain(second.cells.iter().filter(|cell| along(cell.coordinates) < seam))
+            .map(|cell| (cell.id, cell.coordinates))
+            .collect();
+        
+        let mut metadata = self.metadata.clone();
+        metadata.id = Uuid::new_v4();
+        metadata.width = width;
+        metadata.height = height;
+        metadata.modified_at = Utc::now();
+        for (element, note) in &second.metadata.notes {
+            metadata.notes.entry(element.clone()).or_insert_with(|| note.clone());
+        }
+        
+        let mut diplomacy = self.diplomacy.clone();
+        for relation in second.diplomacy.iter() {
+            if !self.diplomacy.iter().any(|existing| (existing.from, existing.to) == (relation.from, relation.to)) {
+                diplomacy.set(relation.from, relation.to, relation.status);
+            }
+        }
+        
+        let mut history = self.history.clone();
+        history.start_year = history.start_year.min(second.history.start_year);
+        history.end_year = history.end_year.max(second.history.end_year);
+        history.events.extend(second.history.events.iter().cloned());
+        history.events.sort_by_key(|event| event.year);
+        // Snapshots cover every cell, so only years recorded in both maps carry over
+        history.snapshots = self
+            .history
+            .snapshots
+            .iter()
+            .filter_map(|first| {
+                let second_snapshot = second.history.snapshots.iter().find(|snapshot| snapshot.year == first.year)?;
+                let cell_states = (self.cells.iter().zip(&first.cell_states))
+                    .chain(second.cells.iter().zip(&second_snapshot.cell_states))
+                    .filter(|(cell, _)| !removed.contains_key(&cell.id))
+                    .map(|(_, state)| *state)
+                    .collect();
+                Some(StateSnapshot { year: first.year, cell_states })
+            })
+            .collect();
+        for event in history.events.iter_mut() {
+            event.cells.retain(|cell| !removed.contains_key(cell));
+        }
+        
+        let mut world_map = WorldMap {
+            metadata,
+            heightmap,
+            cells: self.cells.iter().chain(&second.cells).filter(|cell| !removed.contains_key(&cell.id)).cloned().collect(),
+            features: self.features.iter().chain(&second.features).cloned().collect(),
+            cultures: combine(&self.cultures, &second.cultures, |culture| culture.id),
+            states: combine(&self.states, &second.states, |state| state.id),
+            provinces: self.provinces.iter().chain(&second.provinces).cloned().collect(),
+            burgs: combine(&self.burgs, &second.burgs, |burg| burg.id),
+            rivers: combine(&self.rivers, &second.rivers, |river| river.id),
+            routes: combine(&self.routes, &second.routes, |route| route.id),
+            markers: combine(&self.markers, &second.markers, |marker| marker.id),
+            zones: self.zones.iter().chain(&second.zones).cloned().collect(),
+            diplomacy,
+            history,
+            calendar: self.calendar.clone(),
+            events: self.events.iter().chain(&second.events).cloned().collect(),
+            topology: Default::default(),
+        };
+        
+        // Entities on given-up cells move to the nearest remaining cell
+        let topology = world_map.topology();
+        let replacements: HashMap<CellId, CellId> = removed
+            .iter()
+            .filter_map(|(&id, &point)| Some((id, world_map.cells[topology.cell_at(point)?].id)))
+            .collect();
+        let replace = |cell: CellId| replacements.get(&cell).copied().unwrap_or(cell);
+        for burg in world_map.burgs.iter_mut() {
+            burg.cell = replace(burg.cell);
+        }
+        for marker in world_map.markers.iter_mut() {
+            marker.cell = replace(marker.cell);
+        }
+        for subject in world_map.events.iter_mut().flat_map(|event| event.subjects.iter_mut()) {
+            if let EntityRef::Cell(cell) = subject {
+                *cell = replace(*cell);
+            }
+        }
+        for river in world_map.rivers.iter_mut() {
+            river.cells = river.cells.iter().map(|&cell| replace(cell)).collect();
+            river.cells.dedup();
+            if let (Some(&source), Some(&mouth)) = (river.cells.first(), river.cells.last()) {
+                river.source = source;
+                river.mouth = mouth;
+            }
+        }
+        for route in world_map.routes.iter_mut() {
+            route.cells = route.cells.iter().map(|&cell| replace(cell)).collect();
+            route.cells.dedup();
+        }
+        for feature in world_map.features.iter_mut() {
+            feature.cells.retain(|cell| !removed.contains_key(cell));
+        }
+        for province in world_map.provinces.iter_mut() {
+            province.cells.retain(|cell| !removed.contains_key(cell));
+        }
+        for zone in world_map.zones.iter_mut() {
+            zone.cells.retain(|cell| !removed.contains_key(cell));
+        }
+        world_map.features.retain(|feature| !feature.cells.is_empty());
+        world_map.provinces.retain(|province| !province.cells.is_empty());
+        world_map.zones.retain(|zone| !zone.cells.is_empty());
+        
+        // Cells in the band take the blended heights
+        let band = start..start + options.overlap as f32;
+        for index in 0..world_map.cells.len() {
+            let point = world_map.cells[index].coordinates;
+            if band.contains(&along(point)) {
+                world_map.cells[index].height = world_map.heightmap.sample(point.x - 0.5, point.y - 0.5, Default::default());
+            }
+        }
+        
+        world_map.repair();
+        Ok(world_map)
+    }
+}
+
+/// Id moved by `offset`, keeping 0 as "none"
+fn shift(id: u32, offset: u32) -> u32 {
+    if id == 0 {
+        0
+    } else {
+        id + offset
+    }
+}
+
+/// Entities of both maps, without those of the second whose id the first already uses
+fn combine<T: Clone, I: Eq + std::hash::Hash>(first: &[T], second: &[T], id: impl Fn(&T) -> I) -> Vec<T> {
+    let taken: HashSet<I> = first.iter().map(&id).collect();
+    first.iter().chain(second.iter().filter(|item| !taken.contains(&id(item)))).cloned().collect()
+}
+
+/// Move every position in the map by `offset`
+fn translate(world_map: &mut WorldMap, offset: Vector2<f32>) {
+    for cell in world_map.cells.iter_mut() {
+        cell.coordinates += offset;
+    }
+    for culture in world_map.cultures.iter_mut() {
+        culture.center += offset;
+    }
+    for state in world_map.states.iter_mut() {
+        state.center += offset;
+    }
+    for province in world_map.provinces.iter_mut() {
+        province.center += offset;
+    }
+    for burg in world_map.burgs.iter_mut() {
+        burg.x += offset.x;
+        burg.y += offset.y;
+    }
+    for location in world_map.events.iter_mut().filter_map(|event| event.location.as_mut()) {
+        *location += offset;
+    }
+    for marker in world_map.markers.iter_mut() {
+        marker.x += offset.x;
+        marker.y += offset.y;
+    }
+}
+
+/// Heights of both maps on the combined grid, cross-faded across the overlap;
+/// areas neither map covers take the lowest height of either
+fn blend_heightmaps(first: &WorldMap, second: &WorldMap, options: &StitchOptions, width: u32, height: u32) -> Grid<f32> {
+    let (first_heights, second_heights) = (first.heightmap_at_map_size(), second.heightmap_at_map_size());
+    let floor = [first_heights.min(), second_heights.min()].into_iter().flatten().fold(f32::MAX, f32::min);
+    let floor = if floor == f32::MAX { 0.0 } else { floor };
+    let (start, overlap) = match options.placement {
+        Placement::Right => (first.metadata.width - options.overlap, options.overlap),
+        Placement::Below => (first.metadata.height - options.overlap, options.overlap),
+    };
+    
+    Grid::from_fn(width, height, |x, y| {
+        let (position, second_x, second_y) = match options.placement {
+            Placement::Right => (x, x.checked_sub(start), Some(y)),
+            Placement::Below => (y, Some(x), y.checked_sub(start)),
+        };
+        let a = first_heights.get(x, y).copied();
+        let b = second_x.zip(second_y).and_then(|(x, y)| second_heights.get(x, y)).copied();
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                let t = ((position - start) as f32 + 0.5) / overlap.max(1) as f32;
+                let t = t * t * (3.0 - 2.0 * t);
+                a + (b - a) * t
+            }
+            (Some(value), None) | (None, Some(value)) => value,
+            (None, None) => floor,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    #[test]
+    fn maps_side_by_side_keep_every_entity_under_new_ids() {
+        let (first, second) = (grid_world(4), grid_world(4));
+        let world_map = first.stitch(&second, &StitchOptions::side_by_side(Placement::Right)).unwrap();
+        
+        assert_eq!((world_map.metadata.width, world_map.metadata.height), (80, 40));
+        assert_eq!((world_map.heightmap.width, world_map.heightmap.height), (80, 40));
+        assert_eq!(world_map.cells.len(), 32);
+        assert_eq!(world_map.states.len(), 8);
+        assert_eq!(world_map.burgs.len(), 16);
+        assert!(world_map.validate().is_valid(), "{:?}", world_map.validate().issues);
+        
+        let moved = world_map.burg(BurgId(9)).unwrap();
+        let original = second.burg(BurgId(1)).unwrap();
+        assert_eq!((moved.x, moved.y), (original.x + 40.0, original.y));
+        assert_eq!(moved.state, StateId(original.state.0 + 4));
+        assert_eq!(world_map.cell(moved.cell).unwrap().coordinates.x, moved.x);
+    }
+    
+    #[test]
+    fn merged_states_fold_into_the_first_map() {
+        let options = StitchOptions {
+            merge_states: vec![(StateId(2), StateId(1))],
+            ..StitchOptions::side_by_side(Placement::Below)
+        };
+        let world_map = grid_world(4).stitch(&grid_world(4), &options).unwrap();
+        
+        assert_eq!((world_map.metadata.width, world_map.metadata.height), (40, 80));
+        assert_eq!(world_map.states.len(), 7);
+        let lower = world_map.cells.iter().filter(|cell| cell.coordinates.y > 40.0);
+        assert!(lower.clone().any(|cell| cell.state == Some(StateId(2))));
+        assert!(lower.clone().all(|cell| cell.state != Some(StateId(1))));
+        assert!(world_map.validate().is_valid());
+    }
+    
+    #[test]
+    fn overlapping_maps_blend_across_the_band() {
+        let first = grid_world(4);
+        let mut second = grid_world(4);
+        second.cells.iter_mut().for_each(|cell| cell.height = 0.9);
+        second.rebuild_heightmap();
+        let world_map = first.stitch(&second, &StitchOptions::stitched(Placement::Right, 10)).unwrap();
+        
+        assert_eq!(world_map.metadata.width, 70);
+        // The first map's last column gives way to the second map's first
+        assert_eq!(world_map.cells.len(), 28);
+        assert!(world_map.validate().is_valid());
+        let heights: Vec<f32> = (0..70).map(|x| *world_map.heightmap.get(x, 20).unwrap()).collect();
+        assert!((heights[40] - 0.9).abs() < 1e-5);
+        assert!(heights[31] < heights[35] && heights[35] < heights[39]);
+        
+        let error = first.stitch(&second, &StitchOptions::stitched(Placement::Right, 50));
+        assert!(matches!(error, Err(WorldFoundryError::Edit(_))));
+    }
+}
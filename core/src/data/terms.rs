@@ -0,0 +1,53 @@
+//! Helpers for enums of named terms
+
+/// Declares an enum of named terms that (de)serializes as the term's name
+///
+/// Names the engine has no variant for are kept verbatim in an `Other`
+/// variant, so imported data round-trips without loss.
+macro_rules! named_terms {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident => $term:literal),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, ::serde::Serialize, ::serde::Deserialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            Other(String),
+        }
+        
+        impl $name {
+            pub fn name(&self) -> &str {
+                match self {
+                    $($name::$variant => $term,)*
+                    $name::Other(name) => name,
+                }
+            }
+        }
+        
+        impl From<&str> for $name {
+            fn from(name: &str) -> Self {
+                match name {
+                    $($term => $name::$variant,)*
+                    other => $name::Other(other.to_string()),
+                }
+            }
+        }
+        
+        impl From<String> for $name {
+            fn from(name: String) -> Self {
+                $name::from(name.as_str())
+            }
+        }
+        
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.name().to_string()
+            }
+        }
+        
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+    };
+}
\ No newline at end of file
@@ -0,0 +1,65 @@
+//! Small worlds for unit tests
+
+use super::*;
+
+/// World of `size` × `size` cells on a 10-unit grid, with a column of sea on
+/// the west and four states, one per quadrant, each with two burgs
+pub(crate) fn grid_world(size: u32) -> WorldMap {
+    let mut world_map = WorldMap::from_json(include_str!("../../example_world.json")).unwrap();
+    world_map.metadata.width = size * 10;
+    world_map.metadata.height = size * 10;
+    
+    let template = world_map.cells[0].clone();
+    world_map.cells = (0..size * size)
+        .map(|index| {
+            let (x, y) = (index % size, index / size);
+            let sea = x == 0;
+            Cell {
+                id: CellId(index),
+                coordinates: Point2::new(x as f32 * 10.0 + 5.0, y as f32 * 10.0 + 5.0),
+                height: if sea { 0.1 } else { 0.3 + 0.5 * x as f32 / size as f32 },
+                biome: if sea { BiomeType::Marine } else { BiomeType::Temperate },
+                state: (!sea).then(|| StateId(1 + (x >= size / 2) as u32 + 2 * (y >= size / 2) as u32)),
+                ..template.clone()
+            }
+        })
+        .collect();
+    
+    let state = world_map.states[0].clone();
+    world_map.states = (1..=4)
+        .map(|id| State { id: StateId(id), name: format!("State {}", id), capital: BurgId(id), ..state.clone() })
+        .collect();
+    let burg = world_map.burgs[0].clone();
+    world_map.burgs = (1..=8)
+        .map(|id| {
+            let cell = &world_map.cells[((id * 37) % (size * size)) as usize];
+            Settlement {
+                id: BurgId(id),
+                cell: cell.id,
+                x: cell.coordinates.x,
+                y: cell.coordinates.y,
+                state: cell.state.unwrap_or(StateId::NEUTRAL),
+                capital: false,
+                ..burg.clone()
+            }
+        })
+        .collect();
+    world_map.repair();
+    world_map.rebuild_heightmap();
+    world_map
+}
+
+/// File in the temporary directory, removed when dropped
+pub(crate) struct TempFile(pub std::path::PathBuf);
+
+impl TempFile {
+    pub(crate) fn new(extension: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("world-foundry-{}.{}", uuid::Uuid::new_v4(), extension)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
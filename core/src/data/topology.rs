@@ -0,0 +1,383 @@
+//! Cell adjacency and spatial lookup, derived from cell positions
+//!
+//! Cells are the Voronoi regions of their centres, so adjacency is read off the
+//! Delaunay triangulation of the centres and "which cell contains this point" is
+//! a nearest-centre query on an R-tree.
+
+use super::{Cell, CellId, WorldMap};
+use nalgebra::{Point2, Vector2};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use spade::handles::{FaceHandle, PossiblyOuterTag};
+use spade::{DelaunayTriangulation, HasPosition, Triangulation};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Cell centre stored in the spatial index, with the cell's index
+type IndexedCentre = GeomWithData<[f32; 2], usize>;
+
+/// Adjacency graph and spatial index of the cells of a world
+///
+/// Cells are referred to by their index in `WorldMap::cells`, not by id; use
+/// `index_of` to translate ids.
+#[derive(Debug, Clone)]
+pub struct CellTopology {
+    /// Neighbouring cells of every cell
+    pub neighbours: Vec<Vec<usize>>,
+    /// Voronoi edges shared by two neighbouring cells, where they cross the map
+    pub edges: Vec<CellEdge>,
+    /// Cells touching the edge of the map
+    pub border: Vec<bool>,
+    /// Land cells next to water and water cells next to land
+    pub coastline: Vec<bool>,
+    /// Area of every cell in square map units, clipped to the map
+    pub areas: Vec<f32>,
+    /// Triangles of neighbouring cell centres, as cell indices
+    pub triangles: Vec<[usize; 3]>,
+    centres: RTree<IndexedCentre>,
+    indices: HashMap<CellId, usize>,
+}
+
+/// Boundary between two neighbouring cells
+#[derive(Debug, Clone)]
+pub struct CellEdge {
+    pub cells: [usize; 2],
+    /// End points of the boundary, clipped to the map
+    pub vertices: [Point2<f32>; 2],
+}
+
+/// Lazily built topology kept alongside a world
+///
+/// Never serialized; it is rebuilt on first use after loading, and after
+/// `WorldMap::invalidate_topology` marked it stale.
+#[derive(Debug, Default)]
+pub struct TopologyCache {
+    /// Bumped whenever the cells change in a way the topology depends on
+    generation: u64,
+    /// Topology with the generation it was built at
+    built: RwLock<Option<(u64, Arc<CellTopology>)>>,
+}
+
+/// Cell centre inserted in the triangulation
+pub(super) struct Site {
+    pub(super) position: spade::Point2<f64>,
+    /// Index of the cell in `WorldMap::cells`
+    pub(super) index: usize,
+}
+
+impl Site {
+    /// Sites of the cells with a finite position
+    pub(super) fn of_cells(cells: &[Cell]) -> Vec<Site> {
+        cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.coordinates.x.is_finite() && cell.coordinates.y.is_finite())
+            .map(|(index, cell)| Site {
+                position: spade::Point2::new(cell.coordinates.x as f64, cell.coordinates.y as f64),
+                index,
+            })
+            .collect()
+    }
+}
+
+impl HasPosition for Site {
+    type Scalar = f64;
+    
+    fn position(&self) -> spade::Point2<f64> {
+        self.position
+    }
+}
+
+impl CellTopology {
+    /// Build the topology of a set of cells on a map of the given size
+    pub fn build(cells: &[Cell], width: f32, height: f32) -> Self {
+        let sites = Site::of_cells(cells);
+        // Cells without a finite position can be neither triangulated nor indexed
+        let centres = RTree::bulk_load(
+            sites
+                .iter()
+                .map(|site| IndexedCentre::new([site.position.x as f32, site.position.y as f32], site.index))
+                .collect(),
+        );
+        
+        // Cells may lie slightly outside the nominal map size
+        let (mut min, mut max) = (Point2::new(0.0f32, 0.0), Point2::new(width, height));
+        for cell in cells.iter().filter(|cell| cell.coordinates.x.is_finite() && cell.coordinates.y.is_finite()) {
+            min = Point2::new(min.x.min(cell.coordinates.x), min.y.min(cell.coordinates.y));
+            max = Point2::new(max.x.max(cell.coordinates.x), max.y.max(cell.coordinates.y));
+        }
+        
+        let mut neighbours = vec![Vec::new(); cells.len()];
+        let mut edges = Vec::new();
+        let mut border = vec![false; cells.len()];
+        let mut areas = vec![0.0f32; cells.len()];
+        let mut triangles = Vec::new();
+        
+        // Triangulation only fails on non-finite input, which was filtered out above
+        if let Ok(triangulation) = DelaunayTriangulation::<Site>::bulk_load(sites) {
+            triangles = triangulation.inner_faces().map(|face| face.vertices().map(|vertex| vertex.data().index)).collect();
+            for edge in triangulation.undirected_edges() {
+                let directed = edge.as_directed();
+                let [from, to] = [directed.from().data().index, directed.to().data().index];
+                neighbours[from].push(to);
+                neighbours[to].push(from);
+                
+                let [a, b] = directed.positions().map(|p| Point2::new(p.x as f32, p.y as f32));
+                let midpoint = Point2::from((a.coords + b.coords) / 2.0);
+                // Normal pointing towards the face on the left of `from -> to`
+                let left_normal = Vector2::new(a.y - b.y, b.x - a.x);
+                
+                // Unbounded sides of the diagram are cut off well outside the map
+                let reach = (max - min).norm() * 2.0;
+                let far = |origin: Point2<f32>, direction: Vector2<f32>| {
+                    origin + direction.normalize() * (reach + (origin - midpoint).norm())
+                };
+                let left_centre = circumcentre(directed.face());
+                let right_centre = circumcentre(directed.rev().face());
+                let left = left_centre.unwrap_or_else(|| far(right_centre.unwrap_or(midpoint), left_normal));
+                let right = right_centre.unwrap_or_else(|| far(left_centre.unwrap_or(midpoint), -left_normal));
+                
+                let clipped = clip_segment(left, right, min, max);
+                if edge.is_part_of_convex_hull() || clipped != Some([left, right]) {
+                    border[from] = true;
+                    border[to] = true;
+                }
+                let Some([left, right]) = clipped else {
+                    continue;
+                };
+                
+                // Each edge closes a triangle with the centre of both of its cells
+                let wedge = |centre: Point2<f32>| (left - centre).perp(&(right - centre)).abs() / 2.0;
+                areas[from] += wedge(a);
+                areas[to] += wedge(b);
+                edges.push(CellEdge { cells: [from, to], vertices: [left, right] });
+            }
+        }
+        
+        // Border cells are open towards the map edge, so their wedges miss the
+        // part of the cell along it; clip the whole cell to the map instead
+        for index in (0..cells.len()).filter(|&index| border[index]) {
+            let centre = cells[index].coordinates;
+            let others = neighbours[index].iter().map(|&other| cells[other].coordinates);
+            areas[index] = polygon_area(&voronoi_cell(centre, others, min, max));
+        }
+        
+        let coastline = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                neighbours[index].iter().any(|&other| cells[other].biome.is_water() != cell.biome.is_water())
+            })
+            .collect();
+        
+        let indices = cells.iter().enumerate().map(|(index, cell)| (cell.id, index)).collect();
+        
+        Self { neighbours, edges, border, coastline, areas, triangles, centres, indices }
+    }
+    
+    /// Index of the cell with the given id
+    pub fn index_of(&self, id: CellId) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+    
+    /// Cell containing a point, i.e. the cell with the nearest centre
+    pub fn cell_at(&self, point: Point2<f32>) -> Option<usize> {
+        self.centres.nearest_neighbor(&[point.x, point.y]).map(|centre| centre.data)
+    }
+    
+    /// Cells whose centre lies within `radius` of a point, in no particular order
+    pub fn cells_within(&self, point: Point2<f32>, radius: f32) -> Vec<usize> {
+        self.centres
+            .locate_within_distance([point.x, point.y], radius * radius)
+            .map(|centre| centre.data)
+            .collect()
+    }
+    
+    /// The `count` cells nearest to a point, closest first
+    pub fn nearest_cells(&self, point: Point2<f32>, count: usize) -> Vec<usize> {
+        self.centres
+            .nearest_neighbor_iter(&[point.x, point.y])
+            .take(count)
+            .map(|centre| centre.data)
+            .collect()
+    }
+}
+
+impl Clone for TopologyCache {
+    fn clone(&self) -> Self {
+        let built = self.built.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        Self { generation: self.generation, built: RwLock::new(built) }
+    }
+}
+
+impl WorldMap {
+    /// Topology of the cells, built on first use and rebuilt after
+    /// `invalidate_topology`
+    pub fn topology(&self) -> Arc<CellTopology> {
+        let generation = self.topology.generation;
+        if let Some((built, topology)) = self.topology.built.read().unwrap_or_else(|poisoned| poisoned.into_inner()).as_ref() {
+            if *built == generation {
+                return Arc::clone(topology);
+            }
+        }
+        let topology = Arc::new(CellTopology::build(&self.cells, self.metadata.width as f32, self.metadata.height as f32));
+        *self.topology.built.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((generation, Arc::clone(&topology)));
+        topology
+    }
+    
+    /// Mark the topology stale, so the next `topology` call rebuilds it
+    ///
+    /// Needed after cells were added, removed, reordered, renumbered, moved or
+    /// changed biome. Repair does this itself and imports start from a fresh
+    /// cache; code changing `cells` directly must call it.
+    pub fn invalidate_topology(&mut self) {
+        self.topology.generation += 1;
+        *self.topology.built.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+/// Centre of the circumcircle of an inner face, the Voronoi vertex dual to it
+fn circumcentre(face: FaceHandle<'_, PossiblyOuterTag, Site, (), (), ()>) -> Option<Point2<f32>> {
+    face.as_inner().map(|inner| {
+        let centre = inner.circumcenter();
+        Point2::new(centre.x as f32, centre.y as f32)
+    })
+}
+
+/// Polygon of the points of the map rectangle closer to `centre` than to any
+/// of `others` (Sutherland-Hodgman clipping by each bisector)
+fn voronoi_cell(
+    centre: Point2<f32>,
+    others: impl Iterator<Item = Point2<f32>>,
+    min: Point2<f32>,
+    max: Point2<f32>,
+) -> Vec<Point2<f32>> {
+    let mut polygon = vec![min, Point2::new(max.x, min.y), max, Point2::new(min.x, max.y)];
+    for other in others {
+        let midpoint = Point2::from((centre.coords + other.coords) / 2.0);
+        let normal = other - centre;
+        // Positive on the side of `other`
+        let side = |point: &Point2<f32>| (point - midpoint).dot(&normal);
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (index, point) in polygon.iter().enumerate() {
+            let next = &polygon[(index + 1) % polygon.len()];
+            let (here, there) = (side(point), side(next));
+            if here <= 0.0 {
+                clipped.push(*point);
+            }
+            if (here < 0.0 && there > 0.0) || (here > 0.0 && there < 0.0) {
+                clipped.push(point + (next - point) * (here / (here - there)));
+            }
+        }
+        polygon = clipped;
+    }
+    polygon
+}
+
+/// Area of a simple polygon (shoelace formula)
+fn polygon_area(polygon: &[Point2<f32>]) -> f32 {
+    let twice: f32 = polygon
+        .iter()
+        .enumerate()
+        .map(|(index, point)| point.coords.perp(&polygon[(index + 1) % polygon.len()].coords))
+        .sum();
+    twice.abs() / 2.0
+}
+
+/// Part of a segment inside the map rectangle (Liang-Barsky clipping)
+fn clip_segment(start: Point2<f32>, end: Point2<f32>, min: Point2<f32>, max: Point2<f32>) -> Option<[Point2<f32>; 2]> {
+    let delta = end - start;
+    let (mut enter, mut exit) = (0.0f32, 1.0f32);
+    for axis in 0..2 {
+        for (p, q) in [(-delta[axis], start[axis] - min[axis]), (delta[axis], max[axis] - start[axis])] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                enter = enter.max(q / p);
+            } else {
+                exit = exit.min(q / p);
+            }
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+    
+    let clip = |t: f32| if t <= 0.0 { start } else if t >= 1.0 { end } else { start + delta * t };
+    Some([clip(enter), clip(exit)])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::testing::grid_world;
+    use crate::data::{BiomeType, CellId};
+    use nalgebra::Point2;
+    
+    #[test]
+    fn neighbours_and_lookup_follow_the_grid() {
+        let world_map = grid_world(5);
+        let topology = world_map.topology();
+        let mut neighbours = topology.neighbours[12].clone();
+        neighbours.sort();
+        assert!([7, 11, 13, 17].iter().all(|index| neighbours.contains(index)));
+        assert_eq!(topology.cell_at(Point2::new(26.0, 24.0)), Some(12));
+        assert_eq!(topology.index_of(CellId(12)), Some(12));
+        assert!(topology.border[0] && !topology.border[12]);
+    }
+    
+    #[test]
+    fn cells_without_a_position_are_left_out() {
+        let mut world_map = grid_world(4);
+        world_map.cells[5].coordinates = Point2::new(f32::NAN, 3.0);
+        world_map.invalidate_topology();
+        let topology = world_map.topology();
+        assert!(topology.neighbours[5].is_empty());
+        assert_eq!(topology.cells_within(Point2::new(15.0, 15.0), 1.0), Vec::<usize>::new());
+        assert_eq!(topology.cell_at(Point2::new(25.0, 15.0)), Some(6));
+    }
+    
+    #[test]
+    fn adding_or_removing_cells_rebuilds_the_topology() {
+        let mut world_map = grid_world(4);
+        assert_eq!(world_map.topology().areas.len(), 16);
+        world_map.cells.pop();
+        world_map.invalidate_topology();
+        assert_eq!(world_map.topology().areas.len(), 15);
+        assert_eq!(world_map.topology().index_of(CellId(15)), None);
+        
+        let mut cell = world_map.cells[0].clone();
+        cell.id = CellId(99);
+        world_map.cells.push(cell);
+        world_map.invalidate_topology();
+        assert_eq!(world_map.topology().index_of(CellId(99)), Some(15));
+    }
+    
+    #[test]
+    fn cell_areas_cover_the_map() {
+        let world_map = grid_world(5);
+        let topology = world_map.topology();
+        let total: f32 = topology.areas.iter().sum();
+        assert!((total - 50.0 * 50.0).abs() < 1.0, "cells cover {}", total);
+        assert!(topology.areas.iter().all(|area| (area - 100.0).abs() < 0.1));
+    }
+    
+    #[test]
+    fn moving_or_reordering_cells_rebuilds_the_topology() {
+        let mut world_map = grid_world(4);
+        assert_eq!(world_map.topology().index_of(CellId(5)), Some(5));
+        world_map.cells.swap(5, 6);
+        world_map.invalidate_topology();
+        assert_eq!(world_map.topology().index_of(CellId(5)), Some(6));
+        
+        world_map.cells[10].coordinates = Point2::new(1.0, 1.0);
+        world_map.invalidate_topology();
+        assert_eq!(world_map.topology().cell_at(Point2::new(0.0, 0.0)), Some(10));
+        
+        assert!(world_map.topology().coastline[0]);
+        world_map.cells.iter_mut().for_each(|cell| cell.biome = BiomeType::Temperate);
+        world_map.invalidate_topology();
+        assert!(!world_map.topology().coastline[0]);
+    }
+}
\ No newline at end of file
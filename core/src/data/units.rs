@@ -0,0 +1,308 @@
+//! Units of measurement used by map settings
+//!
+//! Names match the values of Azgaar's unit selectors. The map stores lengths
+//! and areas in map units, heights normalized and temperatures in °C; the
+//! settings convert them to the units chosen for display.
+
+use super::{CellId, MapSettings, River, Route, WorldMap};
+
+/// Normalized height of the coastline, Azgaar's height 20
+pub const SEA_LEVEL: f32 = 0.2;
+
+named_terms! {
+    /// Unit of map distances
+    DistanceUnit {
+        Kilometre => "km",
+        Mile => "mi",
+        League => "lg",
+        Verst => "vr",
+        NauticalMile => "nmi",
+        NauticalLeague => "nlg",
+    }
+}
+
+named_terms! {
+    /// Unit of areas
+    AreaUnit {
+        /// The square of the distance unit
+        Square => "square",
+        SquareKilometre => "km²",
+        SquareMile => "mi²",
+        Hectare => "ha",
+        Acre => "acres",
+    }
+}
+
+named_terms! {
+    /// Unit of elevations and depths
+    HeightUnit {
+        Metre => "m",
+        Foot => "ft",
+        Fathom => "f",
+    }
+}
+
+named_terms! {
+    /// Scale temperatures are displayed in
+    TemperatureScale {
+        Celsius => "°C",
+        Fahrenheit => "°F",
+        Kelvin => "K",
+        Rankine => "°R",
+        Delisle => "°De",
+        Newton => "°N",
+        Reaumur => "°Ré",
+        Romer => "°Rø",
+    }
+}
+
+impl DistanceUnit {
+    /// Length of one unit in kilometres; `None` for units the engine does not know
+    pub fn kilometres(&self) -> Option<f64> {
+        match self {
+            DistanceUnit::Kilometre => Some(1.0),
+            DistanceUnit::Mile => Some(1.609344),
+            DistanceUnit::League => Some(4.828032),
+            DistanceUnit::Verst => Some(1.0668),
+            DistanceUnit::NauticalMile => Some(1.852),
+            DistanceUnit::NauticalLeague => Some(5.556),
+            DistanceUnit::Other(_) => None,
+        }
+    }
+}
+
+impl HeightUnit {
+    /// Length of one unit in metres; `None` for units the engine does not know
+    pub fn metres(&self) -> Option<f64> {
+        match self {
+            HeightUnit::Metre => Some(1.0),
+            HeightUnit::Foot => Some(0.3048),
+            HeightUnit::Fathom => Some(1.8288),
+            HeightUnit::Other(_) => None,
+        }
+    }
+}
+
+impl TemperatureScale {
+    /// Temperature on this scale; `None` for scales the engine does not know
+    pub fn from_celsius(&self, celsius: f32) -> Option<f32> {
+        match self {
+            TemperatureScale::Celsius => Some(celsius),
+            TemperatureScale::Fahrenheit => Some(celsius * 9.0 / 5.0 + 32.0),
+            TemperatureScale::Kelvin => Some(celsius + 273.15),
+            TemperatureScale::Rankine => Some((celsius + 273.15) * 9.0 / 5.0),
+            TemperatureScale::Delisle => Some((100.0 - celsius) * 3.0 / 2.0),
+            TemperatureScale::Newton => Some(celsius * 33.0 / 100.0),
+            TemperatureScale::Reaumur => Some(celsius * 4.0 / 5.0),
+            TemperatureScale::Romer => Some(celsius * 21.0 / 40.0 + 7.5),
+            TemperatureScale::Other(_) => None,
+        }
+    }
+    
+    /// Temperature in °C of a value on this scale; `None` for scales the engine does not know
+    pub fn to_celsius(&self, value: f32) -> Option<f32> {
+        match self {
+            TemperatureScale::Celsius => Some(value),
+            TemperatureScale::Fahrenheit => Some((value - 32.0) * 5.0 / 9.0),
+            TemperatureScale::Kelvin => Some(value - 273.15),
+            TemperatureScale::Rankine => Some(value * 5.0 / 9.0 - 273.15),
+            TemperatureScale::Delisle => Some(100.0 - value * 2.0 / 3.0),
+            TemperatureScale::Newton => Some(value * 100.0 / 33.0),
+            TemperatureScale::Reaumur => Some(value * 5.0 / 4.0),
+            TemperatureScale::Romer => Some((value - 7.5) * 40.0 / 21.0),
+            TemperatureScale::Other(_) => None,
+        }
+    }
+}
+
+impl AreaUnit {
+    /// Size of one unit in square kilometres, given the distance unit `Square`
+    /// refers to; `None` for units the engine does not know
+    pub fn square_kilometres(&self, distance_unit: &DistanceUnit) -> Option<f64> {
+        match self {
+            AreaUnit::Square => distance_unit.kilometres().map(|kilometres| kilometres * kilometres),
+            AreaUnit::SquareKilometre => Some(1.0),
+            AreaUnit::SquareMile => Some(2.589988110336),
+            AreaUnit::Hectare => Some(0.01),
+            AreaUnit::Acre => Some(0.0040468564224),
+            AreaUnit::Other(_) => None,
+        }
+    }
+}
+
+impl MapSettings {
+    /// Length in `distance_unit` of a distance measured in map units
+    pub fn length(&self, map_units: f32) -> f32 {
+        map_units * self.distance_scale
+    }
+    
+    /// Area in `area_unit` of a region measured in square map units
+    ///
+    /// Falls back to the square of `distance_unit` when either unit is unknown.
+    pub fn area(&self, square_map_units: f32) -> f32 {
+        let square_distance = square_map_units as f64 * (self.distance_scale as f64).powi(2);
+        let factor = AreaUnit::Square
+            .square_kilometres(&self.distance_unit)
+            .zip(self.area_unit.square_kilometres(&self.distance_unit))
+            .map_or(1.0, |(from, to)| from / to);
+        (square_distance * factor) as f32
+    }
+    
+    /// Elevation in `height_unit` of a normalized height, negative below sea level
+    ///
+    /// Follows Azgaar: land rises as `(height × 100 - 18) ^ height_exponent`
+    /// metres, and the sea deepens steeply towards -990 m. Unknown units give metres.
+    pub fn elevation(&self, height: f32) -> f32 {
+        let azgaar = height as f64 * 100.0;
+        let metres = if azgaar >= SEA_LEVEL as f64 * 100.0 {
+            (azgaar - 18.0).powf(self.height_exponent as f64)
+        } else if azgaar > 0.0 {
+            (azgaar - 20.0) / azgaar * 50.0
+        } else {
+            -990.0
+        };
+        (metres / self.height_unit.metres().unwrap_or(1.0)) as f32
+    }
+    
+    /// Temperature in `temperature_scale` of a value in °C; unknown scales give °C
+    pub fn temperature(&self, celsius: f32) -> f32 {
+        self.temperature_scale.from_celsius(celsius).unwrap_or(celsius)
+    }
+    
+    /// Measure distances in another unit, keeping real distances the same
+    ///
+    /// `distance_scale` is rescaled; it is left alone when either unit is unknown.
+    pub fn set_distance_unit(&mut self, unit: DistanceUnit) {
+        if let (Some(from), Some(to)) = (self.distance_unit.kilometres(), unit.kilometres()) {
+            self.distance_scale = (self.distance_scale as f64 * from / to) as f32;
+        }
+        self.distance_unit = unit;
+    }
+}
+
+impl WorldMap {
+    /// Length of a river in the map's distance unit
+    pub fn river_length(&self, river: &River) -> f32 {
+        self.metadata.settings.length(river.length)
+    }
+    
+    /// Length of a route in the map's distance unit
+    pub fn route_length(&self, route: &Route) -> f32 {
+        self.metadata.settings.length(route.length)
+    }
+    
+    /// Area of a cell in the map's area unit
+    pub fn cell_area(&self, cell: CellId) -> Option<f32> {
+        let topology = self.topology();
+        topology.index_of(cell).map(|index| self.metadata.settings.area(topology.areas[index]))
+    }
+    
+    /// Elevation of a cell in the map's height unit
+    pub fn cell_elevation(&self, cell: CellId) -> Option<f32> {
+        self.cell(cell).map(|cell| self.metadata.settings.elevation(cell.height))
+    }
+    
+    /// Temperature of a cell on the map's temperature scale
+    pub fn cell_temperature(&self, cell: CellId) -> Option<f32> {
+        self.cell(cell).map(|cell| self.metadata.settings.temperature(cell.temperature))
+    }
+    
+    /// Switch the units the map is measured in
+    ///
+    /// Stored values are unit-free, so only the settings change; distances stay
+    /// the same length in the new unit.
+    pub fn set_units(&mut self, distance: DistanceUnit, area: AreaUnit, height: HeightUnit, temperature: TemperatureScale) {
+        let settings = &mut self.metadata.settings;
+        settings.set_distance_unit(distance);
+        settings.area_unit = area;
+        settings.height_unit = height;
+        settings.temperature_scale = temperature;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    fn settings() -> MapSettings {
+        let mut settings = grid_world(2).metadata.settings;
+        settings.distance_unit = DistanceUnit::Kilometre;
+        settings.distance_scale = 2.0;
+        settings.area_unit = AreaUnit::SquareKilometre;
+        settings.height_unit = HeightUnit::Metre;
+        settings.height_exponent = 2.0;
+        settings
+    }
+    
+    #[test]
+    fn temperatures_convert_both_ways() {
+        assert_eq!(TemperatureScale::Fahrenheit.from_celsius(100.0), Some(212.0));
+        assert_eq!(TemperatureScale::Kelvin.to_celsius(273.15), Some(0.0));
+        assert_eq!(TemperatureScale::from("°X").from_celsius(10.0), None);
+        for scale in ["°C", "°F", "K", "°R", "°De", "°N", "°Ré", "°Rø"].map(TemperatureScale::from) {
+            let back = scale.to_celsius(scale.from_celsius(-12.5).unwrap()).unwrap();
+            assert!((back + 12.5).abs() < 1e-3, "{}", scale);
+        }
+        
+        let mut settings = settings();
+        settings.temperature_scale = TemperatureScale::Fahrenheit;
+        assert_eq!(settings.temperature(0.0), 32.0);
+        settings.temperature_scale = TemperatureScale::from("°X");
+        assert_eq!(settings.temperature(21.0), 21.0);
+    }
+    
+    #[test]
+    fn lengths_and_areas_follow_the_distance_scale() {
+        let mut settings = settings();
+        assert_eq!(settings.length(15.0), 30.0);
+        assert!((settings.area(100.0) - 400.0).abs() < 1e-3);
+        settings.area_unit = AreaUnit::Hectare;
+        assert!((settings.area(100.0) - 40_000.0).abs() < 0.1);
+        
+        // The square of a mile is a square mile whatever the area unit is called
+        settings.distance_unit = DistanceUnit::Mile;
+        settings.area_unit = AreaUnit::Square;
+        assert!((settings.area(100.0) - 400.0).abs() < 1e-3);
+        settings.area_unit = AreaUnit::SquareMile;
+        assert!((settings.area(100.0) - 400.0).abs() < 1e-2);
+        settings.area_unit = AreaUnit::from("leagues²");
+        assert!((settings.area(100.0) - 400.0).abs() < 1e-3);
+        
+        settings.distance_unit = DistanceUnit::Kilometre;
+        settings.set_distance_unit(DistanceUnit::Mile);
+        assert!((settings.length(1.609344) - 2.0).abs() < 1e-5);
+        settings.set_distance_unit(DistanceUnit::from("paces"));
+        assert_eq!(settings.distance_unit, DistanceUnit::Other("paces".to_string()));
+        assert!((settings.length(1.609344) - 2.0).abs() < 1e-5);
+    }
+    
+    #[test]
+    fn elevations_rise_from_the_coast_and_fall_to_the_sea_floor() {
+        let mut settings = settings();
+        assert!((settings.elevation(SEA_LEVEL) - 4.0).abs() < 1e-3);
+        assert!((settings.elevation(0.28) - 100.0).abs() < 1e-3);
+        assert!((settings.elevation(0.1) + 50.0).abs() < 1e-3);
+        assert_eq!(settings.elevation(0.0), -990.0);
+        settings.height_unit = HeightUnit::Foot;
+        assert!((settings.elevation(0.28) - 328.084).abs() < 1e-2);
+    }
+    
+    #[test]
+    fn world_metrics_use_the_map_units() {
+        let mut world_map = grid_world(2);
+        world_map.metadata.settings = settings();
+        let cell = world_map.cells[1].clone();
+        assert!((world_map.cell_area(cell.id).unwrap() - 400.0).abs() < 1e-3);
+        assert_eq!(world_map.cell_elevation(cell.id), Some(world_map.metadata.settings.elevation(cell.height)));
+        assert_eq!(world_map.cell_temperature(cell.id), Some(cell.temperature));
+        assert_eq!(world_map.cell_area(CellId(99)), None);
+        
+        world_map.set_units(DistanceUnit::Mile, AreaUnit::Acre, HeightUnit::Fathom, TemperatureScale::Kelvin);
+        let settings = &world_map.metadata.settings;
+        assert!((settings.length(1.0) * 1.609344 - 2.0).abs() < 1e-5);
+        let acre = AreaUnit::Acre.square_kilometres(&settings.distance_unit).unwrap() as f32;
+        assert!((world_map.cell_area(cell.id).unwrap() - 400.0 / acre).abs() < 1.0);
+        assert_eq!(world_map.cell_temperature(cell.id), Some(cell.temperature + 273.15));
+    }
+}
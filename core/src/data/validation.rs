@@ -0,0 +1,505 @@
+//! Referential integrity checks for world maps
+//!
+//! Imported maps often carry ids pointing at entities that no longer exist.
+//! `WorldMap::validate` reports those problems instead of letting them reach the
+//! renderer or the generators.
+
+use super::{BurgId, CellId, CultureId, EventId, FeatureId, MarkerId, ProvinceId, RiverId, RouteId, StateId, WorldMap};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::fmt;
+
+/// Result of validating a world map
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    /// Inconsistent data that can be worked around
+    Warning,
+    /// Data that breaks lookups, such as a dangling id
+    Error,
+}
+
+/// Entity of a world map, identified by its id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntityRef {
+    Cell(CellId),
+    Feature(FeatureId),
+    Culture(CultureId),
+    State(StateId),
+    Province(ProvinceId),
+    Settlement(BurgId),
+    River(RiverId),
+    Route(RouteId),
+    Marker(MarkerId),
+    Event(EventId),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IssueKind {
+    /// Two entities of the same kind share an id
+    DuplicateId { entity: EntityRef },
+    /// A field of `entity` names an entity that does not exist
+    DanglingReference { entity: EntityRef, field: String, target: EntityRef },
+    /// The capital of a state is not one of its settlements
+    CapitalOutsideState { state: StateId, capital: BurgId },
+    /// Two consecutive cells of a river are not neighbours
+    DisconnectedRiver { river: RiverId, from: CellId, to: CellId },
+    /// `State::cells` and `Cell::state` disagree about a cell
+    StateCellMismatch { state: StateId, cell: CellId, cell_state: Option<StateId> },
+    /// The heightmap does not match the map size
+    HeightmapSizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    /// The heightmap holds a different number of values than its size implies
+    HeightmapDataLength { expected: usize, actual: usize },
+    /// An imported note names an element the map does not hold
+    DetachedNote { element: String },
+}
+
+impl ValidationReport {
+    /// True when no errors were found; warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+    
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Error)
+    }
+    
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Warning)
+    }
+    
+    fn error(&mut self, kind: IssueKind) {
+        self.issues.push(ValidationIssue { severity: Severity::Error, kind });
+    }
+    
+    fn warning(&mut self, kind: IssueKind) {
+        self.issues.push(ValidationIssue { severity: Severity::Warning, kind });
+    }
+}
+
+impl WorldMap {
+    /// Check that ids refer to existing entities and that redundant data agrees
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        
+        let cells = collect_ids(&mut report, self.cells.iter().map(|cell| cell.id), EntityRef::Cell);
+        let features = collect_ids(&mut report, self.features.iter().map(|feature| feature.id), EntityRef::Feature);
+        let cultures = collect_ids(&mut report, self.cultures.iter().map(|culture| culture.id), EntityRef::Culture);
+        let states = collect_ids(&mut report, self.states.iter().map(|state| state.id), EntityRef::State);
+        let provinces = collect_ids(&mut report, self.provinces.iter().map(|province| province.id), EntityRef::Province);
+        let burgs = collect_ids(&mut report, self.burgs.iter().map(|burg| burg.id), EntityRef::Settlement);
+        let rivers = collect_ids(&mut report, self.rivers.iter().map(|river| river.id), EntityRef::River);
+        let routes = collect_ids(&mut report, self.routes.iter().map(|route| route.id), EntityRef::Route);
+        let markers = collect_ids(&mut report, self.markers.iter().map(|marker| marker.id), EntityRef::Marker);
+        collect_ids(&mut report, self.events.iter().map(|event| event.id), EntityRef::Event);
+        
+        let mut dangling = |entity: EntityRef, field: &str, target: EntityRef| {
+            report.error(IssueKind::DanglingReference { entity, field: field.to_string(), target });
+        };
+        
+        for cell in &self.cells {
+            let entity = EntityRef::Cell(cell.id);
+            if let Some(culture) = cell.culture.filter(|culture| !cultures.contains(culture)) {
+                dangling(entity, "culture", EntityRef::Culture(culture));
+            }
+            if let Some(state) = cell.state.filter(|state| !states.contains(state)) {
+                dangling(entity, "state", EntityRef::State(state));
+            }
+            if let Some(province) = cell.province.filter(|province| !provinces.contains(province)) {
+                dangling(entity, "province", EntityRef::Province(province));
+            }
+        }
+        
+        for culture in &self.cultures {
+            for &origin in culture.origins.iter().filter(|&&origin| !is_known_or_none(origin, &cultures)) {
+                dangling(EntityRef::Culture(culture.id), "origins", EntityRef::Culture(origin));
+            }
+        }
+        
+        for state in &self.states {
+            let entity = EntityRef::State(state.id);
+            if !is_known_or_none(state.capital, &burgs) {
+                dangling(entity, "capital", EntityRef::Settlement(state.capital));
+            }
+            if !is_known_or_none(state.culture, &cultures) {
+                dangling(entity, "culture", EntityRef::Culture(state.culture));
+            }
+            for &cell in state.cells.iter().filter(|cell| !cells.contains(cell)) {
+                dangling(entity, "cells", EntityRef::Cell(cell));
+            }
+        }
+        
+        for province in &self.provinces {
+            let entity = EntityRef::Province(province.id);
+            if !states.contains(&province.state) {
+                dangling(entity, "state", EntityRef::State(province.state));
+            }
+            if let Some(burg) = province.burg.filter(|burg| !burgs.contains(burg)) {
+                dangling(entity, "burg", EntityRef::Settlement(burg));
+            }
+            for &cell in province.cells.iter().filter(|cell| !cells.contains(cell)) {
+                dangling(entity, "cells", EntityRef::Cell(cell));
+            }
+        }
+        
+        for burg in &self.burgs {
+            let entity = EntityRef::Settlement(burg.id);
+            if !cells.contains(&burg.cell) {
+                dangling(entity, "cell", EntityRef::Cell(burg.cell));
+            }
+            if !is_known_or_none(burg.state, &states) {
+                dangling(entity, "state", EntityRef::State(burg.state));
+            }
+            if !is_known_or_none(burg.culture, &cultures) {
+                dangling(entity, "culture", EntityRef::Culture(burg.culture));
+            }
+            if !is_known_or_none(burg.feature, &features) {
+                dangling(entity, "feature", EntityRef::Feature(burg.feature));
+            }
+        }
+        
+        for river in &self.rivers {
+            let entity = EntityRef::River(river.id);
+            for (field, cell) in [("source", river.source), ("mouth", river.mouth)] {
+                if !cells.contains(&cell) {
+                    dangling(entity, field, EntityRef::Cell(cell));
+                }
+            }
+            for &cell in river.cells.iter().filter(|cell| !cells.contains(cell)) {
+                dangling(entity, "cells", EntityRef::Cell(cell));
+            }
+        }
+        for route in &self.routes {
+            let entity = EntityRef::Route(route.id);
+            if !is_known_or_none(route.feature, &features) {
+                dangling(entity, "feature", EntityRef::Feature(route.feature));
+            }
+            for &cell in route.cells.iter().filter(|cell| !cells.contains(cell)) {
+                dangling(entity, "cells", EntityRef::Cell(cell));
+            }
+        }
+        for marker in self.markers.iter().filter(|marker| !cells.contains(&marker.cell)) {
+            dangling(EntityRef::Marker(marker.id), "cell", EntityRef::Cell(marker.cell));
+        }
+        
+        for event in &self.events {
+            let known = |subject: &EntityRef| match *subject {
+                EntityRef::Cell(id) => cells.contains(&id),
+                EntityRef::Feature(id) => features.contains(&id),
+                EntityRef::Culture(id) => cultures.contains(&id),
+                EntityRef::State(id) => states.contains(&id),
+                EntityRef::Province(id) => provinces.contains(&id),
+                EntityRef::Settlement(id) => burgs.contains(&id),
+                EntityRef::River(id) => rivers.contains(&id),
+                EntityRef::Route(id) => routes.contains(&id),
+                EntityRef::Marker(id) => markers.contains(&id),
+                EntityRef::Event(_) => true,
+            };
+            for &subject in event.subjects.iter().filter(|subject| !known(subject)) {
+                dangling(EntityRef::Event(event.id), "subjects", subject);
+            }
+        }
+        
+        self.check_capitals(&mut report);
+        self.check_rivers(&mut report);
+        self.check_state_cells(&mut report);
+        self.check_heightmap(&mut report);
+        
+        for element in self.metadata.notes.keys() {
+            report.warning(IssueKind::DetachedNote { element: element.clone() });
+        }
+        
+        report
+    }
+    
+    fn check_capitals(&self, report: &mut ValidationReport) {
+        let burg_states: HashMap<BurgId, StateId> = self.burgs.iter().map(|burg| (burg.id, burg.state)).collect();
+        for state in &self.states {
+            if let Some(&burg_state) = burg_states.get(&state.capital) {
+                if burg_state != state.id {
+                    report.warning(IssueKind::CapitalOutsideState { state: state.id, capital: state.capital });
+                }
+            }
+        }
+    }
+    
+    fn check_rivers(&self, report: &mut ValidationReport) {
+        if self.rivers.is_empty() {
+            return;
+        }
+        let topology = self.topology();
+        for river in &self.rivers {
+            for pair in river.cells.windows(2) {
+                let (Some(from), Some(to)) = (topology.index_of(pair[0]), topology.index_of(pair[1])) else {
+                    continue;
+                };
+                if from != to && !topology.neighbours[from].contains(&to) {
+                    report.warning(IssueKind::DisconnectedRiver { river: river.id, from: pair[0], to: pair[1] });
+                }
+            }
+        }
+    }
+    
+    fn check_state_cells(&self, report: &mut ValidationReport) {
+        let cell_states: HashMap<CellId, Option<StateId>> = self.cells.iter().map(|cell| (cell.id, cell.state)).collect();
+        let mut listed: HashSet<(StateId, CellId)> = HashSet::new();
+        
+        for state in &self.states {
+            for &cell in &state.cells {
+                listed.insert((state.id, cell));
+                if let Some(&cell_state) = cell_states.get(&cell) {
+                    if cell_state != Some(state.id) {
+                        report.warning(IssueKind::StateCellMismatch { state: state.id, cell, cell_state });
+                    }
+                }
+            }
+        }
+        
+        // Cells claiming a state that does not list them
+        let states: HashSet<StateId> = self.states.iter().map(|state| state.id).collect();
+        for cell in &self.cells {
+            if let Some(state) = cell.state {
+                if states.contains(&state) && !listed.contains(&(state, cell.id)) {
+                    report.warning(IssueKind::StateCellMismatch { state, cell: cell.id, cell_state: cell.state });
+                }
+            }
+        }
+    }
+    
+    fn check_heightmap(&self, report: &mut ValidationReport) {
+        let heightmap = &self.heightmap;
+        let expected = (self.metadata.width, self.metadata.height);
+        let actual = (heightmap.width, heightmap.height);
+        if expected != actual {
+            report.warning(IssueKind::HeightmapSizeMismatch { expected, actual });
+        }
+        
+        let expected_len = heightmap.width as usize * heightmap.height as usize;
+        if heightmap.data.len() != expected_len {
+            report.error(IssueKind::HeightmapDataLength { expected: expected_len, actual: heightmap.data.len() });
+        }
+    }
+}
+
+/// Ids of a collection, reporting duplicates
+fn collect_ids<T: Copy + Eq + Hash>(
+    report: &mut ValidationReport,
+    ids: impl Iterator<Item = T>,
+    entity: fn(T) -> EntityRef,
+) -> HashSet<T> {
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !seen.insert(id) {
+            report.error(IssueKind::DuplicateId { entity: entity(id) });
+        }
+    }
+    seen
+}
+
+/// Non-optional references use 0 for "none", Azgaar's neutral state and
+/// wildlands, unless an entity with id 0 exists
+fn is_known_or_none<T: Copy + Eq + Hash + Into<u32>>(id: T, ids: &HashSet<T>) -> bool {
+    ids.contains(&id) || id.into() == 0
+}
+
+impl fmt::Display for EntityRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityRef::Cell(id) => write!(f, "cell {}", id),
+            EntityRef::Feature(id) => write!(f, "feature {}", id),
+            EntityRef::Culture(id) => write!(f, "culture {}", id),
+            EntityRef::State(id) => write!(f, "state {}", id),
+            EntityRef::Province(id) => write!(f, "province {}", id),
+            EntityRef::Settlement(id) => write!(f, "settlement {}", id),
+            EntityRef::River(id) => write!(f, "river {}", id),
+            EntityRef::Route(id) => write!(f, "route {}", id),
+            EntityRef::Marker(id) => write!(f, "marker {}", id),
+            EntityRef::Event(id) => write!(f, "event {}", id),
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: ", severity)?;
+        match &self.kind {
+            IssueKind::DuplicateId { entity } => write!(f, "duplicate {}", entity),
+            IssueKind::DanglingReference { entity, field, target } => {
+                write!(f, "{} field `{}` refers to missing {}", entity, field, target)
+            }
+            IssueKind::CapitalOutsideState { state, capital } => {
+                write!(f, "capital {} of state {} belongs to another state", capital, state)
+            }
+            IssueKind::DisconnectedRiver { river, from, to } => {
+                write!(f, "river {} jumps from cell {} to non-adjacent cell {}", river, from, to)
+            }
+            IssueKind::StateCellMismatch { state, cell, cell_state } => match cell_state {
+                Some(owner) if owner == state => write!(f, "cell {} belongs to state {} but is not in its cell list", cell, state),
+                Some(owner) => write!(f, "state {} lists cell {}, which belongs to state {}", state, cell, owner),
+                None => write!(f, "state {} lists cell {}, which belongs to no state", state, cell),
+            },
+            IssueKind::HeightmapSizeMismatch { expected, actual } => write!(
+                f,
+                "heightmap is {}×{} but the map is {}×{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            IssueKind::HeightmapDataLength { expected, actual } => {
+                write!(f, "heightmap holds {} values instead of {}", actual, expected)
+            }
+            IssueKind::DetachedNote { element } => write!(f, "note for `{}` is not attached to any entity", element),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{Feature, FeatureType, Note, River, RiverType};
+    
+    fn dangling(report: &ValidationReport) -> Vec<(EntityRef, &str, EntityRef)> {
+        report
+            .issues
+            .iter()
+            .filter_map(|issue| match &issue.kind {
+                IssueKind::DanglingReference { entity, field, target } => Some((*entity, field.as_str(), *target)),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    fn river(cells: Vec<CellId>) -> River {
+        River {
+            id: RiverId(1),
+            source: cells[0],
+            mouth: cells[cells.len() - 1],
+            discharge: 1.0,
+            length: 10.0,
+            width: 1.0,
+            cells,
+            basin: RiverId(1),
+            name: "Stray".to_string(),
+            type_: RiverType::River,
+            note: None,
+            attributes: Default::default(),
+        }
+    }
+    
+    fn has(report: &ValidationReport, severity: Severity, kind: IssueKind) -> bool {
+        report.issues.contains(&ValidationIssue { severity, kind })
+    }
+    
+    #[test]
+    fn duplicate_features_are_reported() {
+        let mut world_map = grid_world(4);
+        let feature = Feature {
+            id: FeatureId(2),
+            name: "Isle".to_string(),
+            feature_type: FeatureType::Island,
+            cells: vec![CellId(5)],
+            group: None,
+            note: None,
+            attributes: Default::default(),
+        };
+        world_map.features = vec![feature.clone(), feature];
+        
+        let duplicate = IssueKind::DuplicateId { entity: EntityRef::Feature(FeatureId(2)) };
+        assert!(world_map.validate().errors().any(|issue| issue.kind == duplicate));
+    }
+    
+    #[test]
+    fn every_reference_is_checked() {
+        let mut world_map = grid_world(4);
+        assert!(dangling(&world_map.validate()).is_empty());
+        
+        world_map.rivers.push(River { source: CellId(90), mouth: CellId(91), ..river(vec![CellId(5), CellId(6)]) });
+        world_map.burgs[0].feature = FeatureId(7);
+        world_map.cultures[0].origins = vec![CultureId(0), CultureId(3)];
+        
+        let report = world_map.validate();
+        assert!(!report.is_valid());
+        assert_eq!(dangling(&report), vec![
+            (EntityRef::Culture(CultureId(0)), "origins", EntityRef::Culture(CultureId(3))),
+            (EntityRef::Settlement(BurgId(1)), "feature", EntityRef::Feature(FeatureId(7))),
+            (EntityRef::River(RiverId(1)), "source", EntityRef::Cell(CellId(90))),
+            (EntityRef::River(RiverId(1)), "mouth", EntityRef::Cell(CellId(91))),
+        ]);
+    }
+    
+    #[test]
+    fn capitals_held_by_another_state_are_reported() {
+        let mut world_map = grid_world(4);
+        let capital = world_map.burgs.iter().find(|burg| burg.state == StateId(2)).unwrap().id;
+        let issue = IssueKind::CapitalOutsideState { state: StateId(1), capital };
+        assert!(!has(&world_map.validate(), Severity::Warning, issue.clone()));
+        
+        world_map.states[0].capital = capital;
+        assert!(has(&world_map.validate(), Severity::Warning, issue));
+    }
+    
+    #[test]
+    fn rivers_jumping_between_distant_cells_are_reported() {
+        let mut world_map = grid_world(4);
+        world_map.rivers.push(river(vec![CellId(5), CellId(6), CellId(15)]));
+        
+        let report = world_map.validate();
+        let disconnected = report.issues.iter().filter(|issue| matches!(issue.kind, IssueKind::DisconnectedRiver { .. }));
+        assert_eq!(disconnected.count(), 1);
+        let jump = IssueKind::DisconnectedRiver { river: RiverId(1), from: CellId(6), to: CellId(15) };
+        assert!(has(&report, Severity::Warning, jump));
+    }
+    
+    #[test]
+    fn state_cell_lists_disagreeing_with_cells_are_reported() {
+        let mut world_map = grid_world(4);
+        world_map.states[0].cells.retain(|&cell| cell != CellId(5));
+        world_map.states[0].cells.push(CellId(6));
+        
+        let report = world_map.validate();
+        let unlisted = IssueKind::StateCellMismatch { state: StateId(1), cell: CellId(5), cell_state: Some(StateId(1)) };
+        let foreign = IssueKind::StateCellMismatch { state: StateId(1), cell: CellId(6), cell_state: Some(StateId(2)) };
+        assert!(has(&report, Severity::Warning, unlisted));
+        assert!(has(&report, Severity::Warning, foreign));
+    }
+    
+    #[test]
+    fn heightmaps_of_the_wrong_size_are_reported() {
+        let mut world_map = grid_world(4);
+        world_map.metadata.width = 50;
+        world_map.heightmap.data.pop();
+        
+        let report = world_map.validate();
+        assert!(has(&report, Severity::Warning, IssueKind::HeightmapSizeMismatch { expected: (50, 40), actual: (40, 40) }));
+        assert!(has(&report, Severity::Error, IssueKind::HeightmapDataLength { expected: 1600, actual: 1599 }));
+    }
+    
+    #[test]
+    fn detached_notes_are_reported() {
+        let mut world_map = grid_world(4);
+        let note = Note { title: "Lost".to_string(), legend: String::new() };
+        world_map.metadata.notes.insert("regiment3".to_string(), note);
+        
+        let report = world_map.validate();
+        assert!(report.is_valid());
+        assert!(has(&report, Severity::Warning, IssueKind::DetachedNote { element: "regiment3".to_string() }));
+    }
+}
@@ -0,0 +1,223 @@
+//! Notifications of what an edit changed
+//!
+//! Every call that changes the world through an `EditHistory` publishes the
+//! changes to the history's subscribers, so views can refresh only what moved
+//! instead of re-reading the whole map. Cell changes come as ranges of indices
+//! into `WorldMap::cells`, heightmap changes as rectangles of grid points.
+//!
+//! Changes made to the world directly, such as `WorldMap::repair` or
+//! stitching, are not published; announce them with `EditHistory::notify`.
+
+use super::EditCommand;
+use crate::{CellId, EntityChange, EntityRef, HeightmapChange, MapDiff, StateId, WorldMap};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// One thing about a world that changed
+///
+/// Only changes made through an `EditHistory` are sent on their own; direct
+/// mutations of the world, such as repair or stitching, send none.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WorldChange {
+    EntityAdded(EntityRef),
+    EntityRemoved(EntityRef),
+    /// An entity other than a cell changed; changed cells come as `CellsChanged`
+    EntityChanged(EntityRef),
+    /// Cells at these indices of `WorldMap::cells` changed; adding or removing
+    /// cells shifts the ones after them, so the range then runs to the end of
+    /// the list, and is empty if only the last cells were removed
+    CellsChanged(Range<usize>),
+    /// Heightmap values in a rectangle of grid points changed, or no longer
+    /// match the cell heights and need rasterizing again
+    HeightmapDirty { x: u32, y: u32, width: u32, height: u32 },
+    /// A part of the world without ids changed, such as "diplomacy" or "metadata"
+    SectionChanged(String),
+}
+
+impl WorldChange {
+    /// Changes listed in a diff from `old` to `new`, with cell indices into
+    /// the new map
+    ///
+    /// For announcing changes made outside the history, such as regenerating
+    /// or merging, through `EditHistory::notify`.
+    pub fn from_diff(diff: &MapDiff, old: &WorldMap, new: &WorldMap) -> Vec<WorldChange> {
+        let (old_topology, topology) = (old.topology(), new.topology());
+        let mut changes = Vec::new();
+        if !diff.metadata.is_empty() {
+            changes.push(WorldChange::SectionChanged("metadata".to_string()));
+        }
+        let mut cells = Vec::new();
+        // First index moved by cells being added or removed
+        let mut shifted: Option<usize> = None;
+        for change in &diff.entities {
+            match change {
+                EntityChange::Added { entity: EntityRef::Cell(cell) } => {
+                    shifted = shifted.into_iter().chain(topology.index_of(*cell)).min();
+                }
+                EntityChange::Removed { entity: EntityRef::Cell(cell) } => {
+                    shifted = shifted.into_iter().chain(old_topology.index_of(*cell)).min();
+                }
+                EntityChange::Added { entity } => changes.push(WorldChange::EntityAdded(*entity)),
+                EntityChange::Removed { entity } => changes.push(WorldChange::EntityRemoved(*entity)),
+                EntityChange::Changed { entity, .. } => changes.push(WorldChange::EntityChanged(*entity)),
+            }
+        }
+        cells.extend(diff.cells.iter().filter_map(|change| topology.index_of(change.cell)));
+        let end = new.cells.len();
+        cells.extend(shifted.map_or(end..end, |start| start..end));
+        changes.extend(cell_ranges(cells).into_iter().map(WorldChange::CellsChanged));
+        if shifted.is_some_and(|start| start >= end) {
+            changes.push(WorldChange::CellsChanged(end..end));
+        }
+        for change in &diff.heightmap {
+            changes.push(match change {
+                HeightmapChange::Resized { to: (width, height), .. } => {
+                    WorldChange::HeightmapDirty { x: 0, y: 0, width: *width, height: *height }
+                }
+                HeightmapChange::Region(region) => WorldChange::HeightmapDirty {
+                    x: region.x,
+                    y: region.y,
+                    width: region.width,
+                    height: region.height,
+                },
+            });
+        }
+        changes.extend(diff.sections.iter().cloned().map(WorldChange::SectionChanged));
+        changes
+    }
+}
+
+/// Changes made by applying `command`, given the inverse it returned
+pub(super) fn command_changes(world_map: &WorldMap, command: &EditCommand, inverse: &EditCommand) -> Vec<WorldChange> {
+    match (command, inverse) {
+        (EditCommand::MoveBurg { burg, .. }, _) => vec![WorldChange::EntityChanged(EntityRef::Settlement(*burg))],
+        (EditCommand::RenameState { state, .. }, _) => vec![WorldChange::EntityChanged(EntityRef::State(*state))],
+        (EditCommand::PaintHeights { heights }, _) => {
+            let indices = indices_of(world_map, heights.iter().map(|(cell, _)| *cell));
+            let mut changes: Vec<WorldChange> = cell_ranges(indices.clone()).into_iter().map(WorldChange::CellsChanged).collect();
+            changes.extend(heightmap_extent(world_map, &indices));
+            changes
+        }
+        (EditCommand::AssignCells { owners }, EditCommand::AssignCells { owners: previous }) => {
+            let indices = indices_of(world_map, owners.iter().map(|(cell, _)| *cell));
+            let mut states: Vec<StateId> = owners.iter().chain(previous).filter_map(|(_, owner)| *owner).collect();
+            states.sort();
+            states.dedup();
+            let mut changes: Vec<WorldChange> = cell_ranges(indices).into_iter().map(WorldChange::CellsChanged).collect();
+            changes.extend(states.into_iter().map(|state| WorldChange::EntityChanged(EntityRef::State(state))));
+            changes
+        }
+        (EditCommand::AssignCells { .. }, _) => Vec::new(),
+    }
+}
+
+fn indices_of(world_map: &WorldMap, ids: impl Iterator<Item = CellId>) -> Vec<usize> {
+    let topology = world_map.topology();
+    ids.filter_map(|id| topology.index_of(id)).collect()
+}
+
+/// Runs of consecutive indices, in order
+fn cell_ranges(mut indices: Vec<usize>) -> Vec<Range<usize>> {
+    indices.sort_unstable();
+    indices.dedup();
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for index in indices {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+/// Heightmap rectangle covering cells and the neighbours their area reaches
+fn heightmap_extent(world_map: &WorldMap, indices: &[usize]) -> Option<WorldChange> {
+    world_map
+        .heightmap_extent(indices)
+        .map(|(x, y, width, height)| WorldChange::HeightmapDirty { x, y, width, height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::BurgId;
+    
+    #[test]
+    fn cell_ranges_join_consecutive_indices() {
+        assert_eq!(cell_ranges(vec![7, 3, 4, 5, 4, 9, 8]), vec![3..6, 7..10]);
+        assert_eq!(cell_ranges(Vec::new()), Vec::<Range<usize>>::new());
+    }
+    
+    #[test]
+    fn painting_a_border_cell_reports_every_changed_height() {
+        let mut world_map = grid_world(8);
+        let before = world_map.heightmap.clone();
+        let command = EditCommand::PaintHeights { heights: vec![(CellId(56), 0.9)] };
+        let inverse = command.apply(&mut world_map).unwrap();
+        let changes = command_changes(&world_map, &command, &inverse);
+        
+        assert_eq!(changes[0], WorldChange::CellsChanged(56..57));
+        let Some(&WorldChange::HeightmapDirty { x, y, width, height }) = changes.get(1) else {
+            panic!("no heightmap change in {:?}", changes);
+        };
+        // The corner cell reaches both edges of the map
+        assert_eq!((x, y + height), (0, world_map.heightmap.height));
+        let grid_width = world_map.heightmap.width;
+        for (index, (old, new)) in before.data.iter().zip(&world_map.heightmap.data).enumerate() {
+            let (px, py) = (index as u32 % grid_width, index as u32 / grid_width);
+            if old != new {
+                assert!(px >= x && px < x + width && py >= y && py < y + height, "({}, {}) changed outside the rectangle", px, py);
+            }
+        }
+    }
+    
+    #[test]
+    fn assigning_cells_reports_old_and_new_owners() {
+        let mut world_map = grid_world(6);
+        let command = EditCommand::AssignCells { owners: vec![(CellId(7), Some(StateId(4))), (CellId(8), None)] };
+        let inverse = command.apply(&mut world_map).unwrap();
+        assert_eq!(
+            command_changes(&world_map, &command, &inverse),
+            vec![
+                WorldChange::CellsChanged(7..9),
+                WorldChange::EntityChanged(EntityRef::State(StateId(1))),
+                WorldChange::EntityChanged(EntityRef::State(StateId(4))),
+            ]
+        );
+    }
+    
+    #[test]
+    fn changes_follow_a_diff() {
+        let old = grid_world(6);
+        let mut new = old.clone();
+        new.states[1].name = "Tor".to_string();
+        new.cells[10].height = 0.9;
+        new.cells[11].height = 0.9;
+        let mut burg = new.burgs[0].clone();
+        burg.id = BurgId(20);
+        new.burgs.push(burg);
+        
+        let changes = WorldChange::from_diff(&old.diff(&new).unwrap(), &old, &new);
+        assert!(changes.contains(&WorldChange::EntityChanged(EntityRef::State(StateId(2)))));
+        assert!(changes.contains(&WorldChange::EntityAdded(EntityRef::Settlement(BurgId(20)))));
+        assert!(changes.contains(&WorldChange::CellsChanged(10..12)));
+    }
+    
+    #[test]
+    fn removed_cells_shift_the_cells_after_them() {
+        let old = grid_world(6);
+        let mut new = old.clone();
+        new.cells.remove(30);
+        new.invalidate_topology();
+        let changes = WorldChange::from_diff(&old.diff(&new).unwrap(), &old, &new);
+        assert!(changes.contains(&WorldChange::CellsChanged(30..35)));
+        assert!(!changes.contains(&WorldChange::EntityRemoved(EntityRef::Cell(CellId(30)))));
+        
+        let mut new = old.clone();
+        new.cells.pop();
+        new.invalidate_topology();
+        let changes = WorldChange::from_diff(&old.diff(&new).unwrap(), &old, &new);
+        assert!(changes.contains(&WorldChange::CellsChanged(35..35)));
+    }
+}
@@ -0,0 +1,494 @@
+//! Reversible map edits with undo/redo history
+//!
+//! Editors change a world only through `EditCommand`s executed by an
+//! `EditHistory`. Applying a command yields its inverse, which the history keeps
+//! so the edit can be undone, and commands can be grouped into transactions
+//! that undo as a single step. Subscribers of a history hear of every change
+//! it makes, see `WorldChange`.
+
+mod changes;
+
+pub use changes::*;
+
+use crate::{BurgId, CellId, Result, StateId, WorldFoundryError, WorldMap};
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Default number of undo steps kept by a history
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A single reversible change to a world map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EditCommand {
+    /// Move a burg; its cell is looked up from the new position unless given
+    MoveBurg { burg: BurgId, position: Point2<f32>, cell: Option<CellId> },
+    RenameState { state: StateId, name: String },
+    /// Set the height of cells, as (cell, height) pairs, and the heightmap
+    /// around them
+    PaintHeights { heights: Vec<(CellId, f32)> },
+    /// Hand cells to a state, or to no state, as (cell, owner) pairs
+    AssignCells { owners: Vec<(CellId, Option<StateId>)> },
+}
+
+/// Commands executed together and undone as one step
+#[derive(Debug, Clone)]
+struct Transaction {
+    label: String,
+    edits: Edits,
+}
+
+/// Commands, each with the inverse produced when it was applied
+type Edits = Vec<(EditCommand, EditCommand)>;
+
+/// Undo and redo stacks of the edits made to one world
+#[derive(Debug, Clone)]
+pub struct EditHistory {
+    /// Oldest steps first, so the front is dropped past the limit
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    /// Nesting depth of `begin` calls on the open transaction
+    depth: usize,
+    limit: usize,
+    /// Channels receiving the changes of each call; closed ones are dropped
+    subscribers: Vec<Sender<Vec<WorldChange>>>,
+}
+
+impl EditCommand {
+    /// Short human-readable description, for undo menus
+    pub fn label(&self) -> String {
+        match self {
+            EditCommand::MoveBurg { burg, .. } => format!("Move burg {}", burg),
+            EditCommand::RenameState { name, .. } => format!("Rename state to {}", name),
+            EditCommand::PaintHeights { heights } => format!("Paint height of {} cells", heights.len()),
+            EditCommand::AssignCells { owners } => format!("Reassign {} cells", owners.len()),
+        }
+    }
+    
+    /// Apply the command and return the command that reverts it
+    ///
+    /// The world is left untouched when the command refers to missing entities.
+    pub fn apply(&self, world_map: &mut WorldMap) -> Result<EditCommand> {
+        match self {
+            EditCommand::MoveBurg { burg, position, cell } => {
+                let cell = match cell {
+                    Some(cell) => {
+                        world_map.topology().index_of(*cell).ok_or_else(|| missing("cell", *cell))?;
+                        *cell
+                    }
+                    None => {
+                        let index = world_map.topology()
+                            .cell_at(*position)
+                            .ok_or_else(|| WorldFoundryError::Edit("map has no cells".to_string()))?;
+                        world_map.cells[index].id
+                    }
+                };
+                let target = world_map
+                    .burg_mut(*burg)
+                    .ok_or_else(|| missing("burg", *burg))?;
+                
+                let inverse = EditCommand::MoveBurg {
+                    burg: *burg,
+                    position: Point2::new(target.x, target.y),
+                    cell: Some(target.cell),
+                };
+                target.x = position.x;
+                target.y = position.y;
+                target.cell = cell;
+                Ok(inverse)
+            }
+            EditCommand::RenameState { state, name } => {
+                let target = world_map
+                    .state_mut(*state)
+                    .ok_or_else(|| missing("state", *state))?;
+                let previous = std::mem::replace(&mut target.name, name.clone());
+                Ok(EditCommand::RenameState { state: *state, name: previous })
+            }
+            EditCommand::PaintHeights { heights } => {
+                let indices = cell_indices(world_map, heights.iter().map(|(cell, _)| *cell))?;
+                let mut previous = Vec::with_capacity(heights.len());
+                for (&index, &(cell, height)) in indices.iter().zip(heights) {
+                    previous.push((cell, world_map.cells[index].height));
+                    world_map.cells[index].height = height;
+                }
+                world_map.refresh_heightmap(&indices);
+                // Undo restores in reverse so repeated cells end at their first value
+                previous.reverse();
+                Ok(EditCommand::PaintHeights { heights: previous })
+            }
+            EditCommand::AssignCells { owners } => {
+                let indices = cell_indices(world_map, owners.iter().map(|(cell, _)| *cell))?;
+                if let Some(state) = owners
+                    .iter()
+                    .filter_map(|(_, owner)| *owner)
+                    .find(|&owner| world_map.state(owner).is_none())
+                {
+                    return Err(missing("state", state));
+                }
+                
+                let mut previous = Vec::with_capacity(owners.len());
+                for (&index, &(cell, owner)) in indices.iter().zip(owners) {
+                    let old = world_map.cells[index].state;
+                    previous.push((cell, old));
+                    if old == owner {
+                        continue;
+                    }
+                    world_map.cells[index].state = owner;
+                    for state in world_map.states.iter_mut() {
+                        if Some(state.id) == old {
+                            state.cells.retain(|&other| other != cell);
+                        } else if Some(state.id) == owner {
+                            state.cells.push(cell);
+                        }
+                    }
+                }
+                previous.reverse();
+                Ok(EditCommand::AssignCells { owners: previous })
+            }
+        }
+    }
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self::with_limit(DEFAULT_HISTORY_LIMIT)
+    }
+    
+    /// History keeping at most `limit` undo steps
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            depth: 0,
+            limit: limit.max(1),
+            subscribers: Vec::new(),
+        }
+    }
+    
+    /// Receive the changes of every later execute, rollback, undo and redo,
+    /// one message per call
+    pub fn subscribe(&mut self) -> Receiver<Vec<WorldChange>> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+    
+    /// Pass changes made outside the history on to its subscribers
+    pub fn notify(&mut self, changes: Vec<WorldChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.subscribers.retain(|subscriber| subscriber.send(changes.clone()).is_ok());
+    }
+    
+    /// Apply a command and record it, in the open transaction if there is one
+    pub fn execute(&mut self, world_map: &mut WorldMap, command: EditCommand) -> Result<()> {
+        let inverse = command.apply(world_map)?;
+        self.redo.clear();
+        self.notify(command_changes(world_map, &command, &inverse));
+        
+        match self.open.as_mut() {
+            Some(transaction) => transaction.edits.push((command, inverse)),
+            None => {
+                let label = command.label();
+                self.push_undo(Transaction { label, edits: vec![(command, inverse)] });
+            }
+        }
+        Ok(())
+    }
+    
+    /// Start grouping commands into one undo step; calls may be nested
+    pub fn begin(&mut self, label: &str) {
+        if self.open.is_none() {
+            self.open = Some(Transaction { label: label.to_string(), edits: Vec::new() });
+        }
+        self.depth += 1;
+    }
+    
+    /// Close the innermost transaction; the outermost one becomes an undo step
+    pub fn commit(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+        self.depth -= 1;
+        if self.depth == 0 {
+            if let Some(transaction) = self.open.take().filter(|transaction| !transaction.edits.is_empty()) {
+                self.push_undo(transaction);
+            }
+        }
+    }
+    
+    /// Revert every command of the open transaction and discard it
+    ///
+    /// On error the world and the open transaction are left as they were.
+    pub fn rollback(&mut self, world_map: &mut WorldMap) -> Result<()> {
+        let Some(transaction) = self.open.as_ref() else {
+            self.depth = 0;
+            return Ok(());
+        };
+        let (_, changes) = apply_all(world_map, transaction.edits.iter().rev().map(|(_, inverse)| inverse))?;
+        self.open = None;
+        self.depth = 0;
+        self.notify(changes);
+        Ok(())
+    }
+    
+    /// Revert the last undo step; returns false when there is nothing to undo
+    ///
+    /// On error the world and both stacks are left as they were.
+    pub fn undo(&mut self, world_map: &mut WorldMap) -> Result<bool> {
+        self.commit_all();
+        let Some(transaction) = self.undo.back() else {
+            return Ok(false);
+        };
+        
+        let (mut edits, changes) = apply_all(world_map, transaction.edits.iter().rev().map(|(_, inverse)| inverse))?;
+        // Reverting an inverse gives back the command, so the pairs swap
+        edits.iter_mut().for_each(|(inverse, command)| std::mem::swap(inverse, command));
+        edits.reverse();
+        let transaction = self.undo.pop_back().unwrap();
+        self.notify(changes);
+        self.redo.push(Transaction { label: transaction.label, edits });
+        Ok(true)
+    }
+    
+    /// Re-apply the last undone step; returns false when there is nothing to redo
+    ///
+    /// On error the world and both stacks are left as they were.
+    pub fn redo(&mut self, world_map: &mut WorldMap) -> Result<bool> {
+        let Some(transaction) = self.redo.last() else {
+            return Ok(false);
+        };
+        
+        let (edits, changes) = apply_all(world_map, transaction.edits.iter().map(|(command, _)| command))?;
+        let transaction = self.redo.pop().unwrap();
+        self.notify(changes);
+        self.push_undo(Transaction { label: transaction.label, edits });
+        Ok(true)
+    }
+    
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|transaction| !transaction.edits.is_empty())
+    }
+    
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    
+    /// Label of the step `undo` would revert
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo.back().map(|transaction| transaction.label.as_str())
+    }
+    
+    /// Label of the step `redo` would re-apply
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last().map(|transaction| transaction.label.as_str())
+    }
+    
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.depth = 0;
+    }
+    
+    fn push_undo(&mut self, transaction: Transaction) {
+        self.undo.push_back(transaction);
+        if self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+    
+    /// Undoing in the middle of a transaction closes it first
+    fn commit_all(&mut self) {
+        while self.depth > 0 {
+            self.commit();
+        }
+    }
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Apply commands in order, returning each with its inverse and the changes
+/// they made
+///
+/// When one fails, the ones already applied are reverted before returning the
+/// error, so a step is never left half done. Should reverting fail as well,
+/// both errors are returned together, as the world may then be half edited.
+fn apply_all<'a>(
+    world_map: &mut WorldMap,
+    commands: impl Iterator<Item = &'a EditCommand>,
+) -> Result<(Edits, Vec<WorldChange>)> {
+    let mut edits: Edits = Vec::new();
+    let mut changes = Vec::new();
+    for command in commands {
+        match command.apply(world_map) {
+            Ok(inverse) => {
+                changes.extend(command_changes(world_map, command, &inverse));
+                edits.push((command.clone(), inverse));
+            }
+            Err(error) => {
+                for (_, inverse) in edits.iter().rev() {
+                    // Inverses only refer to what their command just found, so
+                    // this only fails if a command is not reversible as promised
+                    if let Err(revert_error) = inverse.apply(world_map) {
+                        return Err(WorldFoundryError::Edit(format!(
+                            "{}; reverting the commands already applied failed too: {}",
+                            error, revert_error
+                        )));
+                    }
+                }
+                return Err(error);
+            }
+        }
+    }
+    Ok((edits, changes))
+}
+
+fn missing(kind: &str, id: impl Into<u32>) -> WorldFoundryError {
+    WorldFoundryError::Edit(format!("{} {} does not exist", kind, id.into()))
+}
+
+/// Indices of the given cell ids, failing on the first unknown id
+fn cell_indices(world_map: &WorldMap, ids: impl Iterator<Item = CellId>) -> Result<Vec<usize>> {
+    let topology = world_map.topology();
+    ids.map(|id| topology.index_of(id).ok_or_else(|| missing("cell", id))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    fn heights(world_map: &WorldMap) -> Vec<f32> {
+        world_map.cells.iter().map(|cell| cell.height).collect()
+    }
+    
+    fn paint(cell: u32, height: f32) -> EditCommand {
+        EditCommand::PaintHeights { heights: vec![(CellId(cell), height)] }
+    }
+    
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut world_map = grid_world(6);
+        let original = world_map.clone();
+        let mut history = EditHistory::new();
+        history.execute(&mut world_map, EditCommand::RenameState { state: StateId(1), name: "Avar".to_string() }).unwrap();
+        history.execute(&mut world_map, paint(14, 0.9)).unwrap();
+        let edited = world_map.clone();
+        
+        assert_eq!(history.undo_label(), Some("Paint height of 1 cells"));
+        assert!(history.undo(&mut world_map).unwrap());
+        assert!(history.undo(&mut world_map).unwrap());
+        assert!(!history.undo(&mut world_map).unwrap());
+        assert_eq!(world_map.state(StateId(1)).unwrap().name, original.state(StateId(1)).unwrap().name);
+        assert_eq!(heights(&world_map), heights(&original));
+        assert_eq!(world_map.heightmap.data, original.heightmap.data);
+        
+        assert!(history.redo(&mut world_map).unwrap());
+        assert!(history.redo(&mut world_map).unwrap());
+        assert!(!history.can_redo());
+        assert_eq!(world_map.state(StateId(1)).unwrap().name, "Avar");
+        assert_eq!(heights(&world_map), heights(&edited));
+        assert_eq!(world_map.heightmap.data, edited.heightmap.data);
+    }
+    
+    #[test]
+    fn transactions_undo_as_one_step() {
+        let mut world_map = grid_world(6);
+        let original = world_map.clone();
+        let mut history = EditHistory::new();
+        history.begin("Raise hills");
+        history.execute(&mut world_map, paint(14, 0.9)).unwrap();
+        history.execute(&mut world_map, paint(14, 0.7)).unwrap();
+        history.execute(&mut world_map, paint(15, 0.8)).unwrap();
+        history.commit();
+        
+        assert_eq!(history.undo_label(), Some("Raise hills"));
+        history.undo(&mut world_map).unwrap();
+        assert_eq!(heights(&world_map), heights(&original));
+        assert!(!history.can_undo());
+        
+        history.begin("Discarded");
+        history.execute(&mut world_map, paint(20, 0.9)).unwrap();
+        history.rollback(&mut world_map).unwrap();
+        assert_eq!(heights(&world_map), heights(&original));
+        assert_eq!(world_map.heightmap.data, original.heightmap.data);
+        assert!(!history.can_undo());
+    }
+    
+    #[test]
+    fn failed_undo_leaves_world_and_history_unchanged() {
+        let mut world_map = grid_world(6);
+        let mut history = EditHistory::new();
+        history.begin("Rename and paint");
+        history.execute(&mut world_map, EditCommand::RenameState { state: StateId(2), name: "Tor".to_string() }).unwrap();
+        history.execute(&mut world_map, paint(14, 0.9)).unwrap();
+        history.commit();
+        
+        // The renamed state disappears, so its rename can no longer be undone
+        let removed = world_map.states.iter().position(|state| state.id == StateId(2)).unwrap();
+        let state = world_map.states.remove(removed);
+        let before = world_map.clone();
+        assert!(history.undo(&mut world_map).is_err());
+        assert_eq!(heights(&world_map), heights(&before));
+        assert_eq!(world_map.heightmap.data, before.heightmap.data);
+        assert_eq!(history.undo_label(), Some("Rename and paint"));
+        assert!(!history.can_redo());
+        
+        world_map.states.insert(removed, state);
+        assert!(history.undo(&mut world_map).unwrap());
+        assert_ne!(world_map.state(StateId(2)).unwrap().name, "Tor");
+    }
+    
+    #[test]
+    fn painting_heights_updates_the_heightmap() {
+        let mut world_map = grid_world(8);
+        // Break the grid's symmetry so the triangulation has one answer
+        for cell in world_map.cells.iter_mut() {
+            let jitter = (cell.id.0 * 7 % 5) as f32 - 2.0;
+            cell.coordinates.x += jitter * 0.4;
+            cell.coordinates.y -= jitter * 0.3;
+        }
+        world_map.rebuild_heightmap();
+        
+        let command = EditCommand::PaintHeights {
+            heights: vec![(CellId(27), 0.95), (CellId(28), 0.05), (CellId(7), 0.6), (CellId(63), 0.2)],
+        };
+        command.apply(&mut world_map).unwrap();
+        let expected = world_map.rasterize_heights(world_map.heightmap.width, world_map.heightmap.height);
+        assert_eq!(world_map.heightmap.data, expected.data);
+    }
+    
+    #[test]
+    fn moving_a_burg_checks_its_cell() {
+        let mut world_map = grid_world(6);
+        let command = EditCommand::MoveBurg { burg: BurgId(1), position: Point2::new(12.0, 12.0), cell: Some(CellId(500)) };
+        assert!(command.apply(&mut world_map).is_err());
+        
+        let command = EditCommand::MoveBurg { burg: BurgId(1), position: Point2::new(12.0, 12.0), cell: None };
+        command.apply(&mut world_map).unwrap();
+        assert_eq!(world_map.burg(BurgId(1)).unwrap().cell, CellId(7));
+    }
+    
+    #[test]
+    fn the_oldest_steps_fall_off_past_the_limit() {
+        let mut world_map = grid_world(6);
+        let mut history = EditHistory::with_limit(2);
+        for height in [0.5, 0.6, 0.7] {
+            history.execute(&mut world_map, paint(14, height)).unwrap();
+        }
+        
+        assert!(history.undo(&mut world_map).unwrap());
+        assert!(history.undo(&mut world_map).unwrap());
+        assert!(!history.undo(&mut world_map).unwrap());
+        assert_eq!(world_map.cell(CellId(14)).unwrap().height, 0.5);
+    }
+}
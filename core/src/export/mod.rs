@@ -1,5 +1,9 @@
 //! Export functionality for various map formats
 
+mod native;
+
+pub use native::*;
+
 use crate::{WorldMap, Result};
 use std::path::Path;
 
@@ -136,6 +140,7 @@ impl ExporterRegistry {
         
         // Register built-in exporters
         registry.register(Box::new(JsonExporter));
+        registry.register(Box::new(NativeExporter::new()));
         registry.register(Box::new(PngExporter::new(2048, 1024)));
         registry.register(Box::new(GeoJsonExporter));
         registry.register(Box::new(SvgExporter::new(2048, 1024)));
@@ -0,0 +1,256 @@
+//! Writer for the World Foundry native binary format (.wfm)
+//!
+//! The layout is documented in `import::native`.
+
+use super::MapExporter;
+use crate::data::*;
+use crate::import::native::{
+    CELL_PAGES_SECTION, CHUNK_ENTRY_SIZE, CHUNK_HEADER_SIZE, CODEC_NONE, CODEC_ZSTD, ENTITIES_SECTION,
+    FORMAT_VERSION, HEIGHTMAP_TILES_SECTION, MAGIC, METADATA_SECTION, TABLE_ENTRY_SIZE, THUMBNAIL_SECTION,
+};
+use crate::{Result, WorldFoundryError};
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Longest side of the embedded thumbnail, in pixels
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Side of a heightmap tile, in grid points
+const TILE_SIZE: u32 = 256;
+
+/// Cells in a page
+const PAGE_SIZE: usize = 4096;
+
+/// Mirror of `import::native::Entities` borrowing from the world
+#[derive(Serialize)]
+struct Entities<'a> {
+    features: &'a [Feature],
+    cultures: &'a [Culture],
+    states: &'a [State],
+    provinces: &'a [Province],
+    burgs: &'a [Settlement],
+    rivers: &'a [River],
+    routes: &'a [Route],
+    markers: &'a [Marker],
+    zones: &'a [Zone],
+    diplomacy: &'a DiplomacyMatrix,
+    history: &'a Timeline,
+    calendar: &'a Calendar,
+    events: &'a [DatedEvent],
+}
+
+/// Section ready to be written
+struct Section {
+    tag: [u8; 4],
+    codec: u8,
+    data: Vec<u8>,
+    raw_len: u64,
+}
+
+/// Chunk ready to be written into a chunked section
+struct Chunk {
+    data: Vec<u8>,
+    raw_len: u64,
+    bounds: [f32; 4],
+}
+
+/// Exporter for the compact binary format, with a PNG thumbnail
+pub struct NativeExporter {
+    /// zstd compression level, 1 (fastest) to 22 (smallest)
+    compression_level: i32,
+}
+
+impl NativeExporter {
+    pub fn new() -> Self {
+        Self { compression_level: 9 }
+    }
+    
+    pub fn with_compression_level(compression_level: i32) -> Self {
+        Self { compression_level }
+    }
+    
+    /// Encode a world into the bytes of a `.wfm` file
+    pub fn to_bytes(&self, world_map: &WorldMap) -> Result<Vec<u8>> {
+        let entities = Entities {
+            features: &world_map.features,
+            cultures: &world_map.cultures,
+            states: &world_map.states,
+            provinces: &world_map.provinces,
+            burgs: &world_map.burgs,
+            rivers: &world_map.rivers,
+            routes: &world_map.routes,
+            markers: &world_map.markers,
+            zones: &world_map.zones,
+            diplomacy: &world_map.diplomacy,
+            history: &world_map.history,
+            calendar: &world_map.calendar,
+            events: &world_map.events,
+        };
+        
+        let heightmap = &world_map.heightmap;
+        let heightmap_header = [heightmap.width, heightmap.height, TILE_SIZE];
+        let cells_header = [world_map.cells.len() as u32, PAGE_SIZE as u32, 0];
+        
+        // Metadata and thumbnail come first so previews read little of the file
+        let sections = vec![
+            self.compress(METADATA_SECTION, encode(&world_map.metadata)?)?,
+            Self::store(THUMBNAIL_SECTION, render_thumbnail(world_map)?),
+            self.chunked(HEIGHTMAP_TILES_SECTION, heightmap_header, self.tiles(heightmap)?),
+            self.chunked(CELL_PAGES_SECTION, cells_header, self.pages(&world_map.cells)?),
+            self.compress(ENTITIES_SECTION, encode(&entities)?)?,
+        ];
+        
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        
+        let mut offset = (bytes.len() + sections.len() * TABLE_ENTRY_SIZE) as u64;
+        for section in &sections {
+            bytes.extend_from_slice(&section.tag);
+            bytes.extend_from_slice(&[section.codec, 0, 0, 0]);
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&(section.data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&section.raw_len.to_le_bytes());
+            offset += section.data.len() as u64;
+        }
+        for section in &sections {
+            bytes.extend_from_slice(&section.data);
+        }
+        
+        Ok(bytes)
+    }
+    
+    fn compress(&self, tag: [u8; 4], raw: Vec<u8>) -> Result<Section> {
+        let data = zstd::bulk::compress(&raw, self.compression_level)?;
+        Ok(Section { tag, codec: CODEC_ZSTD, data, raw_len: raw.len() as u64 })
+    }
+    
+    fn store(tag: [u8; 4], data: Vec<u8>) -> Section {
+        Section { tag, codec: CODEC_NONE, raw_len: data.len() as u64, data }
+    }
+    
+    fn compress_chunk(&self, raw: Vec<u8>, bounds: [f32; 4]) -> Result<Chunk> {
+        let data = zstd::bulk::compress(&raw, self.compression_level)?;
+        Ok(Chunk { data, raw_len: raw.len() as u64, bounds })
+    }
+    
+    /// Heightmap split into square tiles, row-major
+    fn tiles(&self, heightmap: &Grid<f32>) -> Result<Vec<Chunk>> {
+        let mut tiles = Vec::new();
+        for y in (0..heightmap.height).step_by(TILE_SIZE as usize) {
+            for x in (0..heightmap.width).step_by(TILE_SIZE as usize) {
+                let tile = heightmap.crop(x, y, TILE_SIZE, TILE_SIZE);
+                let raw = tile.data.iter().flat_map(|value| value.to_le_bytes()).collect();
+                let bounds = [x, y, x + tile.width, y + tile.height].map(|bound| bound as f32);
+                tiles.push(self.compress_chunk(raw, bounds)?);
+            }
+        }
+        Ok(tiles)
+    }
+    
+    /// Cells split into pages of consecutive cells
+    fn pages(&self, cells: &[Cell]) -> Result<Vec<Chunk>> {
+        cells
+            .chunks(PAGE_SIZE)
+            .map(|page| {
+                let bounds = page.iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |bounds, cell| {
+                    let (x, y) = (cell.coordinates.x, cell.coordinates.y);
+                    [bounds[0].min(x), bounds[1].min(y), bounds[2].max(x), bounds[3].max(y)]
+                });
+                self.compress_chunk(encode(page)?, bounds)
+            })
+            .collect()
+    }
+    
+    /// Section of separately compressed chunks behind a header and index
+    fn chunked(&self, tag: [u8; 4], header: [u32; 3], chunks: Vec<Chunk>) -> Section {
+        let mut data = Vec::new();
+        for value in header.into_iter().chain([chunks.len() as u32]) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut offset = (CHUNK_HEADER_SIZE + chunks.len() * CHUNK_ENTRY_SIZE) as u64;
+        for chunk in &chunks {
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&(chunk.data.len() as u64).to_le_bytes());
+            data.extend_from_slice(&chunk.raw_len.to_le_bytes());
+            for bound in chunk.bounds {
+                data.extend_from_slice(&bound.to_le_bytes());
+            }
+            offset += chunk.data.len() as u64;
+        }
+        for chunk in &chunks {
+            data.extend_from_slice(&chunk.data);
+        }
+        Self::store(tag, data)
+    }
+}
+
+impl Default for NativeExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapExporter for NativeExporter {
+    fn export(&self, world_map: &WorldMap, file_path: &Path) -> Result<()> {
+        std::fs::write(file_path, self.to_bytes(world_map)?)?;
+        Ok(())
+    }
+    
+    fn file_extension(&self) -> &'static str {
+        "wfm"
+    }
+    
+    fn format_name(&self) -> &'static str {
+        "World Foundry Binary"
+    }
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(value).map_err(|e| WorldFoundryError::Export(format!("Cannot encode section: {}", e)))
+}
+
+/// Small PNG of the land and sea, coloured by height
+fn render_thumbnail(world_map: &WorldMap) -> Result<Vec<u8>> {
+    let (map_width, map_height) = (world_map.metadata.width.max(1), world_map.metadata.height.max(1));
+    let scale = THUMBNAIL_SIZE as f32 / map_width.max(map_height) as f32;
+    let width = ((map_width as f32 * scale).round() as u32).max(1);
+    let height = ((map_height as f32 * scale).round() as u32).max(1);
+    
+    let cells = &world_map.cells;
+    let (min_height, max_height) = cells
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), cell| (min.min(cell.height), max.max(cell.height)));
+    let relief = (max_height - min_height).max(f32::EPSILON);
+    
+    let mut image = image::RgbImage::new(width, height);
+    if !cells.is_empty() {
+        let topology = world_map.topology();
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let point = nalgebra::Point2::new((x as f32 + 0.5) / scale, (y as f32 + 0.5) / scale);
+            let Some(index) = topology.cell_at(point) else {
+                continue;
+            };
+            let cell = &cells[index];
+            let shade = (cell.height - min_height) / relief;
+            *pixel = match cell.biome {
+                BiomeType::Marine => image::Rgb([40, 80, 140]),
+                BiomeType::Freshwater => image::Rgb([90, 140, 200]),
+                // Green lowlands shading to brown highlands
+                _ => image::Rgb([
+                    (80.0 + 100.0 * shade) as u8,
+                    (140.0 - 40.0 * shade) as u8,
+                    (70.0 + 10.0 * shade) as u8,
+                ]),
+            };
+        }
+    }
+    
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| WorldFoundryError::Export(format!("Cannot encode thumbnail: {}", e)))?;
+    Ok(png)
+}
\ No newline at end of file
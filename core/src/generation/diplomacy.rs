@@ -0,0 +1,246 @@
+//! Generation of diplomatic relations between states
+
+use super::PoliticalParams;
+use crate::data::{CultureId, DiplomacyMatrix, DiplomaticStatus, StateId, WorldMap};
+use nalgebra::Point2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+/// Number of closest states considered direct neighbours
+const NEIGHBOUR_COUNT: usize = 4;
+
+/// Generator for the diplomacy matrix of an existing political map
+///
+/// Relations are derived from proximity (neighbours quarrel, distant states
+/// never meet), shared culture and shared dominant religion, plus some
+/// seeded randomness so that every world gets its own politics.
+pub struct DiplomacyGenerator {
+    params: PoliticalParams,
+    seed: u64,
+}
+
+/// Per-state facts the generator needs
+struct StateProfile {
+    id: StateId,
+    center: Point2<f32>,
+    culture: CultureId,
+    religion: Option<u32>,
+    size: f32,
+}
+
+impl DiplomacyGenerator {
+    pub fn new(params: PoliticalParams, seed: u64) -> Self {
+        Self { params, seed }
+    }
+    
+    /// Generate relations between all states of the given world
+    pub fn generate(&self, world_map: &WorldMap) -> DiplomacyMatrix {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut matrix = DiplomacyMatrix::new();
+        
+        let mut profiles = Self::profiles(world_map);
+        profiles.sort_by_key(|profile| profile.id);
+        
+        let diagonal = ((world_map.metadata.width as f32).powi(2)
+            + (world_map.metadata.height as f32).powi(2))
+            .sqrt()
+            .max(1.0);
+        let neighbours = Self::neighbours(&profiles);
+        let aggressiveness = self.params.expansion_aggressiveness.max(0.0);
+        let volatility = 1.0 - self.params.border_stability.clamp(0.0, 1.0) * 0.5;
+        
+        for (a_index, a) in profiles.iter().enumerate() {
+            for (b_index, b) in profiles.iter().enumerate().skip(a_index + 1) {
+                let distance = (a.center - b.center).norm() / diagonal;
+                let adjacent = neighbours[a_index].contains(&b_index) || neighbours[b_index].contains(&a_index);
+                
+                // States on opposite sides of the world have never met
+                if !adjacent && distance > 0.5 {
+                    matrix.set_mutual(a.id, b.id, DiplomaticStatus::Unknown);
+                    continue;
+                }
+                
+                let mut affinity = 0.0;
+                if a.culture == b.culture {
+                    affinity += 1.0;
+                }
+                if a.religion.is_some() && a.religion == b.religion {
+                    affinity += 0.7;
+                }
+                if adjacent {
+                    affinity -= 0.6 * aggressiveness;
+                } else {
+                    affinity += 0.3 * distance;
+                }
+                affinity += rng.gen_range(-0.8..0.8) * volatility;
+                
+                // A much larger neighbour may have reduced the smaller one to vassalage
+                let (large, small) = if a.size >= b.size { (a, b) } else { (b, a) };
+                if adjacent
+                    && large.size > small.size * 4.0
+                    && matrix.suzerain_of(small.id).is_none()
+                    && matrix.suzerain_of(large.id) != Some(small.id)
+                    && rng.gen::<f32>() < 0.3 * aggressiveness.min(2.0)
+                {
+                    matrix.set_mutual(small.id, large.id, DiplomaticStatus::Vassal);
+                    continue;
+                }
+                
+                let status = Self::status_for(affinity, adjacent);
+                matrix.set_mutual(a.id, b.id, status);
+                
+                // Friendship is not always returned
+                if matches!(status, DiplomaticStatus::Friendly | DiplomaticStatus::Neutral) && rng.gen::<f32>() < 0.15 {
+                    if rng.gen::<bool>() {
+                        matrix.set(a.id, b.id, DiplomaticStatus::Suspicion);
+                    } else {
+                        matrix.set(b.id, a.id, DiplomaticStatus::Suspicion);
+                    }
+                }
+            }
+        }
+        
+        Self::join_overlord_wars(&mut matrix, &profiles);
+        matrix
+    }
+    
+    fn status_for(affinity: f32, adjacent: bool) -> DiplomaticStatus {
+        if affinity > 1.2 {
+            DiplomaticStatus::Ally
+        } else if affinity > 0.5 {
+            DiplomaticStatus::Friendly
+        } else if affinity > -0.3 {
+            DiplomaticStatus::Neutral
+        } else if affinity > -0.8 {
+            DiplomaticStatus::Suspicion
+        } else if affinity > -1.3 || !adjacent {
+            DiplomaticStatus::Rival
+        } else {
+            DiplomaticStatus::Enemy
+        }
+    }
+    
+    /// Vassals are drawn into the wars of their overlords, except against
+    /// states they are already bound to by vassalage
+    fn join_overlord_wars(matrix: &mut DiplomacyMatrix, profiles: &[StateProfile]) {
+        for profile in profiles {
+            if let Some(suzerain) = matrix.suzerain_of(profile.id) {
+                for enemy in matrix.enemies_of(suzerain) {
+                    let bound = matches!(
+                        matrix.status(profile.id, enemy),
+                        DiplomaticStatus::Vassal | DiplomaticStatus::Suzerain
+                    );
+                    if enemy != profile.id && !bound {
+                        matrix.set_mutual(profile.id, enemy, DiplomaticStatus::Enemy);
+                    }
+                }
+            }
+        }
+    }
+    
+    fn profiles(world_map: &WorldMap) -> Vec<StateProfile> {
+        let mut cell_counts: HashMap<StateId, u32> = HashMap::new();
+        let mut religions: HashMap<StateId, HashMap<u32, u32>> = HashMap::new();
+        for cell in &world_map.cells {
+            if let Some(state) = cell.state {
+                *cell_counts.entry(state).or_default() += 1;
+                if let Some(religion) = cell.religion {
+                    *religions.entry(state).or_default().entry(religion).or_default() += 1;
+                }
+            }
+        }
+        
+        world_map.states
+            .iter()
+            .map(|state| {
+                let religion = religions.get(&state.id).and_then(|counts| {
+                    counts
+                        .iter()
+                        .max_by_key(|(&religion, &count)| (count, std::cmp::Reverse(religion)))
+                        .map(|(&religion, _)| religion)
+                });
+                let cells = cell_counts.get(&state.id).copied().unwrap_or(state.cells.len() as u32);
+                
+                StateProfile {
+                    id: state.id,
+                    center: state.center,
+                    culture: state.culture,
+                    religion,
+                    size: if cells > 0 { cells as f32 } else { state.area.max(0.0) },
+                }
+            })
+            .collect()
+    }
+    
+    /// Indices of the closest states for every state
+    fn neighbours(profiles: &[StateProfile]) -> Vec<Vec<usize>> {
+        profiles
+            .iter()
+            .enumerate()
+            .map(|(index, profile)| {
+                let mut others: Vec<(usize, f32)> = profiles
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(other, candidate)| (other, (candidate.center - profile.center).norm()))
+                    .collect();
+                others.sort_by(|a, b| a.1.total_cmp(&b.1));
+                others.into_iter().take(NEIGHBOUR_COUNT).map(|(other, _)| other).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use DiplomaticStatus::*;
+    
+    fn params() -> PoliticalParams {
+        PoliticalParams { num_states: 4, expansion_aggressiveness: 1.0, border_stability: 0.5 }
+    }
+    
+    #[test]
+    fn every_pair_of_states_gets_a_seeded_relation() {
+        let world_map = grid_world(8);
+        let matrix = DiplomacyGenerator::new(params(), 7).generate(&world_map);
+        let again = DiplomacyGenerator::new(params(), 7).generate(&world_map);
+        assert_eq!(matrix.iter().collect::<Vec<_>>(), again.iter().collect::<Vec<_>>());
+        
+        assert_eq!(matrix.len(), 12);
+        for relation in matrix.iter() {
+            let reverse = matrix.status(relation.to, relation.from);
+            match relation.status {
+                Vassal => assert_eq!(reverse, Suzerain),
+                Suzerain => assert_eq!(reverse, Vassal),
+                Enemy => assert_eq!(reverse, Enemy),
+                _ => {}
+            }
+        }
+    }
+    
+    #[test]
+    fn vassals_join_overlord_wars_except_against_their_own_vassals() {
+        let profiles: Vec<StateProfile> = (1..=4)
+            .map(|id| StateProfile {
+                id: StateId(id),
+                center: Point2::origin(),
+                culture: CultureId(0),
+                religion: None,
+                size: 1.0,
+            })
+            .collect();
+        let mut matrix = DiplomacyMatrix::new();
+        matrix.set_mutual(StateId(2), StateId(1), Vassal);
+        matrix.set_mutual(StateId(3), StateId(2), Vassal);
+        matrix.set_mutual(StateId(1), StateId(3), Enemy);
+        matrix.set_mutual(StateId(1), StateId(4), Enemy);
+        
+        DiplomacyGenerator::join_overlord_wars(&mut matrix, &profiles);
+        assert_eq!(matrix.status(StateId(2), StateId(4)), Enemy);
+        assert_eq!(matrix.status(StateId(2), StateId(3)), Suzerain);
+        assert_eq!(matrix.status(StateId(3), StateId(2)), Vassal);
+    }
+}
@@ -0,0 +1,313 @@
+//! Procedural heraldry for states, provinces and settlements
+
+use crate::data::{
+    Charge, CoatOfArms, CultureId, Division, DivisionKind, LineStyle, Ordinary, OrdinaryKind,
+    Province, Settlement, ShieldShape, State, Tincture, WorldMap,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const CHARGES: [&str; 20] = [
+    "mullet", "crescent", "roundel", "lozenge", "annulet", "fusil", "billet", "cross",
+    "lionRampant", "eagle", "tower", "fleurDeLis", "key", "sword", "crown", "tree",
+    "horse", "stag", "boar", "wolf",
+];
+
+const DIVISIONS: [DivisionKind; 8] = [
+    DivisionKind::PerPale,
+    DivisionKind::PerFess,
+    DivisionKind::PerBend,
+    DivisionKind::PerBendSinister,
+    DivisionKind::PerChevron,
+    DivisionKind::PerCross,
+    DivisionKind::PerSaltire,
+    DivisionKind::Gyronny,
+];
+
+const ORDINARIES: [OrdinaryKind; 12] = [
+    OrdinaryKind::Pale,
+    OrdinaryKind::Fess,
+    OrdinaryKind::Bend,
+    OrdinaryKind::BendSinister,
+    OrdinaryKind::Chief,
+    OrdinaryKind::Base,
+    OrdinaryKind::Chevron,
+    OrdinaryKind::Cross,
+    OrdinaryKind::Saltire,
+    OrdinaryKind::Pile,
+    OrdinaryKind::Bordure,
+    OrdinaryKind::Canton,
+];
+
+/// Entity kinds, used to give every entity its own random stream
+const STATE_SALT: u64 = 1;
+const PROVINCE_SALT: u64 = 2;
+const BURG_SALT: u64 = 3;
+
+/// Generator for coats of arms
+///
+/// Arms are drawn on the shield shape of the owner's culture and obey the rule
+/// of tincture (no metal on metal, no colour on colour). Provinces and burgs
+/// often derive their arms from their overlord, the way cadet arms do.
+pub struct HeraldryGenerator {
+    seed: u64,
+}
+
+impl HeraldryGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+    
+    /// Generate arms for every state, province and burg, replacing existing ones
+    pub fn generate_all(&self, world_map: &mut WorldMap) {
+        let state_arms: Vec<CoatOfArms> = world_map.states
+            .iter()
+            .map(|state| self.state_arms(world_map, state))
+            .collect();
+        for (state, arms) in world_map.states.iter_mut().zip(state_arms) {
+            state.coa = Some(arms);
+        }
+        
+        let province_arms: Vec<CoatOfArms> = world_map.provinces
+            .iter()
+            .map(|province| self.province_arms(world_map, province))
+            .collect();
+        for (province, arms) in world_map.provinces.iter_mut().zip(province_arms) {
+            province.coa = Some(arms);
+        }
+        
+        let burg_arms: Vec<CoatOfArms> = world_map.burgs
+            .iter()
+            .map(|burg| self.burg_arms(world_map, burg))
+            .collect();
+        for (burg, arms) in world_map.burgs.iter_mut().zip(burg_arms) {
+            burg.coa = Some(arms);
+        }
+    }
+    
+    pub fn state_arms(&self, world_map: &WorldMap, state: &State) -> CoatOfArms {
+        let mut rng = self.rng(STATE_SALT, state.id.into());
+        let shield = Self::culture_shield(world_map, state.culture);
+        Self::generate(&mut rng, shield, None)
+    }
+    
+    /// Arms of a province, often derived from its state's arms
+    pub fn province_arms(&self, world_map: &WorldMap, province: &Province) -> CoatOfArms {
+        let mut rng = self.rng(PROVINCE_SALT, province.id.into());
+        let state = world_map.state(province.state);
+        let shield = state
+            .map(|state| Self::culture_shield(world_map, state.culture))
+            .unwrap_or_default();
+        let parent = state.map(|state| {
+            state.coa.clone().unwrap_or_else(|| self.state_arms(world_map, state))
+        });
+        Self::generate(&mut rng, shield, parent.as_ref())
+    }
+    
+    /// Arms of a settlement, often derived from its province's or state's arms
+    pub fn burg_arms(&self, world_map: &WorldMap, burg: &Settlement) -> CoatOfArms {
+        let mut rng = self.rng(BURG_SALT, burg.id.into());
+        let shield = Self::culture_shield(world_map, burg.culture);
+        
+        let province = world_map
+            .cell(burg.cell)
+            .and_then(|cell| cell.province)
+            .and_then(|id| world_map.province(id));
+        let parent = match province {
+            Some(province) => Some(province.coa.clone().unwrap_or_else(|| self.province_arms(world_map, province))),
+            None => world_map
+                .state(burg.state)
+                .map(|state| state.coa.clone().unwrap_or_else(|| self.state_arms(world_map, state))),
+        };
+        Self::generate(&mut rng, shield, parent.as_ref())
+    }
+    
+    fn rng(&self, salt: u64, id: u32) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (salt << 56) ^ (id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+    
+    /// Shield shape of a culture; unknown shapes fall back to the heater shield
+    fn culture_shield(world_map: &WorldMap, culture: CultureId) -> ShieldShape {
+        let shape = world_map
+            .culture(culture)
+            .map(|candidate| ShieldShape::from(candidate.shield.as_str()));
+        match shape {
+            Some(ShieldShape::Other(_)) | None => ShieldShape::Heater,
+            Some(shape) => shape,
+        }
+    }
+    
+    fn generate(rng: &mut StdRng, shield: ShieldShape, parent: Option<&CoatOfArms>) -> CoatOfArms {
+        if let Some(parent) = parent {
+            if rng.gen::<f32>() < 0.5 {
+                return Self::difference(rng, shield, parent);
+            }
+        }
+        
+        let field = Self::random_tincture(rng);
+        let mut arms = CoatOfArms::plain(shield, field.clone());
+        
+        if rng.gen::<f32>() < 0.3 {
+            arms.division = Some(Division {
+                kind: DIVISIONS.choose(rng).cloned().unwrap_or(DivisionKind::PerPale),
+                tincture: Self::contrasting(rng, &field),
+                line: Self::random_line(rng),
+            });
+        }
+        
+        let ordinary_chance = if arms.division.is_some() { 0.2 } else { 0.45 };
+        if rng.gen::<f32>() < ordinary_chance {
+            arms.ordinaries.push(Ordinary {
+                kind: ORDINARIES.choose(rng).cloned().unwrap_or(OrdinaryKind::Fess),
+                tincture: Self::contrasting(rng, &field),
+                line: Self::random_line(rng),
+            });
+        }
+        
+        let charge_chance = match (arms.division.is_some(), arms.ordinaries.is_empty()) {
+            (true, _) => 0.3,
+            (false, true) => 0.9,
+            (false, false) => 0.5,
+        };
+        if rng.gen::<f32>() < charge_chance {
+            let positions = match arms.ordinaries.first() {
+                Some(ordinary) => Self::free_positions(&ordinary.kind).to_string(),
+                None => ["e", "e", "e", "def", "abc", "bdf", "acg"].choose(rng).unwrap_or(&"e").to_string(),
+            };
+            let size = if positions.len() == 1 && arms.ordinaries.is_empty() { 1.5 } else { 0.7 };
+            arms.charges.push(Charge {
+                charge: CHARGES.choose(rng).unwrap_or(&"mullet").to_string(),
+                tincture: Self::contrasting(rng, &field),
+                positions,
+                size,
+                sinister: false,
+                reversed: false,
+            });
+        }
+        
+        arms
+    }
+    
+    /// Cadet arms: the parent's arms with a mark of difference
+    fn difference(rng: &mut StdRng, shield: ShieldShape, parent: &CoatOfArms) -> CoatOfArms {
+        let mut arms = parent.clone();
+        arms.shield = shield;
+        
+        let mark = if arms.ordinaries.iter().any(|ordinary| ordinary.kind == OrdinaryKind::Bordure) {
+            OrdinaryKind::Chief
+        } else {
+            [OrdinaryKind::Bordure, OrdinaryKind::Chief, OrdinaryKind::Canton]
+                .choose(rng)
+                .cloned()
+                .unwrap_or(OrdinaryKind::Bordure)
+        };
+        arms.ordinaries.push(Ordinary {
+            tincture: Self::contrasting(rng, &arms.field),
+            kind: mark,
+            line: LineStyle::Straight,
+        });
+        arms
+    }
+    
+    fn random_tincture(rng: &mut StdRng) -> Tincture {
+        let roll = rng.gen::<f32>();
+        if roll < 0.6 {
+            Tincture::COLOURS.choose(rng).cloned().unwrap_or(Tincture::Gules)
+        } else if roll < 0.95 {
+            Tincture::METALS.choose(rng).cloned().unwrap_or(Tincture::Argent)
+        } else {
+            [Tincture::Ermine, Tincture::Vair].choose(rng).cloned().unwrap_or(Tincture::Ermine)
+        }
+    }
+    
+    /// Tincture that may lie on `base` under the rule of tincture
+    fn contrasting(rng: &mut StdRng, base: &Tincture) -> Tincture {
+        if base.is_metal() {
+            Tincture::COLOURS.choose(rng).cloned().unwrap_or(Tincture::Gules)
+        } else if base.is_fur() {
+            Tincture::COLOURS.choose(rng).cloned().unwrap_or(Tincture::Sable)
+        } else {
+            Tincture::METALS.choose(rng).cloned().unwrap_or(Tincture::Or)
+        }
+    }
+    
+    fn random_line(rng: &mut StdRng) -> LineStyle {
+        if rng.gen::<f32>() < 0.75 {
+            LineStyle::Straight
+        } else {
+            [LineStyle::Wavy, LineStyle::Indented, LineStyle::Embattled, LineStyle::Engrailed]
+                .choose(rng)
+                .cloned()
+                .unwrap_or(LineStyle::Wavy)
+        }
+    }
+    
+    /// Grid positions not covered by the given ordinary
+    fn free_positions(kind: &OrdinaryKind) -> &'static str {
+        match kind {
+            OrdinaryKind::Pale => "df",
+            OrdinaryKind::Fess => "bh",
+            OrdinaryKind::Bend => "cg",
+            OrdinaryKind::BendSinister => "ai",
+            OrdinaryKind::Chief => "e",
+            OrdinaryKind::Base => "b",
+            OrdinaryKind::Chevron => "ach",
+            OrdinaryKind::Cross => "acgi",
+            OrdinaryKind::Saltire => "bdfh",
+            OrdinaryKind::Pile => "gi",
+            _ => "e",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    /// Tinctures that may not lie on each other under the rule of tincture
+    fn clash(field: &Tincture, tincture: &Tincture) -> bool {
+        (field.is_metal() && tincture.is_metal()) || (!field.is_metal() && !field.is_fur() && !tincture.is_metal())
+    }
+    
+    #[test]
+    fn arms_are_seeded_and_drawn_on_the_culture_shield() {
+        let mut world_map = grid_world(8);
+        world_map.cultures[0].shield = "spanish".to_string();
+        world_map.burgs.iter_mut().for_each(|burg| burg.culture = world_map.cultures[0].id);
+        let mut again = world_map.clone();
+        
+        HeraldryGenerator::new(5).generate_all(&mut world_map);
+        HeraldryGenerator::new(5).generate_all(&mut again);
+        for (state, other) in world_map.states.iter().zip(&again.states) {
+            assert_eq!(state.coa, other.coa);
+        }
+        let shields = world_map.states.iter().filter_map(|state| state.coa.as_ref()).map(|arms| &arms.shield);
+        assert_eq!(shields.filter(|&shield| *shield == ShieldShape::Spanish).count(), world_map.states.len());
+        assert!(world_map.burgs.iter().all(|burg| burg.coa.as_ref().unwrap().shield == ShieldShape::Spanish));
+    }
+    
+    #[test]
+    fn arms_follow_the_rule_of_tincture() {
+        let generator = HeraldryGenerator::new(11);
+        for id in 0..200 {
+            let arms = HeraldryGenerator::generate(&mut generator.rng(STATE_SALT, id), ShieldShape::Heater, None);
+            let cadet = HeraldryGenerator::difference(&mut generator.rng(BURG_SALT, id), ShieldShape::Round, &arms);
+            assert_eq!(cadet.ordinaries.len(), arms.ordinaries.len() + 1);
+            assert_eq!(cadet.shield, ShieldShape::Round);
+            
+            for arms in [&arms, &cadet] {
+                let tinctures = arms
+                    .division
+                    .iter()
+                    .map(|division| &division.tincture)
+                    .chain(arms.ordinaries.iter().map(|ordinary| &ordinary.tincture))
+                    .chain(arms.charges.iter().map(|charge| &charge.tincture));
+                for tincture in tinctures {
+                    assert!(!clash(&arms.field, tincture), "{:?} on {:?}", tincture, arms.field);
+                }
+            }
+        }
+    }
+}
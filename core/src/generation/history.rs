@@ -0,0 +1,528 @@
+//! Simulation of political history
+
+use super::HistoryParams;
+use crate::data::{
+    BurgId, CellId, DiplomaticStatus, HistoricalEvent, HistoricalEventKind, State, StateId, StateSnapshot,
+    Timeline, WorldMap,
+};
+use nalgebra::Point2;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Wars are not settled before they have lasted this many years
+const MIN_WAR_YEARS: i32 = 3;
+
+/// States smaller than this are split no further
+const MIN_SPLIT_CELLS: usize = 20;
+
+const COMPASS_PREFIXES: [&str; 4] = ["Northern", "Southern", "Eastern", "Western"];
+
+/// Simulator evolving the political map over a number of years
+///
+/// The world passed in is the political situation at `start_year`; after the
+/// simulation its states, cell ownership, burg allegiance and diplomacy reflect
+/// the end of the simulated period, and `WorldMap::history` holds the timeline
+/// that led there.
+pub struct HistorySimulator {
+    params: HistoryParams,
+    seed: u64,
+}
+
+/// Running war between two states
+struct War {
+    attacker: StateId,
+    defender: StateId,
+    since: i32,
+}
+
+/// Aggregates of a state expressed per owned cell, so they follow border changes
+#[derive(Clone, Copy)]
+struct PerCell {
+    area: f32,
+    population: f32,
+    rural: f32,
+    urban: f32,
+}
+
+impl HistorySimulator {
+    pub fn new(params: HistoryParams, seed: u64) -> Self {
+        Self { params, seed }
+    }
+    
+    /// Run the simulation, updating the world and recording its timeline
+    pub fn simulate(&self, world_map: &mut WorldMap) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let neighbours = world_map.topology().neighbours.clone();
+        let mut owners: Vec<Option<StateId>> = world_map.cells.iter().map(|cell| cell.state).collect();
+        
+        let mut per_cell: HashMap<StateId, PerCell> = HashMap::new();
+        for state in &world_map.states {
+            let count = owners.iter().filter(|&&owner| owner == Some(state.id)).count().max(1) as f32;
+            per_cell.insert(state.id, PerCell {
+                area: state.area / count,
+                population: state.population as f32 / count,
+                rural: state.rural / count,
+                urban: state.urban / count,
+            });
+        }
+        
+        let mut timeline = Timeline {
+            start_year: self.params.start_year,
+            end_year: self.params.start_year + self.params.years as i32,
+            events: Vec::new(),
+            snapshots: vec![StateSnapshot { year: self.params.start_year, cell_states: owners.clone() }],
+        };
+        
+        let mut wars: Vec<War> = world_map.diplomacy
+            .wars()
+            .into_iter()
+            .map(|(attacker, defender)| War { attacker, defender, since: self.params.start_year })
+            .collect();
+        let interval = self.params.snapshot_interval.max(1);
+        
+        for offset in 1..=self.params.years {
+            let year = self.params.start_year + offset as i32;
+            
+            self.declare_wars(&mut rng, world_map, &owners, &neighbours, &mut wars, &mut timeline, year);
+            self.fight_wars(&mut rng, world_map, &mut owners, &neighbours, &mut wars, &mut timeline, year);
+            self.split_dynasties(&mut rng, world_map, &mut owners, &mut per_cell, &mut timeline, year);
+            
+            if offset % interval == 0 || offset == self.params.years {
+                timeline.snapshots.push(StateSnapshot { year, cell_states: owners.clone() });
+            }
+        }
+        
+        Self::apply(world_map, &owners, &per_cell);
+        world_map.history = timeline;
+    }
+    
+    /// Neighbouring states in bad terms may go to war
+    #[allow(clippy::too_many_arguments)]
+    fn declare_wars(
+        &self,
+        rng: &mut StdRng,
+        world_map: &mut WorldMap,
+        owners: &[Option<StateId>],
+        neighbours: &[Vec<usize>],
+        wars: &mut Vec<War>,
+        timeline: &mut Timeline,
+        year: i32,
+    ) {
+        for (a, b) in Self::adjacent_states(owners, neighbours) {
+            if wars.iter().any(|war| Self::involves(war, a, b)) {
+                continue;
+            }
+            
+            let multiplier = match world_map.diplomacy.status(a, b) {
+                DiplomaticStatus::Rival => 2.0,
+                DiplomaticStatus::Suspicion => 1.5,
+                DiplomaticStatus::Neutral | DiplomaticStatus::Unknown => 0.5,
+                DiplomaticStatus::Friendly => 0.1,
+                DiplomaticStatus::Ally | DiplomaticStatus::Vassal | DiplomaticStatus::Suzerain | DiplomaticStatus::Enemy => 0.0,
+            };
+            if rng.gen::<f32>() >= self.params.war_frequency * multiplier {
+                continue;
+            }
+            
+            let (attacker, defender) = if rng.gen::<bool>() { (a, b) } else { (b, a) };
+            world_map.diplomacy.set_mutual(attacker, defender, DiplomaticStatus::Enemy);
+            wars.push(War { attacker, defender, since: year });
+            timeline.events.push(HistoricalEvent {
+                year,
+                kind: HistoricalEventKind::WarDeclared,
+                states: vec![attacker, defender],
+                cells: Vec::new(),
+                description: format!(
+                    "{} declared war on {}",
+                    Self::name_of(world_map, attacker),
+                    Self::name_of(world_map, defender)
+                ),
+            });
+        }
+    }
+    
+    /// Each year of war moves the front line; beaten states are annexed
+    #[allow(clippy::too_many_arguments)]
+    fn fight_wars(
+        &self,
+        rng: &mut StdRng,
+        world_map: &mut WorldMap,
+        owners: &mut [Option<StateId>],
+        neighbours: &[Vec<usize>],
+        wars: &mut Vec<War>,
+        timeline: &mut Timeline,
+        year: i32,
+    ) {
+        let active: Vec<(StateId, StateId, i32)> = wars.iter().map(|war| (war.attacker, war.defender, war.since)).collect();
+        for (attacker, defender, since) in active {
+            // An earlier annexation this year may have ended the war
+            if !wars.iter().any(|war| war.attacker == attacker && war.defender == defender) {
+                continue;
+            }
+            
+            let attacker_strength = Self::strength(rng, world_map, owners, attacker);
+            let defender_strength = Self::strength(rng, world_map, owners, defender);
+            let (winner, loser) = if attacker_strength >= defender_strength {
+                (attacker, defender)
+            } else {
+                (defender, attacker)
+            };
+            
+            // The front advances further the more lopsided the war is
+            let ratio = attacker_strength.max(defender_strength) / attacker_strength.min(defender_strength).max(0.01);
+            let advance = (rng.gen_range(1.0..3.0) * ratio.min(4.0)).round() as usize;
+            let mut front: Vec<usize> = (0..owners.len())
+                .filter(|&cell| owners[cell] == Some(loser))
+                .filter(|&cell| neighbours[cell].iter().any(|&other| owners[other] == Some(winner)))
+                .collect();
+            front.shuffle(rng);
+            front.truncate(advance);
+            
+            if !front.is_empty() {
+                for &cell in &front {
+                    owners[cell] = Some(winner);
+                }
+                timeline.events.push(HistoricalEvent {
+                    year,
+                    kind: HistoricalEventKind::BorderShift,
+                    states: vec![winner, loser],
+                    cells: front.iter().map(|&cell| world_map.cells[cell].id).collect(),
+                    description: format!(
+                        "{} took {} cells from {}",
+                        Self::name_of(world_map, winner),
+                        front.len(),
+                        Self::name_of(world_map, loser)
+                    ),
+                });
+            }
+            
+            let remaining = owners.iter().filter(|&&owner| owner == Some(loser)).count();
+            if remaining < 5 {
+                Self::annex(world_map, owners, wars, timeline, year, winner, loser);
+            } else if year - since >= MIN_WAR_YEARS && rng.gen::<f32>() < 0.2 {
+                world_map.diplomacy.set_mutual(winner, loser, DiplomaticStatus::Suspicion);
+                timeline.events.push(HistoricalEvent {
+                    year,
+                    kind: HistoricalEventKind::PeaceSigned,
+                    states: vec![winner, loser],
+                    cells: Vec::new(),
+                    description: format!(
+                        "{} and {} made peace",
+                        Self::name_of(world_map, winner),
+                        Self::name_of(world_map, loser)
+                    ),
+                });
+                wars.retain(|war| !(war.attacker == attacker && war.defender == defender));
+            }
+        }
+    }
+    
+    /// Absorb the rest of a defeated state into the victor
+    #[allow(clippy::too_many_arguments)]
+    fn annex(
+        world_map: &mut WorldMap,
+        owners: &mut [Option<StateId>],
+        wars: &mut Vec<War>,
+        timeline: &mut Timeline,
+        year: i32,
+        winner: StateId,
+        loser: StateId,
+    ) {
+        let mut cells = Vec::new();
+        for (index, owner) in owners.iter_mut().enumerate() {
+            if *owner == Some(loser) {
+                *owner = Some(winner);
+                cells.push(world_map.cells[index].id);
+            }
+        }
+        
+        timeline.events.push(HistoricalEvent {
+            year,
+            kind: HistoricalEventKind::Annexation,
+            states: vec![winner, loser],
+            cells,
+            description: format!(
+                "{} annexed {}",
+                Self::name_of(world_map, winner),
+                Self::name_of(world_map, loser)
+            ),
+        });
+        
+        for burg in world_map.burgs.iter_mut().filter(|burg| burg.state == loser) {
+            burg.state = winner;
+            burg.capital = false;
+        }
+        world_map.states.retain(|state| state.id != loser);
+        world_map.diplomacy.remove_state(loser);
+        wars.retain(|war| war.attacker != loser && war.defender != loser);
+    }
+    
+    /// Large states may break apart when a dynasty divides its inheritance
+    fn split_dynasties(
+        &self,
+        rng: &mut StdRng,
+        world_map: &mut WorldMap,
+        owners: &mut [Option<StateId>],
+        per_cell: &mut HashMap<StateId, PerCell>,
+        timeline: &mut Timeline,
+        year: i32,
+    ) {
+        let candidates: Vec<StateId> = world_map.states.iter().map(|state| state.id).collect();
+        for state_id in candidates {
+            let owned: Vec<usize> = (0..owners.len()).filter(|&cell| owners[cell] == Some(state_id)).collect();
+            if owned.len() < MIN_SPLIT_CELLS || rng.gen::<f32>() >= self.params.dynastic_split_chance {
+                continue;
+            }
+            
+            let Some(parent) = world_map.state(state_id).cloned() else {
+                continue;
+            };
+            
+            // The heir's share is the part of the realm far from the capital
+            let seat = world_map
+                .burg(parent.capital)
+                .filter(|burg| burg.state == state_id)
+                .map(|burg| Point2::new(burg.x, burg.y))
+                .unwrap_or(parent.center);
+            let Some(&far) = owned.iter().max_by(|&&a, &&b| {
+                let da = (world_map.cells[a].coordinates - seat).norm();
+                let db = (world_map.cells[b].coordinates - seat).norm();
+                da.total_cmp(&db)
+            }) else {
+                continue;
+            };
+            let heir_seat = world_map.cells[far].coordinates;
+            let share: Vec<usize> = owned
+                .iter()
+                .copied()
+                .filter(|&cell| {
+                    let position = world_map.cells[cell].coordinates;
+                    (position - heir_seat).norm() < (position - seat).norm()
+                })
+                .collect();
+            if share.len() < 5 || owned.len() - share.len() < 5 {
+                continue;
+            }
+            
+            let new_id = StateId(world_map.states.iter().map(|state| state.id.0).max().unwrap_or(0) + 1);
+            for &cell in &share {
+                owners[cell] = Some(new_id);
+            }
+            let share_ids: BTreeSet<CellId> = share.iter().map(|&cell| world_map.cells[cell].id).collect();
+            
+            // Burgs in the heir's share change allegiance; the largest becomes the capital
+            let mut capital = None;
+            for burg in world_map.burgs.iter_mut().filter(|burg| share_ids.contains(&burg.cell)) {
+                burg.state = new_id;
+                burg.capital = false;
+                if capital.is_none_or(|(_, population)| burg.population > population) {
+                    capital = Some((burg.id, burg.population));
+                }
+            }
+            if let Some((capital_id, _)) = capital {
+                if let Some(burg) = world_map.burg_mut(capital_id) {
+                    burg.capital = true;
+                }
+            }
+            
+            let base_name = COMPASS_PREFIXES
+                .iter()
+                .find_map(|prefix| parent.name.strip_prefix(prefix).map(str::trim_start))
+                .unwrap_or(&parent.name);
+            let name = format!("{} {}", Self::direction_prefix(seat, heir_seat), base_name);
+            let heir = State {
+                id: new_id,
+                full_name: name.clone(),
+                name,
+                color: parent.color.lighten(0.4),
+                capital: capital.map(|(id, _)| id).unwrap_or(BurgId(0)),
+                center: heir_seat,
+                cells: Vec::new(),
+                coa: None,
+                ..parent.clone()
+            };
+            
+            // The heir keeps the parent's friendships and feuds but resents the split;
+            // each side keeps its own stance, so one-sided suspicion stays one-sided
+            let inherited: Vec<(StateId, DiplomaticStatus, DiplomaticStatus)> = world_map.diplomacy
+                .relations_of(state_id)
+                .filter(|relation| !matches!(relation.status, DiplomaticStatus::Enemy | DiplomaticStatus::Vassal | DiplomaticStatus::Suzerain))
+                .map(|relation| (relation.to, relation.status, world_map.diplomacy.status(relation.to, state_id)))
+                .collect();
+            for (other, stance, returned) in inherited {
+                world_map.diplomacy.set(new_id, other, stance);
+                world_map.diplomacy.set(other, new_id, returned);
+            }
+            world_map.diplomacy.set_mutual(new_id, state_id, DiplomaticStatus::Rival);
+            
+            if let Some(&inherited) = per_cell.get(&state_id) {
+                per_cell.insert(new_id, inherited);
+            }
+            
+            timeline.events.push(HistoricalEvent {
+                year,
+                kind: HistoricalEventKind::DynasticSplit,
+                states: vec![new_id, state_id],
+                cells: share_ids.into_iter().collect(),
+                description: format!("{} broke away from {}", heir.name, parent.name),
+            });
+            world_map.states.push(heir);
+        }
+    }
+    
+    /// Write the simulated ownership back into cells and refresh state aggregates
+    fn apply(world_map: &mut WorldMap, owners: &[Option<StateId>], per_cell: &HashMap<StateId, PerCell>) {
+        let mut owned: BTreeMap<StateId, Vec<usize>> = BTreeMap::new();
+        for (index, cell) in world_map.cells.iter_mut().enumerate() {
+            cell.state = owners[index];
+            if let Some(owner) = owners[index] {
+                owned.entry(owner).or_default().push(index);
+            }
+        }
+        
+        let cells = &world_map.cells;
+        let burgs = &world_map.burgs;
+        for state in world_map.states.iter_mut() {
+            let indices = owned.get(&state.id).map(Vec::as_slice).unwrap_or(&[]);
+            state.cells = indices.iter().map(|&index| cells[index].id).collect();
+            state.burgs = burgs.iter().filter(|burg| burg.state == state.id).count() as u32;
+            
+            if !indices.is_empty() {
+                let sum = indices
+                    .iter()
+                    .fold(nalgebra::Vector2::zeros(), |sum, &index| sum + cells[index].coordinates.coords);
+                state.center = Point2::from(sum / indices.len() as f32);
+            }
+            
+            if let Some(values) = per_cell.get(&state.id) {
+                let count = indices.len() as f32;
+                state.area = values.area * count;
+                state.population = (values.population * count).round() as u32;
+                state.rural = values.rural * count;
+                state.urban = values.urban * count;
+            }
+        }
+    }
+    
+    /// Pairs of distinct states owning adjacent cells, lower id first
+    fn adjacent_states(owners: &[Option<StateId>], neighbours: &[Vec<usize>]) -> BTreeSet<(StateId, StateId)> {
+        let mut pairs = BTreeSet::new();
+        for (cell, owner) in owners.iter().enumerate() {
+            let Some(owner) = *owner else { continue };
+            for &other in &neighbours[cell] {
+                if let Some(other_owner) = owners[other] {
+                    if other_owner != owner {
+                        pairs.insert((owner.min(other_owner), owner.max(other_owner)));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+    
+    fn strength(rng: &mut StdRng, world_map: &WorldMap, owners: &[Option<StateId>], state: StateId) -> f32 {
+        let cells = owners.iter().filter(|&&owner| owner == Some(state)).count() as f32;
+        let expansionism = world_map
+            .state(state)
+            .map(|candidate| candidate.expansionism)
+            .unwrap_or(1.0);
+        cells * (0.5 + expansionism.max(0.0)) * rng.gen_range(0.5..1.5)
+    }
+    
+    fn involves(war: &War, a: StateId, b: StateId) -> bool {
+        (war.attacker == a && war.defender == b) || (war.attacker == b && war.defender == a)
+    }
+    
+    fn name_of(world_map: &WorldMap, state: StateId) -> String {
+        world_map
+            .state(state)
+            .map(|candidate| candidate.name.clone())
+            .unwrap_or_else(|| format!("State {}", state))
+    }
+    
+    /// Compass prefix for a realm lying at `heir` as seen from `seat`
+    fn direction_prefix(seat: Point2<f32>, heir: Point2<f32>) -> &'static str {
+        let delta = heir - seat;
+        if delta.x.abs() >= delta.y.abs() {
+            if delta.x >= 0.0 { COMPASS_PREFIXES[2] } else { COMPASS_PREFIXES[3] }
+        } else if delta.y >= 0.0 {
+            // Map y grows southwards
+            COMPASS_PREFIXES[1]
+        } else {
+            COMPASS_PREFIXES[0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    fn params(years: u32, war_frequency: f32, dynastic_split_chance: f32) -> HistoryParams {
+        HistoryParams { start_year: 100, years, snapshot_interval: 10, war_frequency, dynastic_split_chance }
+    }
+    
+    #[test]
+    fn the_timeline_leads_to_the_final_map() {
+        let mut world_map = grid_world(12);
+        let mut again = world_map.clone();
+        HistorySimulator::new(params(60, 0.3, 0.02), 3).simulate(&mut world_map);
+        HistorySimulator::new(params(60, 0.3, 0.02), 3).simulate(&mut again);
+        
+        let owners: Vec<Option<StateId>> = world_map.cells.iter().map(|cell| cell.state).collect();
+        assert_eq!(owners, again.cells.iter().map(|cell| cell.state).collect::<Vec<_>>());
+        
+        let history = &world_map.history;
+        assert_eq!((history.start_year, history.end_year), (100, 160));
+        let years: Vec<i32> = history.snapshots.iter().map(|snapshot| snapshot.year).collect();
+        assert_eq!(years, [100, 110, 120, 130, 140, 150, 160]);
+        assert!(history.snapshots.iter().all(|snapshot| snapshot.cell_states.len() == world_map.cells.len()));
+        assert_eq!(history.snapshots.last().unwrap().cell_states, owners);
+        assert!(!history.events.is_empty());
+        assert!(history.events.windows(2).all(|pair| pair[0].year <= pair[1].year));
+        assert!(history.events.iter().all(|event| (101..=160).contains(&event.year)));
+        
+        for state in &world_map.states {
+            let owned: Vec<CellId> = world_map.cells.iter().filter(|cell| cell.state == Some(state.id)).map(|cell| cell.id).collect();
+            assert_eq!(state.cells, owned);
+        }
+        for burg in &world_map.burgs {
+            assert!(world_map.state(burg.state).is_some(), "{} follows a state that no longer exists", burg.id);
+        }
+    }
+    
+    #[test]
+    fn heirs_inherit_each_side_of_a_relation() {
+        let mut world_map = grid_world(12);
+        world_map.diplomacy.set(StateId(1), StateId(2), DiplomaticStatus::Suspicion);
+        world_map.diplomacy.set(StateId(2), StateId(1), DiplomaticStatus::Friendly);
+        HistorySimulator::new(params(1, 0.0, 1.0), 3).simulate(&mut world_map);
+        
+        let split = world_map
+            .history
+            .events
+            .iter()
+            .find(|event| event.kind == HistoricalEventKind::DynasticSplit && event.states[1] == StateId(1))
+            .expect("state 1 splits");
+        let heir = split.states[0];
+        let diplomacy = &world_map.diplomacy;
+        assert_eq!(diplomacy.status(heir, StateId(2)), DiplomaticStatus::Suspicion);
+        assert_eq!(diplomacy.status(StateId(2), heir), DiplomaticStatus::Friendly);
+        assert_eq!(diplomacy.status(heir, StateId(1)), DiplomaticStatus::Rival);
+        
+        let name = &world_map.state(heir).unwrap().name;
+        assert!(COMPASS_PREFIXES.iter().any(|prefix| name.starts_with(prefix)), "{}", name);
+        assert!(split.cells.iter().all(|&cell| world_map.cell(cell).unwrap().state == Some(heir)));
+    }
+    
+    #[test]
+    fn heirs_are_named_after_where_they_lie() {
+        let seat = Point2::new(50.0, 50.0);
+        assert_eq!(HistorySimulator::direction_prefix(seat, Point2::new(90.0, 60.0)), "Eastern");
+        assert_eq!(HistorySimulator::direction_prefix(seat, Point2::new(40.0, 90.0)), "Southern");
+        assert_eq!(HistorySimulator::direction_prefix(seat, Point2::new(45.0, 0.0)), "Northern");
+    }
+}
@@ -0,0 +1,397 @@
+//! Procedural points of interest
+
+use crate::data::{Attributes, BiomeType, CellId, CellTopology, Color, HistoricalEventKind, Marker, MarkerId, MarkerType, Note, WorldMap};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Presentation of one kind of marker, following Azgaar's marker types
+struct MarkerKind {
+    type_: MarkerType,
+    /// Title of the marker notes
+    title: &'static str,
+    icon: &'static str,
+    fill: Color,
+    /// Land cells per marker of this kind
+    rarity: f32,
+}
+
+const VOLCANOES: MarkerKind = MarkerKind { type_: MarkerType::Volcanoes, title: "Volcano", icon: "🌋", fill: Color::rgb(0xe6, 0xa1, 0x9e), rarity: 1500.0 };
+const HOT_SPRINGS: MarkerKind = MarkerKind { type_: MarkerType::HotSprings, title: "Hot springs", icon: "♨️", fill: Color::rgb(0x9e, 0xd3, 0xe6), rarity: 1000.0 };
+const MINES: MarkerKind = MarkerKind { type_: MarkerType::Mines, title: "Mine", icon: "⛏️", fill: Color::rgb(0xc8, 0xb8, 0x8a), rarity: 400.0 };
+const BRIDGES: MarkerKind = MarkerKind { type_: MarkerType::Bridges, title: "Bridge", icon: "🌉", fill: Color::rgb(0xa3, 0xc4, 0xe0), rarity: 600.0 };
+const INNS: MarkerKind = MarkerKind { type_: MarkerType::Inns, title: "Inn", icon: "🍻", fill: Color::rgb(0xe0, 0xc4, 0x8a), rarity: 500.0 };
+const LIGHTHOUSES: MarkerKind = MarkerKind { type_: MarkerType::Lighthouses, title: "Lighthouse", icon: "🚨", fill: Color::rgb(0xf5, 0xf5, 0xdc), rarity: 800.0 };
+const RUINS: MarkerKind = MarkerKind { type_: MarkerType::Ruins, title: "Ruins", icon: "🏺", fill: Color::rgb(0xc9, 0xb7, 0x9c), rarity: 600.0 };
+const BATTLEFIELDS: MarkerKind = MarkerKind { type_: MarkerType::Battlefields, title: "Battlefield", icon: "⚔️", fill: Color::rgb(0xd9, 0xa0, 0xa0), rarity: 500.0 };
+const DUNGEONS: MarkerKind = MarkerKind { type_: MarkerType::Dungeons, title: "Dungeon", icon: "🗝️", fill: Color::rgb(0xb0, 0xa8, 0xc8), rarity: 800.0 };
+
+const MINE_RESOURCES: [&str; 8] = ["iron", "copper", "silver", "gold", "tin", "salt", "coal", "gems"];
+
+/// Generator for volcanoes, mines, inns and other points of interest
+///
+/// Markers are placed where the terrain and settlements make sense of them:
+/// volcanoes on the highest ground, bridges where roads cross rivers, battlefields
+/// where the simulated history saw borders move. Every marker carries a short
+/// note that can serve as an adventure hook.
+pub struct MarkerGenerator {
+    seed: u64,
+}
+
+/// Terrain facts shared by the placement rules
+struct Terrain {
+    topology: Arc<CellTopology>,
+    land: Vec<bool>,
+    coastal: Vec<bool>,
+    /// Height relative to the lowest (0.0) and highest (1.0) land cell
+    altitude: Vec<f32>,
+    burg_cells: HashSet<usize>,
+}
+
+/// Markers placed so far, at most one per cell
+struct Placement {
+    markers: Vec<Marker>,
+    used: HashSet<usize>,
+}
+
+impl MarkerGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+    
+    /// Generate the markers of a world, numbered from zero
+    pub fn generate(&self, world_map: &WorldMap) -> Vec<Marker> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let terrain = Terrain::new(world_map);
+        let land_cells = terrain.land.iter().filter(|&&land| land).count();
+        let mut placement = Placement { markers: Vec::new(), used: HashSet::new() };
+        
+        let candidates = |filter: &dyn Fn(usize) -> bool| -> Vec<usize> {
+            (0..world_map.cells.len()).filter(|&index| filter(index)).collect()
+        };
+        
+        let volcanoes = candidates(&|index| terrain.land[index] && terrain.altitude[index] >= 0.75);
+        for index in Self::pick(&mut rng, volcanoes, &VOLCANOES, land_cells) {
+            let note = match rng.gen_range(0..3) {
+                0 => format!("A smoking volcano looming over {}.", nearest_burg(world_map, index)),
+                1 => format!("A dormant volcano; the people of {} say it is only sleeping.", nearest_burg(world_map, index)),
+                _ => "An active volcano, its slopes covered in black glass.".to_string(),
+            };
+            placement.add(world_map, index, &VOLCANOES, note);
+        }
+        
+        let springs = candidates(&|index| {
+            terrain.land[index] && terrain.altitude[index] >= 0.4 && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, springs, &HOT_SPRINGS, land_cells) {
+            let note = format!(
+                "Hot springs near {}, said to cure {}.",
+                nearest_burg(world_map, index),
+                ["aching joints", "the pox", "melancholy", "old wounds"].choose(&mut rng).unwrap(),
+            );
+            placement.add(world_map, index, &HOT_SPRINGS, note);
+        }
+        
+        let hills = candidates(&|index| {
+            terrain.land[index]
+                && terrain.altitude[index] >= 0.3
+                && !matches!(world_map.cells[index].biome, BiomeType::Frozen)
+                && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, hills, &MINES, land_cells) {
+            let resource = MINE_RESOURCES.choose(&mut rng).unwrap();
+            let note = if rng.gen_bool(0.25) {
+                format!("An abandoned mine that once produced {}. The miners of {} will not say why.", resource, nearest_burg(world_map, index))
+            } else {
+                format!("A mine producing {} for {}.", resource, nearest_burg(world_map, index))
+            };
+            placement.add(world_map, index, &MINES, note);
+        }
+        
+        // Bridges go where roads cross rivers, or in riverside towns
+        let river_cells: HashSet<CellId> = world_map.rivers.iter().flat_map(|river| river.cells.iter().copied()).collect();
+        let route_cells: HashSet<CellId> = world_map.routes.iter().flat_map(|route| route.cells.iter().copied()).collect();
+        let crossings = candidates(&|index| {
+            let id = world_map.cells[index].id;
+            terrain.land[index]
+                && river_cells.contains(&id)
+                && (route_cells.contains(&id) || terrain.burg_cells.contains(&index))
+                && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, crossings, &BRIDGES, land_cells) {
+            let river = world_map.rivers
+                .iter()
+                .find(|river| river.cells.contains(&world_map.cells[index].id))
+                .map(|river| river.name.as_str())
+                .unwrap_or("the river");
+            let note = format!("A stone bridge over {} near {}. A toll is taken here.", river, nearest_burg(world_map, index));
+            placement.add(world_map, index, &BRIDGES, note);
+        }
+        
+        // Inns line the roads between towns; without roads they sit outside town gates
+        let roadside = candidates(&|index| {
+            let id = world_map.cells[index].id;
+            let on_road = if route_cells.is_empty() {
+                terrain.topology.neighbours[index].iter().any(|other| terrain.burg_cells.contains(other))
+            } else {
+                route_cells.contains(&id)
+            };
+            terrain.land[index] && on_road && !terrain.burg_cells.contains(&index) && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, roadside, &INNS, land_cells) {
+            let note = format!(
+                "The {} {}, an inn on the road to {}.",
+                ["Golden", "Drunken", "Prancing", "Rusty", "Sleeping", "Laughing"].choose(&mut rng).unwrap(),
+                ["Goose", "Dragon", "Pony", "Anchor", "Giant", "Boar"].choose(&mut rng).unwrap(),
+                nearest_burg(world_map, index),
+            );
+            placement.add(world_map, index, &INNS, note);
+        }
+        
+        let harbours = candidates(&|index| {
+            terrain.land[index] && terrain.coastal[index] && !placement.used.contains(&index)
+                && world_map.burgs.iter().any(|burg| burg.port != 0 && burg.cell == world_map.cells[index].id)
+        });
+        let harbours = if harbours.is_empty() {
+            candidates(&|index| terrain.coastal[index] && terrain.burg_cells.contains(&index) && !placement.used.contains(&index))
+        } else {
+            harbours
+        };
+        for index in Self::pick(&mut rng, harbours, &LIGHTHOUSES, land_cells) {
+            let note = format!("A lighthouse guiding ships into {}.", nearest_burg(world_map, index));
+            placement.add(world_map, index, &LIGHTHOUSES, note);
+        }
+        
+        let wilderness = candidates(&|index| {
+            terrain.land[index]
+                && !terrain.burg_cells.contains(&index)
+                && !matches!(world_map.cells[index].biome, BiomeType::Frozen)
+                && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, wilderness, &RUINS, land_cells) {
+            let note = format!(
+                "Ruins of {} near {}.",
+                ["an ancient city", "a forgotten temple", "a fallen castle", "an elven tower"].choose(&mut rng).unwrap(),
+                nearest_burg(world_map, index),
+            );
+            placement.add(world_map, index, &RUINS, note);
+        }
+        
+        self.battlefields(&mut rng, world_map, &terrain, land_cells, &mut placement);
+        
+        let depths = candidates(&|index| {
+            terrain.land[index]
+                && terrain.altitude[index] >= 0.2
+                && !terrain.burg_cells.contains(&index)
+                && !placement.used.contains(&index)
+        });
+        for index in Self::pick(&mut rng, depths, &DUNGEONS, land_cells) {
+            let note = format!(
+                "A dungeon beneath {}. {}",
+                ["a collapsed keep", "an old barrow", "a sealed cave", "a wizard's ruined tower"].choose(&mut rng).unwrap(),
+                ["Treasure is rumoured inside.", "No one has come back out.", "Strange lights are seen at night."].choose(&mut rng).unwrap(),
+            );
+            placement.add(world_map, index, &DUNGEONS, note);
+        }
+        
+        placement.markers
+    }
+    
+    /// Battlefields mark the border shifts of the simulated history, or lie on
+    /// current borders when the world has no history
+    fn battlefields(&self, rng: &mut StdRng, world_map: &WorldMap, terrain: &Terrain, land_cells: usize, placement: &mut Placement) {
+        let index_of: HashMap<CellId, usize> = world_map.cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| (cell.id, index))
+            .collect();
+        
+        let battles: Vec<(usize, String)> = world_map.history.events
+            .iter()
+            .filter(|event| matches!(event.kind, HistoricalEventKind::BorderShift | HistoricalEventKind::Annexation))
+            .filter_map(|event| {
+                let index = *index_of.get(event.cells.choose(rng)?)?;
+                Some((index, format!("Battlefield of the year {}: {}.", event.year, event.description)))
+            })
+            .filter(|(index, _)| terrain.land[*index])
+            .collect();
+        
+        let mut battles = if battles.is_empty() {
+            (0..world_map.cells.len())
+                .filter(|&index| {
+                    let state = world_map.cells[index].state;
+                    terrain.land[index]
+                        && state.is_some()
+                        && terrain.topology.neighbours[index].iter().any(|&other| terrain.land[other] && world_map.cells[other].state != state)
+                })
+                .map(|index| (index, format!("An old battlefield near {}, littered with rusted arms.", nearest_burg(world_map, index))))
+                .collect()
+        } else {
+            battles
+        };
+        
+        battles.shuffle(rng);
+        let count = Self::count(&BATTLEFIELDS, land_cells);
+        let mut placed = 0;
+        for (index, note) in battles {
+            if placed >= count {
+                break;
+            }
+            if placement.used.insert(index) {
+                placement.add(world_map, index, &BATTLEFIELDS, note);
+                placed += 1;
+            }
+        }
+    }
+    
+    /// Random selection of candidate cells, as many as the kind's rarity allows
+    fn pick(rng: &mut StdRng, mut candidates: Vec<usize>, kind: &MarkerKind, land_cells: usize) -> Vec<usize> {
+        candidates.shuffle(rng);
+        candidates.truncate(Self::count(kind, land_cells));
+        candidates
+    }
+    
+    fn count(kind: &MarkerKind, land_cells: usize) -> usize {
+        if land_cells == 0 {
+            return 0;
+        }
+        ((land_cells as f32 / kind.rarity).round() as usize).max(1)
+    }
+}
+
+impl Terrain {
+    fn new(world_map: &WorldMap) -> Self {
+        let cells = &world_map.cells;
+        let topology = world_map.topology();
+        let neighbours = &topology.neighbours;
+        let land: Vec<bool> = cells
+            .iter()
+            .map(|cell| !matches!(cell.biome, BiomeType::Marine | BiomeType::Freshwater))
+            .collect();
+        let coastal = (0..cells.len())
+            .map(|index| land[index] && neighbours[index].iter().any(|&other| matches!(cells[other].biome, BiomeType::Marine)))
+            .collect();
+        
+        let (min_height, max_height) = cells
+            .iter()
+            .zip(&land)
+            .filter(|(_, &land)| land)
+            .fold((f32::MAX, f32::MIN), |(min, max), (cell, _)| (min.min(cell.height), max.max(cell.height)));
+        let relief = (max_height - min_height).max(f32::EPSILON);
+        let altitude = cells.iter().map(|cell| ((cell.height - min_height) / relief).clamp(0.0, 1.0)).collect();
+        
+        let burg_ids: HashSet<CellId> = world_map.burgs.iter().map(|burg| burg.cell).collect();
+        let burg_cells = (0..cells.len()).filter(|&index| burg_ids.contains(&cells[index].id)).collect();
+        
+        Self { topology, land, coastal, altitude, burg_cells }
+    }
+}
+
+impl Placement {
+    fn add(&mut self, world_map: &WorldMap, index: usize, kind: &MarkerKind, note: String) {
+        let cell = &world_map.cells[index];
+        let id = MarkerId(self.markers.len() as u32);
+        self.used.insert(index);
+        self.markers.push(Marker {
+            id,
+            icon: kind.icon.to_string(),
+            type_: kind.type_.clone(),
+            dx: 50.0,
+            dy: 50.0,
+            x: cell.coordinates.x,
+            y: cell.coordinates.y,
+            cell: cell.id,
+            i: id.0,
+            size: 30.0,
+            fill: kind.fill.clone(),
+            stroke: Color::rgb(0, 0, 0),
+            note: Some(Note::new(kind.title, note)),
+            attributes: Attributes::new(),
+        });
+    }
+}
+
+/// Name of the burg closest to a cell, for use in notes
+fn nearest_burg(world_map: &WorldMap, index: usize) -> &str {
+    let position = world_map.cells[index].coordinates;
+    world_map.burgs
+        .iter()
+        .min_by(|a, b| {
+            let distance_a = (a.x - position.x).powi(2) + (a.y - position.y).powi(2);
+            let distance_b = (b.x - position.x).powi(2) + (b.y - position.y).powi(2);
+            distance_a.total_cmp(&distance_b)
+        })
+        .map(|burg| burg.name.as_str())
+        .unwrap_or("the wilds")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{HistoricalEvent, River, RiverId, RiverType, Route, RouteId, StateId};
+    
+    #[test]
+    fn markers_are_seeded_and_stand_alone_on_land() {
+        let world_map = grid_world(10);
+        let markers = MarkerGenerator::new(9).generate(&world_map);
+        let again = MarkerGenerator::new(9).generate(&world_map);
+        let placed = |markers: &[Marker]| markers.iter().map(|marker| (marker.cell, marker.type_.clone())).collect::<Vec<_>>();
+        assert_eq!(placed(&markers), placed(&again));
+        
+        let cells: HashSet<CellId> = markers.iter().map(|marker| marker.cell).collect();
+        assert_eq!(cells.len(), markers.len());
+        for (index, marker) in markers.iter().enumerate() {
+            assert_eq!(marker.id, MarkerId(index as u32));
+            assert_ne!(world_map.cell(marker.cell).unwrap().biome, BiomeType::Marine);
+            assert!(marker.note.as_ref().is_some_and(|note| !note.legend.is_empty()));
+        }
+        
+        let volcano = markers.iter().find(|marker| marker.type_ == MarkerType::Volcanoes).expect("a volcano");
+        assert!(world_map.cell(volcano.cell).unwrap().coordinates.x > 80.0, "volcanoes rise on the highest ground");
+    }
+    
+    #[test]
+    fn bridges_and_battlefields_follow_rivers_roads_and_history() {
+        let mut world_map = grid_world(10);
+        world_map.rivers.push(River {
+            id: RiverId(1),
+            source: CellId(31),
+            mouth: CellId(32),
+            discharge: 1.0,
+            length: 10.0,
+            width: 1.0,
+            cells: vec![CellId(31), CellId(32)],
+            basin: RiverId(1),
+            name: "Swift".to_string(),
+            type_: RiverType::River,
+            note: None,
+            attributes: Default::default(),
+        });
+        world_map.routes.push(Route {
+            id: RouteId(1),
+            group: 0,
+            cells: vec![CellId(42), CellId(32), CellId(22)],
+            feature: Default::default(),
+            length: 20.0,
+        });
+        world_map.history.events.push(HistoricalEvent {
+            year: 120,
+            kind: HistoricalEventKind::BorderShift,
+            states: vec![StateId(1), StateId(3)],
+            cells: vec![CellId(53)],
+            description: "State 1 took 1 cells from State 3".to_string(),
+        });
+        
+        let markers = MarkerGenerator::new(9).generate(&world_map);
+        let of_type = |type_: MarkerType| markers.iter().find(|marker| marker.type_ == type_).unwrap();
+        let bridge = of_type(MarkerType::Bridges);
+        assert_eq!(bridge.cell, CellId(32));
+        assert!(bridge.note.as_ref().unwrap().legend.contains("Swift"));
+        let battlefield = of_type(MarkerType::Battlefields);
+        assert_eq!(battlefield.cell, CellId(53));
+        assert!(battlefield.note.as_ref().unwrap().legend.contains("year 120"));
+    }
+}
@@ -1,5 +1,19 @@
 //! World generation algorithms and utilities
 
+mod diplomacy;
+mod heraldry;
+mod history;
+mod markers;
+mod population;
+mod region;
+
+pub use diplomacy::*;
+pub use heraldry::*;
+pub use history::*;
+pub use markers::*;
+pub use population::*;
+pub use region::*;
+
 use crate::{WorldMap, Result};
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +28,12 @@ pub struct GenerationParams {
     pub biome_params: BiomeParams,
     pub culture_params: CultureParams,
     pub political_params: PoliticalParams,
+    #[serde(default)]
+    pub population_params: PopulationParams,
+    #[serde(default)]
+    pub history_params: HistoryParams,
+    #[serde(default)]
+    pub region_params: RegionParams,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +75,42 @@ pub struct PoliticalParams {
     pub border_stability: f32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PopulationParams {
+    /// Rural population points of a temperate cell away from water
+    pub cell_capacity: f32,
+    /// Capacity gained by cells on the largest river
+    pub river_bonus: f32,
+    /// Capacity gained by coastal cells
+    pub coast_bonus: f32,
+    /// Urban population points per point of capacity around a burg
+    pub urban_share: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryParams {
+    pub start_year: i32,
+    pub years: u32,
+    pub snapshot_interval: u32,
+    /// Yearly chance of war between hostile neighbours
+    pub war_frequency: f32,
+    /// Yearly chance of a large state splitting between heirs
+    pub dynastic_split_chance: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionParams {
+    /// Map units of the sub-map per map unit of the source
+    pub scale: f32,
+    /// Sub-map cells per source cell
+    pub subdivision: u32,
+    /// Strength of the detail noise, as a share of the source height range
+    pub detail_amplitude: f32,
+    /// Size of the coarsest detail noise features, in sub-map units
+    pub detail_wavelength: f32,
+    pub detail_octaves: u32,
+}
+
 /// World generator
 pub struct WorldGenerator {
     params: GenerationParams,
@@ -95,6 +151,38 @@ impl WorldGenerator {
             "Climate generation not yet implemented".to_string()
         ))
     }
+    
+    /// Generate diplomatic relations between the states of an existing world
+    pub fn generate_diplomacy(&self, world_map: &WorldMap) -> Result<crate::data::DiplomacyMatrix> {
+        let generator = DiplomacyGenerator::new(self.params.political_params.clone(), self.params.seed);
+        Ok(generator.generate(world_map))
+    }
+    
+    /// Distribute population over the cells and burgs of an existing world
+    pub fn generate_population(&self, world_map: &mut WorldMap) -> Result<()> {
+        let model = PopulationModel::new(self.params.population_params.clone());
+        model.populate(world_map);
+        Ok(())
+    }
+    
+    /// Generate points of interest for an existing world
+    pub fn generate_markers(&self, world_map: &WorldMap) -> Result<Vec<crate::data::Marker>> {
+        let generator = MarkerGenerator::new(self.params.seed);
+        Ok(generator.generate(world_map))
+    }
+    
+    /// Simulate the political history of an existing world, evolving its states
+    pub fn simulate_history(&self, world_map: &mut WorldMap) -> Result<()> {
+        let simulator = HistorySimulator::new(self.params.history_params.clone(), self.params.seed);
+        simulator.simulate(world_map);
+        Ok(())
+    }
+    
+    /// Build a detailed sub-map of one region of an existing world
+    pub fn extract_region(&self, world_map: &WorldMap, region: &Region) -> Result<WorldMap> {
+        let extractor = RegionExtractor::new(self.params.region_params.clone(), self.params.seed);
+        extractor.extract(world_map, region)
+    }
 }
 
 /// Climate data for the world
@@ -148,6 +236,44 @@ impl Default for GenerationParams {
                 expansion_aggressiveness: 1.0,
                 border_stability: 0.8,
             },
+            population_params: PopulationParams::default(),
+            history_params: HistoryParams::default(),
+            region_params: RegionParams::default(),
+        }
+    }
+}
+
+impl Default for PopulationParams {
+    fn default() -> Self {
+        Self {
+            cell_capacity: 10.0,
+            river_bonus: 1.0,
+            coast_bonus: 0.5,
+            urban_share: 0.15,
+        }
+    }
+}
+
+impl Default for HistoryParams {
+    fn default() -> Self {
+        Self {
+            start_year: 0,
+            years: 300,
+            snapshot_interval: 25,
+            war_frequency: 0.02,
+            dynastic_split_chance: 0.002,
+        }
+    }
+}
+
+impl Default for RegionParams {
+    fn default() -> Self {
+        Self {
+            scale: 4.0,
+            subdivision: 8,
+            detail_amplitude: 0.05,
+            detail_wavelength: 32.0,
+            detail_octaves: 4,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,214 @@
+//! Population distribution over cells and settlements
+
+use super::PopulationParams;
+use crate::data::{BiomeType, CellId, CultureId, StateId, WorldMap};
+use std::collections::HashMap;
+
+/// Capitals draw people from the whole state
+const CAPITAL_MULTIPLIER: f32 = 1.3;
+
+/// Ports grow on trade
+const PORT_MULTIPLIER: f32 = 1.3;
+
+/// Share of the capacity lost on the highest land of the map
+const ALTITUDE_PENALTY: f32 = 0.6;
+
+/// Carrying-capacity population model
+///
+/// Each land cell supports a number of people given by its biome, altitude and
+/// access to rivers and coast. That rural population is stored in
+/// `Cell::population`; settlements take an urban population from the capacity
+/// of their hinterland. Counts are in people: capacities are in population
+/// points, scaled by `MapSettings::population_rate`, and urban counts further
+/// by `MapSettings::urbanization`.
+pub struct PopulationModel {
+    params: PopulationParams,
+}
+
+impl PopulationModel {
+    pub fn new(params: PopulationParams) -> Self {
+        Self { params }
+    }
+    
+    /// Fill cell and burg populations, then refresh state and culture totals
+    pub fn populate(&self, world_map: &mut WorldMap) {
+        let neighbours = world_map.topology().neighbours.clone();
+        let capacities = self.capacities(world_map, &neighbours);
+        let settings = &world_map.metadata.settings;
+        let rate = settings.population_rate;
+        let urbanization = settings.urbanization;
+        
+        for (cell, capacity) in world_map.cells.iter_mut().zip(&capacities) {
+            cell.population = (capacity * self.params.cell_capacity * rate).round() as u32;
+        }
+        
+        let index_of: HashMap<CellId, usize> = world_map.cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| (cell.id, index))
+            .collect();
+        
+        for burg in world_map.burgs.iter_mut() {
+            let Some(&index) = index_of.get(&burg.cell) else {
+                continue;
+            };
+            let hinterland: f32 = capacities[index]
+                + neighbours[index].iter().map(|&other| capacities[other]).sum::<f32>();
+            
+            let mut points = hinterland * self.params.cell_capacity * self.params.urban_share;
+            if burg.capital {
+                points *= CAPITAL_MULTIPLIER;
+            }
+            if burg.port != 0 {
+                points *= PORT_MULTIPLIER;
+            }
+            burg.population = points * rate * urbanization;
+        }
+        
+        Self::update_totals(world_map);
+    }
+    
+    /// Recompute the rural, urban and total population of states and cultures
+    /// from their cells and burgs
+    pub fn update_totals(world_map: &mut WorldMap) {
+        let mut state_totals: HashMap<StateId, (f32, f32)> = HashMap::new();
+        let mut culture_totals: HashMap<CultureId, (f32, f32)> = HashMap::new();
+        
+        for cell in &world_map.cells {
+            let rural = cell.population as f32;
+            if let Some(state) = cell.state {
+                state_totals.entry(state).or_default().0 += rural;
+            }
+            if let Some(culture) = cell.culture {
+                culture_totals.entry(culture).or_default().0 += rural;
+            }
+        }
+        for burg in &world_map.burgs {
+            state_totals.entry(burg.state).or_default().1 += burg.population;
+            culture_totals.entry(burg.culture).or_default().1 += burg.population;
+        }
+        
+        for state in world_map.states.iter_mut() {
+            let (rural, urban) = state_totals.get(&state.id).copied().unwrap_or_default();
+            state.rural = rural;
+            state.urban = urban;
+            state.population = (rural + urban).round() as u32;
+        }
+        for culture in world_map.cultures.iter_mut() {
+            let (rural, urban) = culture_totals.get(&culture.id).copied().unwrap_or_default();
+            culture.rural = rural;
+            culture.urban = urban;
+        }
+    }
+    
+    /// Relative carrying capacity of every cell, 1.0 being an average
+    /// temperate cell away from water
+    fn capacities(&self, world_map: &WorldMap, neighbours: &[Vec<usize>]) -> Vec<f32> {
+        let cells = &world_map.cells;
+        
+        let is_land = |biome: BiomeType| !matches!(biome, BiomeType::Marine | BiomeType::Freshwater);
+        let (min_height, max_height) = cells
+            .iter()
+            .filter(|cell| is_land(cell.biome))
+            .fold((f32::MAX, f32::MIN), |(min, max), cell| (min.min(cell.height), max.max(cell.height)));
+        let relief = (max_height - min_height).max(f32::EPSILON);
+        
+        // Largest discharge of the rivers crossing each cell
+        let mut discharges: HashMap<CellId, f32> = HashMap::new();
+        for river in &world_map.rivers {
+            for &cell in &river.cells {
+                let discharge = discharges.entry(cell).or_insert(0.0);
+                *discharge = discharge.max(river.discharge);
+            }
+        }
+        let max_discharge = discharges.values().copied().fold(0.0f32, f32::max).max(f32::EPSILON);
+        
+        cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                if !is_land(cell.biome) {
+                    return 0.0;
+                }
+                
+                let altitude = ((cell.height - min_height) / relief).clamp(0.0, 1.0);
+                let capacity = Self::habitability(cell.biome) * (1.0 - ALTITUDE_PENALTY * altitude * altitude);
+                
+                let mut bonus = 0.0;
+                if let Some(discharge) = discharges.get(&cell.id) {
+                    bonus += self.params.river_bonus * (discharge / max_discharge).max(0.0).sqrt();
+                }
+                let shore = neighbours[index]
+                    .iter()
+                    .map(|&other| match cells[other].biome {
+                        BiomeType::Marine => self.params.coast_bonus,
+                        // Lakeshores are half as attractive as the sea coast
+                        BiomeType::Freshwater => self.params.coast_bonus * 0.5,
+                        _ => 0.0,
+                    })
+                    .fold(0.0f32, f32::max);
+                bonus += shore;
+                
+                capacity * (1.0 + bonus)
+            })
+            .collect()
+    }
+    
+    fn habitability(biome: BiomeType) -> f32 {
+        match biome {
+            BiomeType::Temperate => 1.0,
+            BiomeType::Hot => 0.7,
+            BiomeType::Cold => 0.3,
+            BiomeType::Dry => 0.15,
+            BiomeType::Frozen => 0.02,
+            BiomeType::Marine | BiomeType::Freshwater => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    
+    fn params() -> PopulationParams {
+        PopulationParams { cell_capacity: 100.0, river_bonus: 0.5, coast_bonus: 0.5, urban_share: 0.2 }
+    }
+    
+    #[test]
+    fn rural_population_follows_biome_altitude_and_coast() {
+        let mut world_map = grid_world(8);
+        world_map.cells[3 * 8 + 3].biome = BiomeType::Dry;
+        PopulationModel::new(params()).populate(&mut world_map);
+        let population = |x: usize, y: usize| world_map.cells[y * 8 + x].population;
+        
+        assert_eq!(population(0, 4), 0);
+        assert!(population(1, 4) > population(2, 4), "the coast is more crowded than inland");
+        assert!(population(2, 4) > population(6, 4), "highlands are less crowded than lowlands");
+        assert!(population(3, 3) < population(3, 4), "deserts are less crowded than temperate land");
+    }
+    
+    #[test]
+    fn settings_scale_populations_and_totals_add_up() {
+        let mut world_map = grid_world(8);
+        let mut scaled = world_map.clone();
+        scaled.metadata.settings.population_rate = 2.0;
+        scaled.metadata.settings.urbanization = 3.0;
+        PopulationModel::new(params()).populate(&mut world_map);
+        PopulationModel::new(params()).populate(&mut scaled);
+        
+        let (burg, scaled_burg) = (&world_map.burgs[4], &scaled.burgs[4]);
+        assert!(burg.population > 0.0);
+        assert!((scaled_burg.population - burg.population * 6.0).abs() < 1e-2 * burg.population);
+        
+        for state in &world_map.states {
+            let rural: u32 = world_map.cells.iter().filter(|cell| cell.state == Some(state.id)).map(|cell| cell.population).sum();
+            let urban: f32 = world_map.burgs.iter().filter(|burg| burg.state == state.id).map(|burg| burg.population).sum();
+            assert_eq!(state.rural, rural as f32);
+            assert!((state.urban - urban).abs() < 1e-3);
+            assert_eq!(state.population, (state.rural + state.urban).round() as u32);
+        }
+        let culture = &world_map.cultures[0];
+        assert_eq!(culture.urban, world_map.burgs.iter().map(|burg| burg.population).sum::<f32>());
+    }
+}
@@ -0,0 +1,726 @@
+//! Detailed sub-maps of a region of a world
+
+use super::RegionParams;
+use crate::data::{
+    BurgId, Cell, CellId, CultureId, DatedEvent, EntityRef, Grid, Interpolation, MapMetadata, ProvinceId, RiverId,
+    RouteId, StateId, Timeline, TopologyCache, WorldMap, SCHEMA_VERSION,
+};
+use crate::{Result, WorldFoundryError};
+use chrono::Utc;
+use nalgebra::{Point2, Vector2};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Largest sub-map heightmap, in pixels, `RegionExtractor` builds
+pub const MAX_REGION_PIXELS: u64 = 1 << 26;
+
+/// Largest number of cells `RegionExtractor` builds for a sub-map
+pub const MAX_REGION_CELLS: usize = 1 << 22;
+
+/// Area of a world map, in map coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Rect { x: f32, y: f32, width: f32, height: f32 },
+    /// Closed polygon; the last point connects back to the first
+    Polygon(Vec<Point2<f32>>),
+}
+
+/// Builds a higher-resolution world map of one region of an existing world
+///
+/// The sub-map covers the bounding box of the region, scaled up by
+/// `RegionParams::scale`. Its heightmap is resampled from the source and
+/// roughened with detail noise, and the region is covered by
+/// `RegionParams::subdivision` times as many cells, each inheriting climate,
+/// culture and ownership from the source cell beneath it. States, provinces,
+/// cultures and features keep their ids; burgs, markers, rivers and routes are
+/// clipped to the region. States whose capital lies outside the region are
+/// left with capital 0, the id references use for "none".
+///
+/// Extraction fails rather than build a heightmap of more than
+/// `MAX_REGION_PIXELS` pixels or more than `MAX_REGION_CELLS` cells.
+pub struct RegionExtractor {
+    params: RegionParams,
+    seed: u64,
+}
+
+/// Placement of the region's bounding box in the sub-map, and the size of
+/// the sub-map it fills
+struct Frame {
+    origin: Point2<f32>,
+    scale: f32,
+    width: u32,
+    height: u32,
+}
+
+impl Region {
+    /// Bounding box as minimum and maximum corners
+    pub fn bounds(&self) -> (Point2<f32>, Point2<f32>) {
+        match self {
+            Region::Rect { x, y, width, height } => (Point2::new(*x, *y), Point2::new(x + width, y + height)),
+            Region::Polygon(points) => points.iter().fold(
+                (Point2::new(f32::MAX, f32::MAX), Point2::new(f32::MIN, f32::MIN)),
+                |(min, max), point| {
+                    (Point2::new(min.x.min(point.x), min.y.min(point.y)), Point2::new(max.x.max(point.x), max.y.max(point.y)))
+                },
+            ),
+        }
+    }
+    
+    pub fn contains(&self, point: Point2<f32>) -> bool {
+        match self {
+            Region::Rect { .. } => {
+                let (min, max) = self.bounds();
+                point.x >= min.x && point.x <= max.x && point.y >= min.y && point.y <= max.y
+            }
+            // Even-odd rule
+            Region::Polygon(points) => {
+                let mut inside = false;
+                for (index, a) in points.iter().enumerate() {
+                    let b = points[(index + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+    
+    pub fn area(&self) -> f32 {
+        match self {
+            Region::Rect { width, height, .. } => (width * height).abs(),
+            // Shoelace formula
+            Region::Polygon(points) => {
+                let twice: f32 = points
+                    .iter()
+                    .enumerate()
+                    .map(|(index, a)| a.coords.perp(&points[(index + 1) % points.len()].coords))
+                    .sum();
+                twice.abs() / 2.0
+            }
+        }
+    }
+}
+
+impl Frame {
+    fn to_sub(&self, point: Point2<f32>) -> Point2<f32> {
+        Point2::from((point - self.origin) * self.scale)
+    }
+    
+    fn to_source(&self, point: Point2<f32>) -> Point2<f32> {
+        self.origin + point.coords / self.scale
+    }
+}
+
+impl RegionExtractor {
+    pub fn new(params: RegionParams, seed: u64) -> Self {
+        Self { params, seed }
+    }
+    
+    /// Build the sub-map of a region
+    pub fn extract(&self, world_map: &WorldMap, region: &Region) -> Result<WorldMap> {
+        let (min, max) = region.bounds();
+        let min = Point2::new(min.x.max(0.0), min.y.max(0.0));
+        let max = Point2::new(max.x.min(world_map.metadata.width as f32), max.y.min(world_map.metadata.height as f32));
+        if max.x <= min.x || max.y <= min.y || region.area() <= 0.0 {
+            return Err(WorldFoundryError::Generation("Region does not overlap the map".to_string()));
+        }
+        
+        let scale = self.params.scale.max(1.0);
+        let width = ((max.x - min.x) * scale).round().max(1.0) as u32;
+        let height = ((max.y - min.y) * scale).round().max(1.0) as u32;
+        if width as u64 * height as u64 > MAX_REGION_PIXELS {
+            return Err(WorldFoundryError::Generation(format!(
+                "Sub-map of {}×{} pixels exceeds the limit of {} pixels",
+                width, height, MAX_REGION_PIXELS
+            )));
+        }
+        let frame = Frame { origin: min, scale, width, height };
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        
+        let heightmap = self.refine_heightmap(world_map, &frame, &mut rng);
+        let (cells, sources) = self.subdivide_cells(world_map, region, &frame, &heightmap, &mut rng)?;
+        
+        let mut settings = world_map.metadata.settings.clone();
+        settings.distance_scale /= scale;
+        let metadata = MapMetadata {
+            id: Uuid::new_v4(),
+            name: format!("{} (region)", world_map.metadata.name),
+            version: world_map.metadata.version.clone(),
+            schema_version: SCHEMA_VERSION,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            width,
+            height,
+            seed: self.seed,
+            settings,
+            notes: world_map.metadata.notes.clone(),
+        };
+        
+        let mut sub_map = WorldMap {
+            metadata,
+            heightmap,
+            cells,
+            features: Vec::new(),
+            cultures: Vec::new(),
+            states: Vec::new(),
+            provinces: Vec::new(),
+            burgs: Vec::new(),
+            rivers: Vec::new(),
+            routes: Vec::new(),
+            markers: Vec::new(),
+            zones: Vec::new(),
+            diplomacy: world_map.diplomacy.clone(),
+            history: Timeline::default(),
+            calendar: world_map.calendar.clone(),
+            events: Vec::new(),
+            topology: TopologyCache::default(),
+        };
+        
+        self.carry_over_entities(world_map, &mut sub_map, region, &frame, &sources);
+        
+        // Rebuild state cell lists and aggregates for the clipped territory
+        sub_map.repair();
+        Ok(sub_map)
+    }
+    
+    /// Source heights resampled to the sub-map, plus detail noise
+    fn refine_heightmap(&self, world_map: &WorldMap, frame: &Frame, rng: &mut StdRng) -> Grid<f32> {
+        let (width, height) = (frame.width, frame.height);
+        let source = world_map.heightmap_at_map_size();
+        if source.is_empty() {
+            return Grid::new(width, height, 0.0);
+        }
+        
+        let relief = source.min_max().map_or(0.0, |(min, max)| max - min);
+        let amplitude = relief * self.params.detail_amplitude;
+        let noise_seed: u64 = rng.gen();
+        let wavelength = self.params.detail_wavelength.max(1.0);
+        
+        Grid::from_fn(width, height, |x, y| {
+            let centre = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let point = frame.to_source(centre);
+            let base = source.sample(point.x - 0.5, point.y - 0.5, Interpolation::Bicubic);
+            base + amplitude * fractal_noise(noise_seed, centre / wavelength, self.params.detail_octaves)
+        })
+    }
+    
+    /// Jittered grid of cells over the region, each with the index of the
+    /// source cell it inherits from
+    fn subdivide_cells(
+        &self,
+        world_map: &WorldMap,
+        region: &Region,
+        frame: &Frame,
+        heightmap: &Grid<f32>,
+        rng: &mut StdRng,
+    ) -> Result<(Vec<Cell>, Vec<usize>)> {
+        let (width, height) = (frame.width, frame.height);
+        let source_cells = world_map.cells.iter().filter(|cell| region.contains(cell.coordinates)).count();
+        if source_cells == 0 {
+            return Err(WorldFoundryError::Generation("Region contains no cells".to_string()));
+        }
+        let target = source_cells.saturating_mul(self.params.subdivision.max(1) as usize);
+        if target > MAX_REGION_CELLS {
+            return Err(WorldFoundryError::Generation(format!(
+                "Sub-map of {} cells exceeds the limit of {} cells",
+                target, MAX_REGION_CELLS
+            )));
+        }
+        let target = target as f32;
+        let spacing = (region.area() * frame.scale * frame.scale / target).sqrt().max(f32::EPSILON);
+        
+        let topology = world_map.topology();
+        let mut positions = Vec::new();
+        let mut sources = Vec::new();
+        let (columns, rows) = ((width as f32 / spacing).ceil() as u32, (height as f32 / spacing).ceil() as u32);
+        for row in 0..rows {
+            for column in 0..columns {
+                let jitter = Vector2::new(rng.gen_range(-0.4..0.4), rng.gen_range(-0.4..0.4)) * spacing;
+                let point = Point2::new((column as f32 + 0.5) * spacing, (row as f32 + 0.5) * spacing) + jitter;
+                if point.x < 0.0 || point.y < 0.0 || point.x >= width as f32 || point.y >= height as f32 {
+                    continue;
+                }
+                let source_point = frame.to_source(point);
+                if !region.contains(source_point) {
+                    continue;
+                }
+                if let Some(source) = topology.cell_at(source_point) {
+                    positions.push(point);
+                    sources.push(source);
+                }
+            }
+        }
+        
+        // Rural population is shared among the cells refining a source cell,
+        // the first ones taking one more each until the remainder is used up
+        let mut shares: HashMap<usize, u32> = HashMap::new();
+        for &source in &sources {
+            *shares.entry(source).or_default() += 1;
+        }
+        let mut handed_out: HashMap<usize, u32> = HashMap::new();
+        
+        let cells = positions
+            .iter()
+            .zip(&sources)
+            .enumerate()
+            .map(|(index, (&position, &source))| {
+                let original = &world_map.cells[source];
+                let share = shares[&source];
+                let rank = handed_out.entry(source).or_default();
+                let population = original.population / share + (*rank < original.population % share) as u32;
+                *rank += 1;
+                Cell {
+                    id: CellId(index as u32),
+                    coordinates: position,
+                    height: heightmap.sample(position.x - 0.5, position.y - 0.5, Interpolation::Bilinear),
+                    population,
+                    ..original.clone()
+                }
+            })
+            .collect();
+        Ok((cells, sources))
+    }
+    
+    fn carry_over_entities(
+        &self,
+        world_map: &WorldMap,
+        sub_map: &mut WorldMap,
+        region: &Region,
+        frame: &Frame,
+        sources: &[usize],
+    ) {
+        let topology = sub_map.topology();
+        let cell_at = |point: Point2<f32>| {
+            topology.cell_at(frame.to_sub(point)).map_or(CellId(0), |index| sub_map.cells[index].id)
+        };
+        
+        // Sub-map cells refining each source cell
+        let mut refined: HashMap<CellId, Vec<CellId>> = HashMap::new();
+        for (cell, &source) in sub_map.cells.iter().zip(sources) {
+            refined.entry(world_map.cells[source].id).or_default().push(cell.id);
+        }
+        let refine = |cells: &[CellId]| -> Vec<CellId> {
+            cells.iter().filter_map(|cell| refined.get(cell)).flatten().copied().collect()
+        };
+        
+        let burgs: Vec<_> = world_map
+            .burgs
+            .iter()
+            .filter(|burg| region.contains(Point2::new(burg.x, burg.y)))
+            .map(|burg| {
+                let position = frame.to_sub(Point2::new(burg.x, burg.y));
+                let mut burg = burg.clone();
+                burg.cell = cell_at(Point2::new(burg.x, burg.y));
+                burg.x = position.x;
+                burg.y = position.y;
+                burg
+            })
+            .collect();
+        let burg_ids: HashSet<BurgId> = burgs.iter().map(|burg| burg.id).collect();
+        
+        let state_ids: HashSet<StateId> = sub_map.cells.iter().filter_map(|cell| cell.state).collect();
+        let states: Vec<_> = world_map
+            .states
+            .iter()
+            .filter(|state| state_ids.contains(&state.id))
+            .map(|state| {
+                let mut state = state.clone();
+                state.center = frame.to_sub(state.center);
+                // A capital outside the region is not carried over
+                if !burg_ids.contains(&state.capital) {
+                    state.capital = BurgId(0);
+                }
+                state
+            })
+            .collect();
+        let province_ids: HashSet<ProvinceId> = sub_map.cells.iter().filter_map(|cell| cell.province).collect();
+        let provinces = world_map
+            .provinces
+            .iter()
+            .filter(|province| province_ids.contains(&province.id))
+            .map(|province| {
+                let mut province = province.clone();
+                province.center = frame.to_sub(province.center);
+                province.cells = refine(&province.cells);
+                province.burg = province.burg.filter(|burg| burg_ids.contains(burg));
+                province
+            })
+            .collect();
+        
+        let mut culture_ids: HashSet<CultureId> = sub_map.cells.iter().filter_map(|cell| cell.culture).collect();
+        culture_ids.extend(burgs.iter().map(|burg| burg.culture));
+        culture_ids.extend(states.iter().map(|state| state.culture));
+        let cultures = world_map
+            .cultures
+            .iter()
+            .filter(|culture| culture_ids.contains(&culture.id))
+            .map(|culture| {
+                let mut culture = culture.clone();
+                culture.center = frame.to_sub(culture.center);
+                culture
+            })
+            .collect();
+        
+        let features = world_map
+            .features
+            .iter()
+            .map(|feature| {
+                let mut feature = feature.clone();
+                feature.cells = refine(&feature.cells);
+                feature
+            })
+            .filter(|feature| !feature.cells.is_empty())
+            .collect();
+        let zones = world_map
+            .zones
+            .iter()
+            .map(|zone| {
+                let mut zone = zone.clone();
+                zone.cells = refine(&zone.cells);
+                zone
+            })
+            .filter(|zone| !zone.cells.is_empty())
+            .collect();
+        
+        let markers = world_map
+            .markers
+            .iter()
+            .filter(|marker| region.contains(Point2::new(marker.x, marker.y)))
+            .map(|marker| {
+                let position = frame.to_sub(Point2::new(marker.x, marker.y));
+                let mut marker = marker.clone();
+                marker.cell = cell_at(Point2::new(marker.x, marker.y));
+                marker.x = position.x;
+                marker.y = position.y;
+                marker
+            })
+            .collect();
+        
+        // Paths are clipped to the region, and split where they leave and re-enter it
+        let mut next_river = world_map.rivers.iter().map(|river| river.id.0 + 1).max().unwrap_or(0);
+        let mut rivers = Vec::new();
+        for river in &world_map.rivers {
+            for (piece, path) in self.clip_path(world_map, sub_map, region, frame, &river.cells).into_iter().enumerate() {
+                let mut river = river.clone();
+                if piece > 0 {
+                    river.id = RiverId(next_river);
+                    next_river += 1;
+                }
+                river.length = path_length(sub_map, &path);
+                river.source = path[0];
+                river.mouth = path[path.len() - 1];
+                river.cells = path;
+                rivers.push(river);
+            }
+        }
+        let mut next_route = world_map.routes.iter().map(|route| route.id.0 + 1).max().unwrap_or(0);
+        let mut routes = Vec::new();
+        for route in &world_map.routes {
+            for (piece, path) in self.clip_path(world_map, sub_map, region, frame, &route.cells).into_iter().enumerate() {
+                let mut route = route.clone();
+                if piece > 0 {
+                    route.id = RouteId(next_route);
+                    next_route += 1;
+                }
+                route.length = path_length(sub_map, &path);
+                route.cells = path;
+                routes.push(route);
+            }
+        }
+        
+        for state in world_map.states.iter().filter(|state| !state_ids.contains(&state.id)) {
+            sub_map.diplomacy.remove_state(state.id);
+        }
+        sub_map.burgs = burgs;
+        sub_map.states = states;
+        sub_map.provinces = provinces;
+        sub_map.cultures = cultures;
+        sub_map.features = features;
+        sub_map.zones = zones;
+        sub_map.markers = markers;
+        sub_map.rivers = rivers;
+        sub_map.routes = routes;
+        sub_map.history = carry_over_history(world_map, sources, &refine);
+        let events = self.carry_over_events(world_map, sub_map, region, frame, &refine);
+        sub_map.events = events;
+    }
+    
+    /// Events that happened in the region or involve entities kept in the
+    /// sub-map, with their other subjects dropped
+    fn carry_over_events(
+        &self,
+        world_map: &WorldMap,
+        sub_map: &WorldMap,
+        region: &Region,
+        frame: &Frame,
+        refine: &dyn Fn(&[CellId]) -> Vec<CellId>,
+    ) -> Vec<DatedEvent> {
+        let kept = |subject: &EntityRef| match *subject {
+            EntityRef::Cell(cell) => !refine(&[cell]).is_empty(),
+            EntityRef::Event(_) => false,
+            EntityRef::Feature(id) => sub_map.feature(id).is_some(),
+            EntityRef::Culture(id) => sub_map.culture(id).is_some(),
+            EntityRef::State(id) => sub_map.state(id).is_some(),
+            EntityRef::Province(id) => sub_map.province(id).is_some(),
+            EntityRef::Settlement(id) => sub_map.burg(id).is_some(),
+            EntityRef::River(id) => sub_map.river(id).is_some(),
+            EntityRef::Route(id) => sub_map.route(id).is_some(),
+            EntityRef::Marker(id) => sub_map.marker(id).is_some(),
+        };
+        world_map
+            .events
+            .iter()
+            .filter(|event| {
+                event.location.is_some_and(|location| region.contains(location)) || event.subjects.iter().any(kept)
+            })
+            .map(|event| {
+                let mut event = event.clone();
+                event.location = event.location.map(|location| frame.to_sub(location));
+                event.subjects = event
+                    .subjects
+                    .iter()
+                    .flat_map(|subject| match subject {
+                        // A cell becomes the cells refining it
+                        EntityRef::Cell(cell) => refine(&[*cell]).into_iter().map(EntityRef::Cell).collect(),
+                        subject => kept(subject).then_some(*subject).into_iter().collect::<Vec<_>>(),
+                    })
+                    .collect();
+                event
+            })
+            .collect()
+    }
+    
+    /// Sub-map cells along the parts of a path of source cells inside the
+    /// region, one path per part with at least two cells
+    fn clip_path(
+        &self,
+        world_map: &WorldMap,
+        sub_map: &WorldMap,
+        region: &Region,
+        frame: &Frame,
+        cells: &[CellId],
+    ) -> Vec<Vec<CellId>> {
+        let points: Vec<Option<Point2<f32>>> = cells
+            .iter()
+            .map(|&cell| world_map.cell(cell).map(|cell| cell.coordinates).filter(|&point| region.contains(point)))
+            .collect();
+        let topology = sub_map.topology();
+        // Step along each segment finely enough not to skip sub-map cells
+        let step = (sub_map.metadata.width as f32 * sub_map.metadata.height as f32 / sub_map.cells.len().max(1) as f32)
+            .sqrt()
+            / 2.0;
+        
+        let mut paths = Vec::new();
+        for run in points.split(Option::is_none).filter(|run| run.len() >= 2) {
+            let mut path: Vec<CellId> = Vec::new();
+            for pair in run.windows(2) {
+                let (from, to) = (frame.to_sub(pair[0].unwrap()), frame.to_sub(pair[1].unwrap()));
+                let steps = ((to - from).norm() / step.max(f32::EPSILON)).ceil().max(1.0) as usize;
+                for index in 0..=steps {
+                    let point = from + (to - from) * (index as f32 / steps as f32);
+                    let Some(cell) = topology.cell_at(point).map(|index| sub_map.cells[index].id) else {
+                        continue;
+                    };
+                    if path.last() != Some(&cell) {
+                        path.push(cell);
+                    }
+                }
+            }
+            if path.len() >= 2 {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+}
+
+/// History of the source world in terms of the sub-map's cells
+///
+/// Snapshots take the owner of the source cell beneath each sub-map cell, and
+/// cells that changed hands become the cells refining them.
+fn carry_over_history(world_map: &WorldMap, sources: &[usize], refine: &dyn Fn(&[CellId]) -> Vec<CellId>) -> Timeline {
+    let mut history = world_map.history.clone();
+    for event in &mut history.events {
+        event.cells = refine(&event.cells);
+    }
+    for snapshot in &mut history.snapshots {
+        snapshot.cell_states = sources.iter().map(|&source| snapshot.cell_states.get(source).copied().flatten()).collect();
+    }
+    history
+}
+
+/// Length of a path through cell centres, in map units
+fn path_length(world_map: &WorldMap, path: &[CellId]) -> f32 {
+    path.windows(2)
+        .filter_map(|pair| Some(nalgebra::distance(&world_map.cell(pair[0])?.coordinates, &world_map.cell(pair[1])?.coordinates)))
+        .sum()
+}
+
+/// Fractal value noise in -1..=1, doubling the frequency with every octave
+fn fractal_noise(seed: u64, point: Point2<f32>, octaves: u32) -> f32 {
+    let (mut total, mut amplitude, mut norm, mut frequency) = (0.0, 1.0, 0.0, 1.0);
+    for octave in 0..octaves.max(1) {
+        total += amplitude * value_noise(seed.wrapping_add(octave as u64), point * frequency);
+        norm += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / norm
+}
+
+/// Smoothly interpolated random values on the integer lattice, in -1..=1
+fn value_noise(seed: u64, point: Point2<f32>) -> f32 {
+    let (x0, y0) = (point.x.floor(), point.y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(point.x - x0), smooth(point.y - y0));
+    let lattice = |dx: i64, dy: i64| lattice_value(seed, x0 as i64 + dx, y0 as i64 + dy);
+    let top = lattice(0, 0) + (lattice(1, 0) - lattice(0, 0)) * tx;
+    let bottom = lattice(0, 1) + (lattice(1, 1) - lattice(0, 1)) * tx;
+    top + (bottom - top) * ty
+}
+
+/// Pseudo-random value of a lattice point, in -1..=1
+fn lattice_value(seed: u64, x: i64, y: i64) -> f32 {
+    // SplitMix64 finalizer over the seed and coordinates
+    let mut hash = seed ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    (hash >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::data::{EventId, HistoricalEvent, HistoricalEventKind, Note, River, RiverType, StateSnapshot, WorldDate};
+    
+    /// Grid world with a river flowing east along the fourth row of cells
+    fn world_with_river() -> WorldMap {
+        let mut world_map = grid_world(8);
+        let cells: Vec<CellId> = (25..32).map(CellId).collect();
+        world_map.rivers.push(River {
+            id: RiverId(1),
+            source: cells[0],
+            mouth: cells[cells.len() - 1],
+            discharge: 10.0,
+            length: 60.0,
+            width: 1.0,
+            cells,
+            basin: RiverId(1),
+            name: "Arn".to_string(),
+            type_: RiverType::River,
+            note: None,
+            attributes: Default::default(),
+        });
+        world_map
+    }
+    
+    fn event(id: u32, subjects: Vec<EntityRef>) -> DatedEvent {
+        DatedEvent {
+            id: EventId(id),
+            date: WorldDate { year: 100, month: 1, day: 1 },
+            note: Note { title: format!("Event {}", id), legend: String::new() },
+            subjects,
+            location: None,
+            attributes: Default::default(),
+        }
+    }
+    
+    fn extract(world_map: &WorldMap) -> WorldMap {
+        let region = Region::Rect { x: 20.0, y: 20.0, width: 40.0, height: 40.0 };
+        RegionExtractor::new(RegionParams::default(), 7).extract(world_map, &region).unwrap()
+    }
+    
+    #[test]
+    fn regions_measure_their_shape() {
+        let triangle = Region::Polygon(vec![Point2::new(0.0, 0.0), Point2::new(10.0, 0.0), Point2::new(0.0, 10.0)]);
+        assert_eq!(triangle.area(), 50.0);
+        assert!(triangle.contains(Point2::new(2.0, 2.0)));
+        assert!(!triangle.contains(Point2::new(8.0, 8.0)));
+        assert_eq!(triangle.bounds(), (Point2::new(0.0, 0.0), Point2::new(10.0, 10.0)));
+    }
+    
+    #[test]
+    fn sub_maps_keep_clipped_rivers_and_their_events() {
+        let mut world_map = world_with_river();
+        world_map.events = vec![
+            event(1, vec![EntityRef::River(RiverId(1))]),
+            event(2, vec![EntityRef::State(StateId(1)), EntityRef::River(RiverId(1))]),
+        ];
+        let sub_map = extract(&world_map);
+        
+        assert_eq!(sub_map.metadata.width, 160);
+        let river = sub_map.river(RiverId(1)).unwrap();
+        assert!(river.cells.len() >= 2);
+        assert_eq!((river.source, river.mouth), (river.cells[0], river.cells[river.cells.len() - 1]));
+        assert_eq!(sub_map.events.len(), 2);
+        assert!(sub_map.events.iter().all(|event| event.subjects.contains(&EntityRef::River(RiverId(1)))));
+    }
+    
+    #[test]
+    fn sub_maps_follow_history_through_their_own_cells() {
+        let mut world_map = grid_world(8);
+        world_map.history.snapshots.push(StateSnapshot {
+            year: 100,
+            cell_states: world_map.cells.iter().map(|cell| cell.state).collect(),
+        });
+        world_map.history.events.push(HistoricalEvent {
+            year: 100,
+            kind: HistoricalEventKind::BorderShift,
+            states: vec![StateId(1), StateId(4)],
+            cells: vec![CellId(27)],
+            description: String::new(),
+        });
+        let sub_map = extract(&world_map);
+        
+        let snapshot = &sub_map.history.snapshots[0];
+        let current: Vec<Option<StateId>> = sub_map.cells.iter().map(|cell| cell.state).collect();
+        assert_eq!(snapshot.cell_states, current);
+        
+        let cells = &sub_map.history.events[0].cells;
+        assert!(!cells.is_empty());
+        let topology = world_map.topology();
+        for &cell in cells {
+            let position = sub_map.cell(cell).unwrap().coordinates;
+            let source = Point2::new(20.0, 20.0) + position.coords / 4.0;
+            assert_eq!(topology.cell_at(source), Some(27));
+        }
+    }
+    
+    #[test]
+    fn rural_population_is_shared_without_loss() {
+        let mut world_map = grid_world(8);
+        for cell in world_map.cells.iter_mut() {
+            cell.population = 7 + cell.id.0 % 5;
+        }
+        let sub_map = extract(&world_map);
+        
+        // Every source cell refined by a sub-map cell hands over its whole population
+        let topology = world_map.topology();
+        let sources: HashSet<usize> = sub_map
+            .cells
+            .iter()
+            .filter_map(|cell| topology.cell_at(Point2::new(20.0, 20.0) + cell.coordinates.coords / 4.0))
+            .collect();
+        let expected: u32 = sources.iter().map(|&source| world_map.cells[source].population).sum();
+        assert_eq!(sub_map.cells.iter().map(|cell| cell.population).sum::<u32>(), expected);
+    }
+    
+    #[test]
+    fn oversized_regions_are_refused() {
+        let world_map = grid_world(8);
+        let region = Region::Rect { x: 0.0, y: 0.0, width: 80.0, height: 80.0 };
+        let params = RegionParams { scale: 1000.0, ..RegionParams::default() };
+        assert!(matches!(
+            RegionExtractor::new(params, 7).extract(&world_map, &region),
+            Err(WorldFoundryError::Generation(_))
+        ));
+        
+        let params = RegionParams { subdivision: u32::MAX, ..RegionParams::default() };
+        assert!(RegionExtractor::new(params, 7).extract(&world_map, &region).is_err());
+    }
+}
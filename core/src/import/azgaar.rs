@@ -1,8 +1,10 @@
 //! Azgaar Fantasy Map Generator import functionality
 
 use super::{MapImporter, MapPreview};
+use crate::data::{AreaUnit, Calendar, Color, DistanceUnit, Era, HeightUnit, SettlementType, StateType, TemperatureScale};
 use crate::{WorldMap, Result, WorldFoundryError};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
 
@@ -71,6 +73,7 @@ impl MapImporter for AzgaarImporter {
             created_at: None,
             file_size: metadata.len(),
             format: format!("Azgaar {}", extension.to_uppercase()),
+            thumbnail: None,
         })
     }
 }
@@ -101,56 +104,98 @@ impl AzgaarImporter {
         use chrono::Utc;
         
         // Convert Azgaar data to World Foundry format
-        let metadata = MapMetadata {
+        let mut metadata = MapMetadata {
             id: Uuid::new_v4(),
             name: azgaar_data.info.map_name.unwrap_or_else(|| "Imported Map".to_string()),
             version: azgaar_data.info.version,
+            schema_version: SCHEMA_VERSION,
             created_at: Utc::now(),
             modified_at: Utc::now(),
             width: azgaar_data.info.width,
             height: azgaar_data.info.height,
             seed: azgaar_data.info.seed,
             settings: MapSettings {
-                distance_unit: azgaar_data.settings.distance_unit.unwrap_or_else(|| "km".to_string()),
+                distance_unit: azgaar_data.settings.distance_unit.unwrap_or(DistanceUnit::Kilometre),
                 distance_scale: azgaar_data.settings.distance_scale.unwrap_or(1.0),
-                area_unit: azgaar_data.settings.area_unit.unwrap_or_else(|| "km²".to_string()),
-                height_unit: azgaar_data.settings.height_unit.unwrap_or_else(|| "m".to_string()),
+                area_unit: azgaar_data.settings.area_unit.unwrap_or(AreaUnit::SquareKilometre),
+                height_unit: azgaar_data.settings.height_unit.unwrap_or(HeightUnit::Metre),
                 height_exponent: azgaar_data.settings.height_exponent.unwrap_or(1.8),
-                temperature_scale: azgaar_data.settings.temperature_scale.unwrap_or_else(|| "°C".to_string()),
+                temperature_scale: azgaar_data.settings.temperature_scale.unwrap_or(TemperatureScale::Celsius),
                 population_rate: azgaar_data.settings.population_rate.unwrap_or(1.0),
                 urbanization: azgaar_data.settings.urbanization.unwrap_or(1.0),
                 latitude: azgaar_data.settings.latitude.unwrap_or(50.0),
                 longitude: azgaar_data.settings.longitude.unwrap_or(0.0),
             },
+            notes: Default::default(),
         };
         
-        // Create empty heightmap for now
-        let heightmap = Grid::new(azgaar_data.info.width, azgaar_data.info.height, 0.0f32);
+        let mut pack = azgaar_data.pack;
         
         // Convert cells if available
-        let cells = if let Some(pack_cells) = azgaar_data.pack.and_then(|p| p.cells) {
-            self.convert_pack_cells(pack_cells)?
+        let cells = if let Some(pack_cells) = pack.as_mut().and_then(|p| p.cells.take()) {
+            self.convert_pack_cells(pack_cells, &metadata.settings)?
         } else {
             Vec::new()
         };
         
-        Ok(WorldMap {
+        // Convert states and their diplomatic relations
+        let (mut states, diplomacy) = if let Some(pack_states) = pack.as_ref().and_then(|p| p.states.as_ref()) {
+            self.convert_states(pack_states, &cells, &metadata.settings)
+        } else {
+            (Vec::new(), DiplomacyMatrix::new())
+        };
+        
+        let provinces = pack.as_ref()
+            .and_then(|p| p.provinces.as_ref())
+            .map(|pack_provinces| self.convert_provinces(pack_provinces, &cells))
+            .unwrap_or_default();
+        
+        let mut burgs = pack.as_ref()
+            .and_then(|p| p.burgs.as_ref())
+            .map(|pack_burgs| self.convert_burgs(pack_burgs, &metadata.settings))
+            .unwrap_or_default();
+        
+        // Notes are keyed by element id, such as "state3" or "burg12"
+        let mut notes = azgaar_data.notes.as_ref().map(Self::convert_notes).unwrap_or_default();
+        for state in states.iter_mut() {
+            state.note = notes.remove(&format!("state{}", state.id));
+        }
+        for burg in burgs.iter_mut() {
+            burg.note = notes.remove(&format!("burg{}", burg.id));
+        }
+        // Cultures, rivers, markers, zones and features are not imported yet,
+        // so their notes are kept on the map until they have an owner
+        metadata.notes = notes.into_iter().collect();
+        
+        let calendar = azgaar_data.settings.options.as_ref().map(Self::convert_calendar).unwrap_or_default();
+        
+        let mut world_map = WorldMap {
             metadata,
-            heightmap,
+            // Rasterized from the cell heights below
+            heightmap: Grid::new(0, 0, 0.0),
             cells,
             features: Vec::new(), // TODO: Convert features
             cultures: Vec::new(), // TODO: Convert cultures
-            states: Vec::new(),   // TODO: Convert states
-            burgs: Vec::new(),    // TODO: Convert burgs
+            states,
+            provinces,
+            burgs,
             rivers: Vec::new(),   // TODO: Convert rivers
             routes: Vec::new(),   // TODO: Convert routes
             markers: Vec::new(),  // TODO: Convert markers
             zones: Vec::new(),    // TODO: Convert zones
-        })
+            diplomacy,
+            history: Timeline::default(),
+            calendar,
+            events: Vec::new(),
+            topology: TopologyCache::default(),
+        };
+        world_map.rebuild_heightmap();
+        
+        Ok(world_map)
     }
     
-    fn convert_pack_cells(&self, pack_cells: AzgaarPackCells) -> Result<Vec<crate::data::Cell>> {
-        use crate::data::{Cell, BiomeType};
+    fn convert_pack_cells(&self, pack_cells: AzgaarPackCells, settings: &crate::data::MapSettings) -> Result<Vec<crate::data::Cell>> {
+        use crate::data::{Cell, BiomeType, CellId, CultureId, ProvinceId, StateId};
         use nalgebra::Point2;
         
         let mut cells = Vec::new();
@@ -158,19 +203,22 @@ impl AzgaarImporter {
         // Azgaar stores cell data in parallel arrays
         for i in 0..pack_cells.i.len() {
             let cell = Cell {
-                id: pack_cells.i[i],
+                id: CellId(pack_cells.i[i]),
                 coordinates: Point2::new(
                     pack_cells.p.get(i * 2).copied().unwrap_or(0.0),
                     pack_cells.p.get(i * 2 + 1).copied().unwrap_or(0.0),
                 ),
-                height: pack_cells.h.get(i).copied().unwrap_or(0.0),
-                biome: BiomeType::Temperate, // TODO: Convert biome data
+                // Azgaar heights run from 0 to 100
+                height: pack_cells.h.get(i).copied().unwrap_or(0.0) / 100.0,
+                biome: pack_cells.biome.get(i).map_or(BiomeType::Temperate, |&biome| Self::convert_biome(biome)),
                 temperature: pack_cells.temp.get(i).copied().unwrap_or(0.0),
                 precipitation: pack_cells.prec.get(i).copied().unwrap_or(0.0),
-                population: pack_cells.pop.get(i).copied().unwrap_or(0),
-                culture: pack_cells.culture.get(i).and_then(|&x| x),
-                state: pack_cells.state.get(i).and_then(|&x| x),
-                province: pack_cells.province.get(i).and_then(|&x| x),
+                // Azgaar stores rural population in points
+                population: (pack_cells.pop.get(i).copied().unwrap_or(0.0) * settings.population_rate).round() as u32,
+                culture: pack_cells.culture.get(i).and_then(|&x| x).map(CultureId),
+                // State 0 holds Azgaar's neutral lands, which belong to no state
+                state: pack_cells.state.get(i).and_then(|&x| x).filter(|&state| state != 0).map(StateId),
+                province: pack_cells.province.get(i).and_then(|&x| x).map(ProvinceId),
                 religion: pack_cells.religion.get(i).and_then(|&x| x),
             };
             cells.push(cell);
@@ -178,6 +226,240 @@ impl AzgaarImporter {
         
         Ok(cells)
     }
+    
+    /// Map Azgaar's default biome ids onto World Foundry biomes
+    fn convert_biome(biome: u8) -> crate::data::BiomeType {
+        use crate::data::BiomeType;
+        
+        match biome {
+            0 => BiomeType::Marine,
+            1 | 2 => BiomeType::Dry,                  // hot and cold deserts
+            3 | 5 | 7 => BiomeType::Hot,              // savanna, tropical forests
+            4 | 6 | 8 | 12 => BiomeType::Temperate,   // grassland, temperate forests, wetland
+            9 | 10 => BiomeType::Cold,                // taiga, tundra
+            11 => BiomeType::Frozen,                  // glacier
+            _ => BiomeType::Temperate,
+        }
+    }
+    
+    fn convert_states(
+        &self,
+        pack_states: &serde_json::Value,
+        cells: &[crate::data::Cell],
+        settings: &crate::data::MapSettings,
+    ) -> (Vec<crate::data::State>, crate::data::DiplomacyMatrix) {
+        use crate::data::{Attributes, State, StateId, BurgId, CultureId, CellId, DiplomacyMatrix, DiplomaticStatus};
+        use nalgebra::Point2;
+        
+        let azgaar_states: Vec<AzgaarState> = Self::parse_entries(pack_states);
+        
+        // Index 0 is the "Neutrals" pseudo-state; removed states keep their slot
+        let is_real = |state: &AzgaarState| state.i != 0 && !state.removed;
+        let real_ids: Vec<u32> = azgaar_states.iter().filter(|s| is_real(s)).map(|s| s.i).collect();
+        
+        let mut states = Vec::new();
+        let mut diplomacy = DiplomacyMatrix::new();
+        
+        for azgaar_state in azgaar_states.iter().filter(|s| is_real(s)) {
+            let state_cells: Vec<CellId> = cells
+                .iter()
+                .filter(|cell| cell.state == Some(StateId(azgaar_state.i)))
+                .map(|cell| cell.id)
+                .collect();
+            
+            let center = match azgaar_state.pole {
+                Some([x, y]) => Point2::new(x, y),
+                None => cells
+                    .iter()
+                    .find(|cell| cell.id == CellId(azgaar_state.center))
+                    .map(|cell| cell.coordinates)
+                    .unwrap_or_else(|| Point2::new(0.0, 0.0)),
+            };
+            
+            // Azgaar counts population in points
+            let rural = azgaar_state.rural * settings.population_rate;
+            let urban = azgaar_state.urban * settings.population_rate * settings.urbanization;
+            
+            states.push(State {
+                id: StateId(azgaar_state.i),
+                name: azgaar_state.name.clone(),
+                full_name: azgaar_state.full_name.clone().unwrap_or_else(|| azgaar_state.name.clone()),
+                color: azgaar_state.color.clone().unwrap_or_default(),
+                capital: BurgId(azgaar_state.capital),
+                center,
+                area: azgaar_state.area,
+                population: (rural + urban).max(0.0).round() as u32,
+                rural,
+                urban,
+                burgs: azgaar_state.burgs,
+                culture: CultureId(azgaar_state.culture),
+                type_: azgaar_state.type_.clone().unwrap_or(StateType::Generic),
+                expansionism: azgaar_state.expansionism,
+                cells: state_cells,
+                coa: azgaar_state.coa.as_ref().and_then(|coa| self.convert_coa(coa)),
+                note: None,
+                attributes: Attributes::new(),
+            });
+            
+            // diplomacy[j] is this state's stance towards state j
+            for (other, label) in azgaar_state.diplomacy.iter().enumerate() {
+                let other = other as u32;
+                if other == azgaar_state.i || !real_ids.contains(&other) {
+                    continue;
+                }
+                if let Some(status) = label.as_str().and_then(DiplomaticStatus::from_azgaar) {
+                    diplomacy.set(StateId(azgaar_state.i), StateId(other), status);
+                }
+            }
+        }
+        
+        (states, diplomacy)
+    }
+    
+    fn convert_provinces(&self, pack_provinces: &serde_json::Value, cells: &[crate::data::Cell]) -> Vec<crate::data::Province> {
+        use crate::data::{BurgId, CellId, Province, ProvinceId, StateId};
+        use nalgebra::Point2;
+        
+        // Index 0 is a placeholder, not an object
+        let azgaar_provinces: Vec<AzgaarProvince> = Self::parse_entries(pack_provinces);
+        
+        azgaar_provinces
+            .into_iter()
+            .filter(|province| province.i != 0 && !province.removed)
+            .map(|province| {
+                let center = match province.pole {
+                    Some([x, y]) => Point2::new(x, y),
+                    None => cells
+                        .iter()
+                        .find(|cell| cell.id == CellId(province.center))
+                        .map(|cell| cell.coordinates)
+                        .unwrap_or_else(|| Point2::new(0.0, 0.0)),
+                };
+                
+                Province {
+                    id: ProvinceId(province.i),
+                    full_name: province.full_name.clone().unwrap_or_else(|| province.name.clone()),
+                    name: province.name,
+                    color: province.color.unwrap_or_default(),
+                    state: StateId(province.state),
+                    burg: Some(province.burg).filter(|&burg| burg != 0).map(BurgId),
+                    center,
+                    cells: cells
+                        .iter()
+                        .filter(|cell| cell.province == Some(ProvinceId(province.i)))
+                        .map(|cell| cell.id)
+                        .collect(),
+                    coa: province.coa.as_ref().and_then(|coa| self.convert_coa(coa)),
+                }
+            })
+            .collect()
+    }
+    
+    fn convert_burgs(&self, pack_burgs: &serde_json::Value, settings: &crate::data::MapSettings) -> Vec<crate::data::Settlement> {
+        use crate::data::{Attributes, BurgId, CellId, CultureId, FeatureId, Settlement, StateId};
+        
+        // Index 0 is an empty placeholder object
+        let azgaar_burgs: Vec<AzgaarBurg> = Self::parse_entries(pack_burgs);
+        
+        azgaar_burgs
+            .into_iter()
+            .filter(|burg| burg.i != 0 && !burg.removed)
+            .map(|burg| Settlement {
+                id: BurgId(burg.i),
+                name: burg.name,
+                cell: CellId(burg.cell),
+                x: burg.x,
+                y: burg.y,
+                state: StateId(burg.state),
+                i: burg.i,
+                culture: CultureId(burg.culture),
+                feature: FeatureId(burg.feature),
+                capital: burg.capital != 0,
+                port: burg.port,
+                // Azgaar stores population in thousands of "population points"
+                population: burg.population * settings.population_rate * settings.urbanization,
+                type_: burg.type_.unwrap_or(SettlementType::Generic),
+                coa: burg.coa.as_ref().and_then(|coa| self.convert_coa(coa)),
+                note: None,
+                attributes: Attributes::new(),
+            })
+            .collect()
+    }
+    
+    /// Convert an Azgaar `coa` object; custom uploaded arms ("custom") are skipped
+    fn convert_coa(&self, coa: &serde_json::Value) -> Option<crate::data::CoatOfArms> {
+        use crate::data::{CoatOfArms, Division, Ordinary, Charge, Tincture, ShieldShape};
+        
+        let coa: AzgaarCoa = serde_json::from_value(coa.clone()).ok()?;
+        let truthy = |value: &Option<serde_json::Value>| match value {
+            Some(serde_json::Value::Bool(flag)) => *flag,
+            Some(serde_json::Value::Number(number)) => number.as_f64().unwrap_or(0.0) != 0.0,
+            _ => false,
+        };
+        
+        Some(CoatOfArms {
+            shield: coa.shield.map(ShieldShape::from).unwrap_or_default(),
+            field: Tincture::from(coa.t1?),
+            division: coa.division.map(|division| Division {
+                kind: division.division.into(),
+                tincture: division.t.into(),
+                line: division.line.map(Into::into).unwrap_or_default(),
+            }),
+            ordinaries: coa.ordinaries
+                .into_iter()
+                .map(|ordinary| Ordinary {
+                    kind: ordinary.ordinary.into(),
+                    tincture: ordinary.t.into(),
+                    line: ordinary.line.map(Into::into).unwrap_or_default(),
+                })
+                .collect(),
+            charges: coa.charges
+                .into_iter()
+                .map(|charge| Charge {
+                    sinister: truthy(&charge.sinister),
+                    reversed: truthy(&charge.reversed),
+                    charge: charge.charge,
+                    tincture: charge.t.into(),
+                    positions: charge.p,
+                    size: charge.size.unwrap_or(1.0),
+                })
+                .collect(),
+        })
+    }
+    
+    fn convert_notes(notes: &serde_json::Value) -> HashMap<String, crate::data::Note> {
+        Self::parse_entries::<AzgaarNote>(notes)
+            .into_iter()
+            .map(|note| (note.id, crate::data::Note::new(note.name, note.legend)))
+            .collect()
+    }
+    
+    /// Azgaar counts the year within its single era, so the era starts at year 1
+    fn convert_calendar(options: &AzgaarOptions) -> Calendar {
+        let mut calendar = Calendar::default();
+        if let Some(name) = &options.era {
+            let short = options.era_short.clone().unwrap_or_else(|| {
+                name.split_whitespace().filter_map(|word| word.chars().next()).collect::<String>().to_uppercase()
+            });
+            calendar.eras.push(Era { name: name.clone(), short, start_year: 1 });
+        }
+        calendar.year = options.year.unwrap_or_default();
+        calendar
+    }
+    
+    /// Parse the object entries of an Azgaar array, skipping placeholders and malformed entries
+    fn parse_entries<T: serde::de::DeserializeOwned>(value: &serde_json::Value) -> Vec<T> {
+        value
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|entry| entry.is_object())
+                    .filter_map(|entry| serde_json::from_value(entry.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
 /// Azgaar JSON data structures
@@ -187,6 +469,8 @@ struct AzgaarJsonData {
     settings: AzgaarSettings,
     pack: Option<AzgaarPack>,
     grid: Option<AzgaarGrid>,
+    #[serde(default)]
+    notes: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -204,22 +488,33 @@ struct AzgaarInfo {
 #[derive(Debug, Deserialize, Serialize)]
 struct AzgaarSettings {
     #[serde(rename = "distanceUnit")]
-    distance_unit: Option<String>,
+    distance_unit: Option<DistanceUnit>,
     #[serde(rename = "distanceScale")]
     distance_scale: Option<f32>,
     #[serde(rename = "areaUnit")]
-    area_unit: Option<String>,
+    area_unit: Option<AreaUnit>,
     #[serde(rename = "heightUnit")]
-    height_unit: Option<String>,
+    height_unit: Option<HeightUnit>,
     #[serde(rename = "heightExponent")]
     height_exponent: Option<f32>,
     #[serde(rename = "temperatureScale")]
-    temperature_scale: Option<String>,
+    temperature_scale: Option<TemperatureScale>,
     #[serde(rename = "populationRate")]
     population_rate: Option<f32>,
     urbanization: Option<f32>,
     latitude: Option<f32>,
     longitude: Option<f32>,
+    #[serde(default)]
+    options: Option<AzgaarOptions>,
+}
+
+/// Map options; only the dating ones are read
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarOptions {
+    year: Option<i32>,
+    era: Option<String>,
+    #[serde(rename = "eraShort")]
+    era_short: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -244,15 +539,288 @@ struct AzgaarPackCells {
     h: Vec<f32>,        // heights
     temp: Vec<f32>,     // temperatures
     prec: Vec<f32>,     // precipitation
-    pop: Vec<u32>,      // population
+    pop: Vec<f32>,      // rural population points
+    #[serde(default)]
+    biome: Vec<u8>,     // biome ids
     culture: Vec<Option<u32>>,
     state: Vec<Option<u32>>,
     province: Vec<Option<u32>>,
     religion: Vec<Option<u32>>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarState {
+    i: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "fullName")]
+    full_name: Option<String>,
+    color: Option<Color>,
+    #[serde(default)]
+    capital: u32,
+    #[serde(default)]
+    center: u32,        // cell id of the state center
+    pole: Option<[f32; 2]>,  // pole of inaccessibility, used for labels
+    #[serde(default)]
+    area: f32,
+    #[serde(default)]
+    burgs: u32,
+    #[serde(default)]
+    rural: f32,
+    #[serde(default)]
+    urban: f32,
+    #[serde(default)]
+    culture: u32,
+    #[serde(rename = "type")]
+    type_: Option<StateType>,
+    #[serde(default)]
+    expansionism: f32,
+    // Labels indexed by state id; the neutrals entry holds a war log instead
+    #[serde(default)]
+    diplomacy: Vec<serde_json::Value>,
+    #[serde(default)]
+    removed: bool,
+    coa: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarProvince {
+    i: u32,
+    #[serde(default)]
+    state: u32,
+    #[serde(default)]
+    center: u32,        // cell id
+    #[serde(default)]
+    burg: u32,          // capital burg id, 0 if none
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "fullName")]
+    full_name: Option<String>,
+    color: Option<Color>,
+    pole: Option<[f32; 2]>,
+    #[serde(default)]
+    removed: bool,
+    coa: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarBurg {
+    i: u32,
+    #[serde(default)]
+    cell: u32,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    state: u32,
+    #[serde(default)]
+    culture: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    feature: u32,
+    #[serde(default)]
+    capital: u32,
+    #[serde(default)]
+    port: u32,
+    #[serde(default)]
+    population: f32,
+    #[serde(rename = "type")]
+    type_: Option<SettlementType>,
+    #[serde(default)]
+    removed: bool,
+    coa: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarNote {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    legend: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarCoa {
+    t1: Option<String>,
+    shield: Option<String>,
+    division: Option<AzgaarCoaDivision>,
+    #[serde(default)]
+    ordinaries: Vec<AzgaarCoaOrdinary>,
+    #[serde(default)]
+    charges: Vec<AzgaarCoaCharge>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarCoaDivision {
+    division: String,
+    t: String,
+    line: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarCoaOrdinary {
+    ordinary: String,
+    t: String,
+    line: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AzgaarCoaCharge {
+    charge: String,
+    t: String,
+    #[serde(default)]
+    p: String,          // positions on the 3x3 grid, e.g. "e" or "abc"
+    size: Option<f32>,
+    sinister: Option<serde_json::Value>,
+    reversed: Option<serde_json::Value>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct AzgaarGrid {
     // Grid cell data structure
     // TODO: Define based on Azgaar's grid format
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DiplomaticStatus, DivisionKind, IssueKind, LineStyle, ShieldShape, StateId, Tincture};
+    
+    fn import(json: &str) -> WorldMap {
+        AzgaarImporter::new().convert_azgaar_to_world_map(serde_json::from_str(json).unwrap()).unwrap()
+    }
+    
+    #[test]
+    fn diplomacy_is_read_between_real_states() {
+        let world_map = import(
+            r#"{
+            "info": { "version": "1.97", "width": 100, "height": 100, "seed": 1 },
+            "settings": {},
+            "pack": { "states": [
+                { "i": 0, "name": "Neutrals", "diplomacy": [["war log"]] },
+                { "i": 1, "name": "North", "diplomacy": ["x", "x", "Suspicion", "Enemy"] },
+                { "i": 2, "name": "South", "diplomacy": ["x", "Vassal", "x", "Ally"] },
+                { "i": 3, "name": "Gone", "removed": true }
+            ] }
+        }"#,
+        );
+        
+        let diplomacy = &world_map.diplomacy;
+        assert_eq!(diplomacy.len(), 2);
+        assert_eq!(diplomacy.status(StateId(1), StateId(2)), DiplomaticStatus::Suspicion);
+        assert_eq!(diplomacy.suzerain_of(StateId(2)), Some(StateId(1)));
+    }
+    
+    #[test]
+    fn heightmaps_are_built_from_cell_heights() {
+        let world_map = import(
+            r#"{
+            "info": { "version": "1.97", "width": 20, "height": 20, "seed": 1 },
+            "settings": {},
+            "pack": { "cells": {
+                "i": [0, 1, 2, 3],
+                "p": [5, 5, 15, 5, 5, 15, 15, 15],
+                "h": [10, 20, 30, 40],
+                "temp": [], "prec": [], "pop": [],
+                "culture": [], "state": [], "province": [], "religion": []
+            } }
+        }"#,
+        );
+        
+        let heightmap = &world_map.heightmap;
+        assert_eq!((heightmap.width, heightmap.height), (20, 20));
+        assert!((heightmap.get(4, 4).unwrap() - 0.1).abs() < 0.01);
+        assert!((heightmap.get(15, 15).unwrap() - 0.4).abs() < 0.01);
+        assert!(heightmap.data.iter().all(|height| (0.1..=0.4).contains(height)));
+    }
+    
+    #[test]
+    fn era_and_year_are_read_into_the_calendar() {
+        let world_map = import(
+            r#"{
+            "info": { "version": "1.97", "width": 100, "height": 100, "seed": 1 },
+            "settings": { "options": { "year": 1024, "era": "Bright Age" } },
+            "pack": {}
+        }"#,
+        );
+        
+        let calendar = &world_map.calendar;
+        assert_eq!(calendar.year, 1024);
+        assert_eq!(calendar.current_era().map(|era| (era.name.as_str(), era.short.as_str())), Some(("Bright Age", "BA")));
+        assert_eq!(calendar.format_year(calendar.year), "1024 BA");
+        
+        let abbreviated = import(
+            r#"{
+            "info": { "version": "1.97", "width": 100, "height": 100, "seed": 1 },
+            "settings": { "options": { "year": 3, "era": "Long Night", "eraShort": "N" } },
+            "pack": {}
+        }"#,
+        );
+        assert_eq!(abbreviated.calendar.format_year(3), "3 N");
+        let undated = import(r#"{ "info": { "version": "1.97", "width": 10, "height": 10, "seed": 1 }, "settings": {}, "pack": {} }"#);
+        assert!(undated.calendar.eras.is_empty());
+    }
+    
+    #[test]
+    fn azgaar_biomes_are_mapped_onto_ours() {
+        use crate::data::BiomeType;
+        
+        let biomes: Vec<BiomeType> = [0, 1, 5, 6, 10, 11, 99].into_iter().map(AzgaarImporter::convert_biome).collect();
+        assert_eq!(biomes, [
+            BiomeType::Marine,
+            BiomeType::Dry,
+            BiomeType::Hot,
+            BiomeType::Temperate,
+            BiomeType::Cold,
+            BiomeType::Frozen,
+            BiomeType::Temperate,
+        ]);
+    }
+    
+    #[test]
+    fn coats_of_arms_are_read() {
+        let world_map = import(
+            r#"{
+            "info": { "version": "1.97", "width": 100, "height": 100, "seed": 1 },
+            "settings": {},
+            "pack": { "burgs": [{}, { "i": 1, "coa": {
+                "t1": "azure",
+                "shield": "swiss",
+                "division": { "division": "perPale", "t": "or", "line": "wavy" },
+                "charges": [{ "charge": "mullet", "t": "argent", "p": "abc", "size": 0.5, "sinister": 1 }]
+            } }] }
+        }"#,
+        );
+        
+        let arms = world_map.burgs[0].coa.as_ref().unwrap();
+        assert_eq!((&arms.shield, &arms.field), (&ShieldShape::Swiss, &Tincture::Azure));
+        let division = arms.division.as_ref().unwrap();
+        assert_eq!((&division.kind, &division.line), (&DivisionKind::PerPale, &LineStyle::Wavy));
+        let charge = &arms.charges[0];
+        assert_eq!((charge.positions.as_str(), charge.size, charge.sinister, charge.reversed), ("abc", 0.5, true, false));
+    }
+    
+    #[test]
+    fn notes_without_an_imported_owner_are_kept() {
+        let json = r#"{
+            "info": { "version": "1.97", "mapName": "Notes", "width": 100, "height": 100, "seed": 1 },
+            "settings": {},
+            "pack": { "burgs": [{}, { "i": 1, "name": "Town" }] },
+            "notes": [
+                { "id": "burg1", "name": "Town", "legend": "Market town" },
+                { "id": "marker4", "name": "Ruin", "legend": "Haunted" }
+            ]
+        }"#;
+        let world_map = import(json);
+        
+        assert_eq!(world_map.burgs[0].note.as_ref().unwrap().legend, "Market town");
+        assert_eq!(world_map.metadata.notes.keys().collect::<Vec<_>>(), ["marker4"]);
+        assert!(world_map
+            .validate()
+            .warnings()
+            .any(|issue| issue.kind == IssueKind::DetachedNote { element: "marker4".to_string() }));
+    }
+}
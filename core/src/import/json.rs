@@ -0,0 +1,62 @@
+//! World Foundry JSON import, the counterpart of `JsonExporter`
+
+use super::{MapImporter, MapPreview};
+use crate::data::{check_schema_version, schema_version, MapMetadata};
+use crate::{Result, WorldFoundryError, WorldMap};
+use std::fs;
+use std::path::Path;
+
+/// Importer for worlds saved as World Foundry JSON, from any schema version
+pub struct JsonImporter;
+
+impl JsonImporter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for JsonImporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapImporter for JsonImporter {
+    fn can_import(&self, file_path: &Path) -> bool {
+        if file_path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            return false;
+        }
+        // Saved worlds always carry these top-level keys; Azgaar exports do not
+        fs::read_to_string(file_path)
+            .map(|content| content.contains("\"metadata\"") && content.contains("\"heightmap\""))
+            .unwrap_or(false)
+    }
+    
+    fn import(&self, file_path: &Path) -> Result<WorldMap> {
+        WorldMap::from_json(&fs::read_to_string(file_path)?)
+    }
+    
+    fn get_preview(&self, file_path: &Path) -> Result<MapPreview> {
+        let file_size = fs::metadata(file_path)?.len();
+        let document: serde_json::Value = serde_json::from_str(&fs::read_to_string(file_path)?)?;
+        check_schema_version(schema_version(&document))?;
+        
+        let metadata = document
+            .get("metadata")
+            .cloned()
+            .ok_or_else(|| WorldFoundryError::Import("World has no metadata".to_string()))?;
+        let metadata: MapMetadata = serde_json::from_value(metadata)?;
+        
+        Ok(MapPreview {
+            name: metadata.name,
+            width: metadata.width,
+            height: metadata.height,
+            seed: Some(metadata.seed),
+            version: Some(metadata.version),
+            created_at: Some(metadata.created_at),
+            file_size,
+            format: "World Foundry JSON".to_string(),
+            thumbnail: None,
+        })
+    }
+}
\ No newline at end of file
@@ -1,6 +1,9 @@
 //! Import functionality for various map formats
 
 pub mod azgaar;
+pub mod json;
+pub mod native;
+pub mod paged;
 
 use crate::{WorldMap, Result};
 use std::path::Path;
@@ -28,6 +31,8 @@ pub struct MapPreview {
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub file_size: u64,
     pub format: String,
+    /// PNG thumbnail, for formats that embed one
+    pub thumbnail: Option<Vec<u8>>,
 }
 
 /// Registry of available importers
@@ -42,6 +47,8 @@ impl ImporterRegistry {
         };
         
         // Register built-in importers
+        registry.register(Box::new(native::NativeImporter::new()));
+        registry.register(Box::new(json::JsonImporter::new()));
         registry.register(Box::new(azgaar::AzgaarImporter::new()));
         
         registry
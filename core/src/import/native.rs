@@ -0,0 +1,554 @@
+//! World Foundry native binary format (.wfm)
+//!
+//! A `.wfm` file is a small header followed by independently compressed
+//! sections:
+//!
+//! ```text
+//! magic        8 bytes   "WFMAP\0\r\n"
+//! version      u32 LE    FORMAT_VERSION
+//! count        u32 LE    number of sections
+//! table        count × { tag: [u8; 4], codec: u8, reserved: [u8; 3],
+//!                        offset: u64 LE, stored: u64 LE, raw: u64 LE }
+//! payloads     section data at the offsets given in the table
+//! ```
+//!
+//! Sections are MessagePack with named fields, compressed with zstd, except the
+//! thumbnail which is stored as a plain PNG. Metadata and thumbnail can be read
+//! without touching the rest of the file.
+//!
+//! Since version 2 the heightmap and cells are chunked sections, stored
+//! uncompressed around chunks compressed one by one, so parts of a large world
+//! can be read on their own (see `import::paged`):
+//!
+//! ```text
+//! header       4 × u32 LE  heightmap: width, height, tile size, chunk count
+//!                          cells: cell count, page size, 0, chunk count
+//! index        count × { offset: u64 LE, stored: u64 LE, raw: u64 LE,
+//!                        bounds: [f32 LE; 4] }
+//! chunks       zstd data at the offsets given in the index, from the section start
+//! ```
+//!
+//! Heightmap tiles are row-major over the grid of tiles and hold their values
+//! as f32 LE; bounds are the tile's `[x, y, x end, y end]` in grid points.
+//! Cell pages are MessagePack lists of cells in the world's cell order; bounds
+//! are the `[min x, min y, max x, max y]` of their cell centres. Version 1 files
+//! store both as single MessagePack sections.
+
+use super::{MapImporter, MapPreview};
+use crate::data::*;
+use crate::{Result, WorldFoundryError};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub(crate) const MAGIC: [u8; 8] = *b"WFMAP\0\r\n";
+
+/// Version written by this build; files with a newer version are rejected
+pub(crate) const FORMAT_VERSION: u32 = 2;
+
+pub(crate) const METADATA_SECTION: [u8; 4] = *b"META";
+pub(crate) const THUMBNAIL_SECTION: [u8; 4] = *b"THMB";
+pub(crate) const HEIGHTMAP_SECTION: [u8; 4] = *b"HGHT";
+pub(crate) const CELLS_SECTION: [u8; 4] = *b"CELL";
+pub(crate) const ENTITIES_SECTION: [u8; 4] = *b"ENTS";
+pub(crate) const HEIGHTMAP_TILES_SECTION: [u8; 4] = *b"HTIL";
+pub(crate) const CELL_PAGES_SECTION: [u8; 4] = *b"CPAG";
+
+pub(crate) const CODEC_NONE: u8 = 0;
+pub(crate) const CODEC_ZSTD: u8 = 1;
+
+/// Size of one section table entry
+pub(crate) const TABLE_ENTRY_SIZE: usize = 32;
+
+/// Size of the header of a chunked section
+pub(crate) const CHUNK_HEADER_SIZE: usize = 16;
+
+/// Size of one chunk index entry
+pub(crate) const CHUNK_ENTRY_SIZE: usize = 40;
+
+/// Most bytes reserved up front for a decompressed section or chunk; longer
+/// data grows the buffer as it is actually decompressed, so a corrupt size
+/// cannot claim more memory than the data behind it
+const PREALLOCATION_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Everything except metadata, heightmap and cells
+#[derive(Deserialize)]
+pub(crate) struct Entities {
+    #[serde(default)]
+    pub features: Vec<Feature>,
+    #[serde(default)]
+    pub cultures: Vec<Culture>,
+    #[serde(default)]
+    pub states: Vec<State>,
+    #[serde(default)]
+    pub provinces: Vec<Province>,
+    #[serde(default)]
+    pub burgs: Vec<Settlement>,
+    #[serde(default)]
+    pub rivers: Vec<River>,
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+    #[serde(default)]
+    pub diplomacy: DiplomacyMatrix,
+    #[serde(default)]
+    pub history: Timeline,
+    #[serde(default)]
+    pub calendar: Calendar,
+    #[serde(default)]
+    pub events: Vec<DatedEvent>,
+}
+
+/// Location of a section in the file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SectionEntry {
+    pub tag: [u8; 4],
+    codec: u8,
+    offset: u64,
+    stored: u64,
+    pub raw: u64,
+}
+
+/// Index of a chunked section
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkedSection {
+    /// First three header values, whose meaning depends on the section
+    pub header: [u32; 3],
+    pub chunks: Vec<ChunkEntry>,
+    /// File offset of the section
+    start: u64,
+}
+
+/// Location of a chunk within its section
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChunkEntry {
+    offset: u64,
+    stored: u64,
+    /// Length once decompressed
+    pub raw: u64,
+    pub bounds: [f32; 4],
+}
+
+/// Heightmap and cell sections as read from a file, still encoded
+enum Layers {
+    /// Version 1: each layer is one MessagePack document
+    Whole { heightmap: Vec<u8>, cells: Vec<u8> },
+    Chunked { heightmap: ChunkedSection, tiles: Vec<Vec<u8>>, pages: Vec<Vec<u8>> },
+}
+
+/// Importer for `.wfm` files
+pub struct NativeImporter;
+
+impl NativeImporter {
+    pub fn new() -> Self {
+        Self
+    }
+    
+    /// Read only the metadata of a file
+    pub fn read_metadata(&self, file_path: &Path) -> Result<MapMetadata> {
+        let mut file = File::open(file_path)?;
+        let table = read_table(&mut file)?;
+        decode(&read_section(&mut file, &table, METADATA_SECTION)?)
+    }
+    
+    /// Read only the PNG thumbnail of a file, if it has one
+    pub fn read_thumbnail(&self, file_path: &Path) -> Result<Option<Vec<u8>>> {
+        let mut file = File::open(file_path)?;
+        let table = read_table(&mut file)?;
+        if !table.iter().any(|entry| entry.tag == THUMBNAIL_SECTION) {
+            return Ok(None);
+        }
+        read_section(&mut file, &table, THUMBNAIL_SECTION).map(Some)
+    }
+}
+
+impl Default for NativeImporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapImporter for NativeImporter {
+    fn can_import(&self, file_path: &Path) -> bool {
+        let mut magic = [0u8; 8];
+        File::open(file_path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .map(|_| magic == MAGIC)
+            .unwrap_or(false)
+    }
+    
+    fn import(&self, file_path: &Path) -> Result<WorldMap> {
+        let mut file = File::open(file_path)?;
+        let table = read_table(&mut file)?;
+        
+        let metadata = read_section(&mut file, &table, METADATA_SECTION)?;
+        let layers = Layers::read(&mut file, &table)?;
+        let entities = read_section(&mut file, &table, ENTITIES_SECTION)?;
+        
+        let header = decode::<MapMetadata>(&metadata)?;
+        check_schema_version(header.schema_version)?;
+        if header.schema_version < SCHEMA_VERSION {
+            return import_legacy(header, &layers, &entities);
+        }
+        
+        // The large sections decode independently, so decode them side by side
+        let (metadata, heightmap, cells, entities) = std::thread::scope(|scope| {
+            let heightmap = scope.spawn(|| layers.heightmap());
+            let cells = scope.spawn(|| layers.cells::<Cell>());
+            let entities = decode::<Entities>(&entities);
+            let metadata = decode::<MapMetadata>(&metadata);
+            (metadata, join(heightmap), join(cells), entities)
+        });
+        let (metadata, heightmap, cells, entities) = (metadata?, heightmap?, cells?, entities?);
+        
+        Ok(WorldMap {
+            metadata,
+            heightmap,
+            cells,
+            features: entities.features,
+            cultures: entities.cultures,
+            states: entities.states,
+            provinces: entities.provinces,
+            burgs: entities.burgs,
+            rivers: entities.rivers,
+            routes: entities.routes,
+            markers: entities.markers,
+            zones: entities.zones,
+            diplomacy: entities.diplomacy,
+            history: entities.history,
+            calendar: entities.calendar,
+            events: entities.events,
+            topology: TopologyCache::default(),
+        })
+    }
+    
+    fn get_preview(&self, file_path: &Path) -> Result<MapPreview> {
+        let file_size = std::fs::metadata(file_path)?.len();
+        let metadata = self.read_metadata(file_path)?;
+        
+        Ok(MapPreview {
+            name: metadata.name,
+            width: metadata.width,
+            height: metadata.height,
+            seed: Some(metadata.seed),
+            version: Some(metadata.version),
+            created_at: Some(metadata.created_at),
+            file_size,
+            format: "World Foundry Binary".to_string(),
+            thumbnail: self.read_thumbnail(file_path)?,
+        })
+    }
+}
+
+impl ChunkedSection {
+    /// Read the header and index of a chunked section; `None` if the file has no such section
+    pub(crate) fn read(file: &mut File, table: &[SectionEntry], tag: [u8; 4]) -> Result<Option<Self>> {
+        let Some(entry) = table.iter().find(|entry| entry.tag == tag) else {
+            return Ok(None);
+        };
+        if entry.codec != CODEC_NONE || entry.stored < CHUNK_HEADER_SIZE as u64 {
+            return Err(corrupt("chunked section has no header"));
+        }
+        let mut header = [0u8; CHUNK_HEADER_SIZE];
+        file.seek(SeekFrom::Start(entry.offset))?;
+        file.read_exact(&mut header)?;
+        let value = |index: usize| u32::from_le_bytes(header[index * 4..index * 4 + 4].try_into().unwrap());
+        
+        let index_len = (value(3) as u64)
+            .checked_mul(CHUNK_ENTRY_SIZE as u64)
+            .filter(|&len| len <= entry.stored - CHUNK_HEADER_SIZE as u64)
+            .ok_or_else(|| corrupt("chunk index is longer than its section"))?;
+        let mut index = vec![0u8; index_len as usize];
+        file.read_exact(&mut index)?;
+        let chunks = index
+            .chunks_exact(CHUNK_ENTRY_SIZE)
+            .map(|chunk| {
+                let chunk = ChunkEntry {
+                    offset: u64::from_le_bytes(chunk[0..8].try_into().unwrap()),
+                    stored: u64::from_le_bytes(chunk[8..16].try_into().unwrap()),
+                    raw: u64::from_le_bytes(chunk[16..24].try_into().unwrap()),
+                    bounds: std::array::from_fn(|corner| {
+                        f32::from_le_bytes(chunk[24 + corner * 4..28 + corner * 4].try_into().unwrap())
+                    }),
+                };
+                match chunk.offset.checked_add(chunk.stored) {
+                    Some(end) if end <= entry.stored => Ok(chunk),
+                    _ => Err(corrupt("chunk lies outside its section")),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self { header: [value(0), value(1), value(2)], chunks, start: entry.offset }))
+    }
+    
+    /// Check the header and index of a heightmap tiles section agree: one tile
+    /// per tile of the grid, together exactly covering it
+    pub(crate) fn check_tiles(&self) -> Result<()> {
+        let [width, height, tile_size] = self.header;
+        let tiles = (tile_size > 0).then(|| width.div_ceil(tile_size) as u64 * height.div_ceil(tile_size) as u64);
+        let raw = self.chunks.iter().try_fold(0u64, |total, chunk| total.checked_add(chunk.raw));
+        if tiles != Some(self.chunks.len() as u64) || raw != (width as u64).checked_mul(height as u64 * 4) {
+            return Err(corrupt("heightmap tiles do not cover the heightmap"));
+        }
+        Ok(())
+    }
+    
+    /// Check the header and index of a cell pages section agree: full pages
+    /// of cells, the last one possibly shorter
+    pub(crate) fn check_pages(&self) -> Result<()> {
+        let [count, page_size, _] = self.header;
+        if page_size == 0 || count.div_ceil(page_size) as usize != self.chunks.len() {
+            return Err(corrupt("cell pages do not match the cell count"));
+        }
+        Ok(())
+    }
+    
+    /// Read and decompress one chunk
+    pub(crate) fn read_chunk(&self, file: &mut File, index: usize) -> Result<Vec<u8>> {
+        let chunk = &self.chunks[index];
+        let mut stored = vec![0u8; chunk.stored as usize];
+        file.seek(SeekFrom::Start(self.start + chunk.offset))?;
+        file.read_exact(&mut stored)?;
+        decompress(&stored, chunk.raw)
+    }
+}
+
+impl Layers {
+    fn read(file: &mut File, table: &[SectionEntry]) -> Result<Self> {
+        let tiles = ChunkedSection::read(file, table, HEIGHTMAP_TILES_SECTION)?;
+        let pages = ChunkedSection::read(file, table, CELL_PAGES_SECTION)?;
+        let (Some(heightmap), Some(cells)) = (tiles, pages) else {
+            return Ok(Layers::Whole {
+                heightmap: read_section(file, table, HEIGHTMAP_SECTION)?,
+                cells: read_section(file, table, CELLS_SECTION)?,
+            });
+        };
+        let tiles = (0..heightmap.chunks.len()).map(|index| heightmap.read_chunk(file, index)).collect::<Result<_>>()?;
+        let pages = (0..cells.chunks.len()).map(|index| cells.read_chunk(file, index)).collect::<Result<_>>()?;
+        Ok(Layers::Chunked { heightmap, tiles, pages })
+    }
+    
+    fn heightmap(&self) -> Result<Grid<f32>> {
+        match self {
+            Layers::Whole { heightmap, .. } => decode(heightmap),
+            Layers::Chunked { heightmap, tiles, .. } => {
+                let [width, height, _] = heightmap.header;
+                heightmap.check_tiles()?;
+                let mut grid = Grid::new(width, height, 0.0);
+                for (chunk, tile) in heightmap.chunks.iter().zip(tiles) {
+                    grid.paste(&decode_tile(chunk, tile)?, chunk.bounds[0] as i64, chunk.bounds[1] as i64);
+                }
+                Ok(grid)
+            }
+        }
+    }
+    
+    fn cells<T: DeserializeOwned>(&self) -> Result<Vec<T>> {
+        match self {
+            Layers::Whole { cells, .. } => decode(cells),
+            Layers::Chunked { pages, .. } => {
+                let mut cells = Vec::new();
+                for page in pages {
+                    cells.extend(decode::<Vec<T>>(page)?);
+                }
+                Ok(cells)
+            }
+        }
+    }
+}
+
+/// Read the section table, checking every section lies within the file
+pub(crate) fn read_table(file: &mut File) -> Result<Vec<SectionEntry>> {
+    let file_len = file.metadata()?.len();
+    let mut header = [0u8; 16];
+    file.read_exact(&mut header)?;
+    if header[..8] != MAGIC {
+        return Err(WorldFoundryError::Import("Not a World Foundry binary map".to_string()));
+    }
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    if version > FORMAT_VERSION {
+        return Err(WorldFoundryError::Import(format!(
+            "Binary map format version {} is newer than supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    
+    let count = u32::from_le_bytes(header[12..16].try_into().unwrap()) as u64;
+    let table_len = count
+        .checked_mul(TABLE_ENTRY_SIZE as u64)
+        .filter(|&len| len <= file_len.saturating_sub(header.len() as u64))
+        .ok_or_else(|| corrupt("section table is longer than the file"))?;
+    let mut table = vec![0u8; table_len as usize];
+    file.read_exact(&mut table)?;
+    
+    table
+        .chunks_exact(TABLE_ENTRY_SIZE)
+        .map(|entry| {
+            let entry = SectionEntry {
+                tag: entry[0..4].try_into().unwrap(),
+                codec: entry[4],
+                offset: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+                stored: u64::from_le_bytes(entry[16..24].try_into().unwrap()),
+                raw: u64::from_le_bytes(entry[24..32].try_into().unwrap()),
+            };
+            match entry.offset.checked_add(entry.stored) {
+                Some(end) if end <= file_len => Ok(entry),
+                _ => Err(corrupt(&format!("section {} lies outside the file", String::from_utf8_lossy(&entry.tag)))),
+            }
+        })
+        .collect()
+}
+
+/// Read and decompress one section
+pub(crate) fn read_section(file: &mut File, table: &[SectionEntry], tag: [u8; 4]) -> Result<Vec<u8>> {
+    let entry = table
+        .iter()
+        .find(|entry| entry.tag == tag)
+        .ok_or_else(|| WorldFoundryError::Import(format!("Missing section {}", String::from_utf8_lossy(&tag))))?;
+    
+    let mut stored = vec![0u8; entry.stored as usize];
+    file.seek(SeekFrom::Start(entry.offset))?;
+    file.read_exact(&mut stored)?;
+    
+    match entry.codec {
+        CODEC_NONE => Ok(stored),
+        CODEC_ZSTD => decompress(&stored, entry.raw),
+        codec => Err(WorldFoundryError::Import(format!("Unknown section codec {}", codec))),
+    }
+}
+
+/// Decompress zstd data that should come to `raw` bytes
+fn decompress(stored: &[u8], raw: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(raw.min(PREALLOCATION_LIMIT) as usize);
+    // One byte past the expected length tells longer data apart
+    zstd::stream::read::Decoder::new(stored)?
+        .take(raw.saturating_add(1))
+        .read_to_end(&mut data)
+        .map_err(|e| corrupt(&format!("cannot decompress: {}", e)))?;
+    if data.len() as u64 != raw {
+        return Err(corrupt("decompressed data has the wrong length"));
+    }
+    Ok(data)
+}
+
+/// Reassemble a file written with an older schema as one document and migrate it
+///
+/// Metadata is passed already decoded: its id is stored as raw bytes, which
+/// have no JSON equivalent.
+fn import_legacy(metadata: MapMetadata, layers: &Layers, entities: &[u8]) -> Result<WorldMap> {
+    let mut document = match decode::<serde_json::Value>(entities)? {
+        serde_json::Value::Object(entities) => entities,
+        _ => return Err(WorldFoundryError::Import("Corrupt section: entities are not a map".to_string())),
+    };
+    document.insert("metadata".to_string(), serde_json::to_value(metadata)?);
+    let heightmap = match layers {
+        Layers::Whole { heightmap, .. } => decode(heightmap)?,
+        Layers::Chunked { .. } => serde_json::to_value(layers.heightmap()?)?,
+    };
+    document.insert("heightmap".to_string(), heightmap);
+    document.insert("cells".to_string(), serde_json::Value::Array(layers.cells()?));
+    WorldMap::from_json_value(serde_json::Value::Object(document))
+}
+
+/// Grid of one heightmap tile
+pub(crate) fn decode_tile(chunk: &ChunkEntry, bytes: &[u8]) -> Result<Grid<f32>> {
+    let [x, y, x_end, y_end] = chunk.bounds.map(|bound| bound as u32);
+    let (width, height) = (x_end.saturating_sub(x), y_end.saturating_sub(y));
+    let len = (width as usize).checked_mul(height as usize).and_then(|len| len.checked_mul(4));
+    if len != Some(bytes.len()) {
+        return Err(corrupt("heightmap tile has the wrong size"));
+    }
+    let data = bytes.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())).collect();
+    Ok(Grid { width, height, data })
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    rmp_serde::from_slice(bytes).map_err(|e| corrupt(&e.to_string()))
+}
+
+fn corrupt(problem: &str) -> WorldFoundryError {
+    WorldFoundryError::Import(format!("Corrupt section: {}", problem))
+}
+
+fn join<T>(handle: std::thread::ScopedJoinHandle<'_, Result<T>>) -> Result<T> {
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(WorldFoundryError::Import("Section decoder panicked".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::{grid_world, TempFile};
+    use crate::export::{MapExporter, NativeExporter};
+    
+    fn save(world_map: &WorldMap) -> TempFile {
+        let file = TempFile::new("wfm");
+        NativeExporter::new().export(world_map, &file.0).unwrap();
+        file
+    }
+    
+    #[test]
+    fn worlds_round_trip_through_the_binary_format() {
+        let world_map = grid_world(8);
+        let file = save(&world_map);
+        let importer = NativeImporter::new();
+        assert!(importer.can_import(&file.0));
+        
+        let imported = importer.import(&file.0).unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&world_map).unwrap());
+        assert_eq!(importer.read_metadata(&file.0).unwrap().name, world_map.metadata.name);
+        let thumbnail = importer.read_thumbnail(&file.0).unwrap().unwrap();
+        assert!(thumbnail.starts_with(b"\x89PNG"));
+    }
+    
+    #[test]
+    fn older_schemas_are_migrated_and_newer_ones_refused() {
+        let mut world_map = grid_world(4);
+        world_map.metadata.schema_version = 1;
+        let imported = NativeImporter::new().import(&save(&world_map).0).unwrap();
+        assert_eq!(imported.metadata.schema_version, SCHEMA_VERSION);
+        assert_eq!(serde_json::to_value(&imported.cells).unwrap(), serde_json::to_value(&world_map.cells).unwrap());
+        assert_eq!(imported.burgs.len(), world_map.burgs.len());
+        
+        world_map.metadata.schema_version = SCHEMA_VERSION + 1;
+        let result = NativeImporter::new().import(&save(&world_map).0);
+        assert!(matches!(result, Err(WorldFoundryError::UnsupportedSchema { .. })));
+    }
+    
+    #[test]
+    fn corrupt_sizes_are_import_errors() {
+        let bytes = NativeExporter::new().to_bytes(&grid_world(4)).unwrap();
+        let corrupted = |patch: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            patch(&mut bytes);
+            let file = TempFile::new("wfm");
+            std::fs::write(&file.0, bytes).unwrap();
+            NativeImporter::new().import(&file.0)
+        };
+        let is_import_error = |result: Result<WorldMap>| matches!(result, Err(WorldFoundryError::Import(_)));
+        
+        // Section count
+        assert!(is_import_error(corrupted(&|bytes| bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes()))));
+        // Stored length of the metadata section
+        assert!(is_import_error(corrupted(&|bytes| bytes[32..40].copy_from_slice(&u64::MAX.to_le_bytes()))));
+        // Raw length of the metadata section
+        assert!(is_import_error(corrupted(&|bytes| bytes[40..48].copy_from_slice(&(u64::MAX / 2).to_le_bytes()))));
+        // Truncated file
+        assert!(is_import_error(corrupted(&|bytes| bytes.truncate(bytes.len() / 2))));
+    }
+    
+    #[test]
+    fn tiles_must_match_their_bounds() {
+        let chunk = |bounds: [f32; 4]| ChunkEntry { offset: 0, stored: 0, raw: 0, bounds };
+        let tile = decode_tile(&chunk([2.0, 3.0, 4.0, 4.0]), &[0; 8]).unwrap();
+        assert_eq!((tile.width, tile.height), (2, 1));
+        assert!(decode_tile(&chunk([0.0, 0.0, 4.0, 4.0]), &[0; 8]).is_err());
+        // Bounds whose area overflows 32 bits
+        assert!(decode_tile(&chunk([0.0, 0.0, 65536.0, 65537.0]), &[0; 16]).is_err());
+    }
+}
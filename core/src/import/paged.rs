@@ -0,0 +1,370 @@
+//! Storage-backed access to `.wfm` worlds too large to hold in memory
+//!
+//! A `PagedWorldMap` keeps the metadata and the entities loaded, and reads
+//! heightmap tiles and cell pages from the file as they are asked for. Chunks
+//! read stay in a cache until it outgrows its budget, when the least recently
+//! used are dropped, so a viewer panning over a world touches the disk only at
+//! the edges of what it has seen.
+
+use super::native::{
+    decode, decode_tile, read_section, read_table, ChunkedSection, Entities, NativeImporter, CELL_PAGES_SECTION,
+    ENTITIES_SECTION, HEIGHTMAP_TILES_SECTION, METADATA_SECTION, THUMBNAIL_SECTION,
+};
+use super::MapImporter;
+use crate::data::*;
+use crate::platform::PlatformCapabilities;
+use crate::{Result, WorldFoundryError};
+use nalgebra::Point2;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Cache budget when the platform sets no memory limit
+const DEFAULT_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How a paged world uses memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagingOptions {
+    /// Most bytes of decoded tiles and pages kept at once; a single chunk is
+    /// kept even if it is larger. Pages count `size_of::<Cell>()` per cell,
+    /// not the names and lists a cell owns on the heap
+    pub cache_bytes: u64,
+}
+
+/// World read from a file, in full or paged
+#[allow(clippy::large_enum_variant)]
+pub enum OpenedWorld {
+    Full(WorldMap),
+    Paged(PagedWorldMap),
+}
+
+/// World whose heightmap and cells stay in its file until needed
+pub struct PagedWorldMap {
+    file: File,
+    /// The world without its heightmap and cells
+    skeleton: WorldMap,
+    tiles: ChunkedSection,
+    pages: ChunkedSection,
+    cache: ChunkCache,
+}
+
+/// Decoded chunks, with the tick of their last use
+struct ChunkCache {
+    limit: u64,
+    used: u64,
+    tick: u64,
+    tiles: HashMap<usize, (Grid<f32>, u64)>,
+    pages: HashMap<usize, (Vec<Cell>, u64)>,
+}
+
+impl PagingOptions {
+    /// A quarter of the platform's memory limit, leaving the rest to the
+    /// entities, rendering and the platform itself
+    pub fn for_platform(capabilities: &PlatformCapabilities) -> Self {
+        Self { cache_bytes: capabilities.memory_limit.map_or(DEFAULT_CACHE_BYTES, |limit| limit / 4) }
+    }
+}
+
+impl Default for PagingOptions {
+    fn default() -> Self {
+        Self { cache_bytes: DEFAULT_CACHE_BYTES }
+    }
+}
+
+impl NativeImporter {
+    /// Approximate memory a file takes once imported in full, in bytes
+    pub fn memory_needed(&self, file_path: &Path) -> Result<u64> {
+        let mut file = File::open(file_path)?;
+        let table = read_table(&mut file)?;
+        let chunked = [HEIGHTMAP_TILES_SECTION, CELL_PAGES_SECTION];
+        // The thumbnail is not part of the imported world
+        let mut bytes: u64 = table
+            .iter()
+            .filter(|entry| entry.tag != THUMBNAIL_SECTION && !chunked.contains(&entry.tag))
+            .map(|entry| entry.raw)
+            .sum();
+        for tag in chunked {
+            if let Some(section) = ChunkedSection::read(&mut file, &table, tag)? {
+                bytes += section.chunks.iter().map(|chunk| chunk.raw).sum::<u64>();
+            }
+        }
+        Ok(bytes)
+    }
+    
+    /// Import a file in full if it fits in the platform's memory, and open it
+    /// paged otherwise
+    pub fn open(&self, file_path: &Path, capabilities: &PlatformCapabilities) -> Result<OpenedWorld> {
+        if capabilities.fits_in_memory(self.memory_needed(file_path)?) {
+            self.import(file_path).map(OpenedWorld::Full)
+        } else {
+            PagedWorldMap::open(file_path, PagingOptions::for_platform(capabilities)).map(OpenedWorld::Paged)
+        }
+    }
+}
+
+impl PagedWorldMap {
+    /// Open a `.wfm` file, reading only its metadata, entities and chunk indices
+    pub fn open(file_path: &Path, options: PagingOptions) -> Result<Self> {
+        let mut file = File::open(file_path)?;
+        let table = read_table(&mut file)?;
+        let metadata: MapMetadata = decode(&read_section(&mut file, &table, METADATA_SECTION)?)?;
+        check_schema_version(metadata.schema_version)?;
+        if metadata.schema_version < SCHEMA_VERSION {
+            return Err(WorldFoundryError::Import(
+                "Map uses an older schema; import and save it again to open it paged".to_string(),
+            ));
+        }
+        let (Some(tiles), Some(pages)) = (
+            ChunkedSection::read(&mut file, &table, HEIGHTMAP_TILES_SECTION)?,
+            ChunkedSection::read(&mut file, &table, CELL_PAGES_SECTION)?,
+        ) else {
+            return Err(WorldFoundryError::Import(
+                "Map was saved without tiles and pages; save it again to open it paged".to_string(),
+            ));
+        };
+        tiles.check_tiles()?;
+        pages.check_pages()?;
+        let entities: Entities = decode(&read_section(&mut file, &table, ENTITIES_SECTION)?)?;
+        
+        let skeleton = WorldMap {
+            metadata,
+            heightmap: Grid::new(0, 0, 0.0),
+            cells: Vec::new(),
+            features: entities.features,
+            cultures: entities.cultures,
+            states: entities.states,
+            provinces: entities.provinces,
+            burgs: entities.burgs,
+            rivers: entities.rivers,
+            routes: entities.routes,
+            markers: entities.markers,
+            zones: entities.zones,
+            diplomacy: entities.diplomacy,
+            history: entities.history,
+            calendar: entities.calendar,
+            events: entities.events,
+            topology: TopologyCache::default(),
+        };
+        Ok(Self { file, skeleton, tiles, pages, cache: ChunkCache::new(options.cache_bytes) })
+    }
+    
+    pub fn metadata(&self) -> &MapMetadata {
+        &self.skeleton.metadata
+    }
+    
+    /// The world without its heightmap and cells: states, burgs, rivers and the
+    /// other entities, diplomacy, history and calendar
+    ///
+    /// Its `heightmap` and `cells` are empty, so methods that look at cells do
+    /// not apply to it.
+    pub fn skeleton(&self) -> &WorldMap {
+        &self.skeleton
+    }
+    
+    pub fn cell_count(&self) -> usize {
+        self.pages.header[0] as usize
+    }
+    
+    /// Width and height of the heightmap, in grid points
+    pub fn heightmap_size(&self) -> (u32, u32) {
+        (self.tiles.header[0], self.tiles.header[1])
+    }
+    
+    /// Bytes of decoded tiles and pages currently cached
+    pub fn cached_bytes(&self) -> u64 {
+        self.cache.used
+    }
+    
+    /// Cell at an index of the cell list; `None` past its end
+    pub fn cell(&mut self, index: usize) -> Result<Option<Cell>> {
+        let page_size = self.pages.header[1].max(1) as usize;
+        if index >= self.cell_count() {
+            return Ok(None);
+        }
+        let page = self.page(index / page_size)?;
+        Ok(page.get(index % page_size).cloned())
+    }
+    
+    /// Cells whose centres lie in a rectangle of map coordinates, in the order
+    /// they are stored
+    pub fn cells_in(&mut self, min: Point2<f32>, max: Point2<f32>) -> Result<Vec<Cell>> {
+        let inside = |point: &Point2<f32>| point.x >= min.x && point.y >= min.y && point.x <= max.x && point.y <= max.y;
+        let mut cells = Vec::new();
+        for index in 0..self.pages.chunks.len() {
+            let [x, y, x_end, y_end] = self.pages.chunks[index].bounds;
+            if x_end < min.x || y_end < min.y || x > max.x || y > max.y {
+                continue;
+            }
+            cells.extend(self.page(index)?.iter().filter(|cell| inside(&cell.coordinates)).cloned());
+        }
+        Ok(cells)
+    }
+    
+    /// Visit every cell in the order they are stored, reading one page at a
+    /// time past the cache so a full pass does not evict what is being viewed
+    pub fn for_each_cell(&mut self, mut visit: impl FnMut(&Cell)) -> Result<()> {
+        for index in 0..self.pages.chunks.len() {
+            match self.cache.pages.get(&index) {
+                Some((page, _)) => page.iter().for_each(&mut visit),
+                None => decode::<Vec<Cell>>(&self.pages.read_chunk(&mut self.file, index)?)?.iter().for_each(&mut visit),
+            }
+        }
+        Ok(())
+    }
+    
+    /// Heightmap value at a grid point; `None` outside the grid
+    pub fn height(&mut self, x: u32, y: u32) -> Result<Option<f32>> {
+        let (width, height) = self.heightmap_size();
+        if x >= width || y >= height {
+            return Ok(None);
+        }
+        let tile_size = self.tiles.header[2].max(1);
+        let tiles_across = width.div_ceil(tile_size);
+        let tile = self.tile(((y / tile_size) * tiles_across + x / tile_size) as usize)?;
+        Ok(tile.get(x % tile_size, y % tile_size).copied())
+    }
+    
+    /// Copy of a rectangle of the heightmap, clipped to it like `Grid::crop`
+    pub fn heightmap_region(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<Grid<f32>> {
+        let (grid_width, grid_height) = self.heightmap_size();
+        let x_end = x.saturating_add(width).min(grid_width);
+        let y_end = y.saturating_add(height).min(grid_height);
+        let (x, y) = (x.min(x_end), y.min(y_end));
+        let mut region = Grid::new(x_end - x, y_end - y, 0.0);
+        for index in 0..self.tiles.chunks.len() {
+            let [tile_x, tile_y, tile_x_end, tile_y_end] = self.tiles.chunks[index].bounds.map(|bound| bound as u32);
+            if tile_x_end <= x || tile_y_end <= y || tile_x >= x_end || tile_y >= y_end {
+                continue;
+            }
+            let tile = self.tile(index)?;
+            region.paste(tile, tile_x as i64 - x as i64, tile_y as i64 - y as i64);
+        }
+        Ok(region)
+    }
+    
+    fn tile(&mut self, index: usize) -> Result<&Grid<f32>> {
+        if !self.cache.tiles.contains_key(&index) {
+            let tile = decode_tile(&self.tiles.chunks[index], &self.tiles.read_chunk(&mut self.file, index)?)?;
+            self.cache.make_room(tile.data.len() as u64 * 4);
+            self.cache.tiles.insert(index, (tile, 0));
+        }
+        let tick = self.cache.touch();
+        let (tile, used) = self.cache.tiles.get_mut(&index).unwrap();
+        *used = tick;
+        Ok(tile)
+    }
+    
+    fn page(&mut self, index: usize) -> Result<&[Cell]> {
+        if !self.cache.pages.contains_key(&index) {
+            let page: Vec<Cell> = decode(&self.pages.read_chunk(&mut self.file, index)?)?;
+            self.cache.make_room((page.len() * std::mem::size_of::<Cell>()) as u64);
+            self.cache.pages.insert(index, (page, 0));
+        }
+        let tick = self.cache.touch();
+        let (page, used) = self.cache.pages.get_mut(&index).unwrap();
+        *used = tick;
+        Ok(page)
+    }
+}
+
+impl ChunkCache {
+    fn new(limit: u64) -> Self {
+        Self { limit, used: 0, tick: 0, tiles: HashMap::new(), pages: HashMap::new() }
+    }
+    
+    fn touch(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+    
+    /// Count in a chunk about to be added, first dropping the least recently
+    /// used chunks until it fits
+    fn make_room(&mut self, bytes: u64) {
+        while self.used + bytes > self.limit {
+            let oldest_tile = self.tiles.iter().map(|(&index, (_, used))| (*used, index)).min();
+            let oldest_page = self.pages.iter().map(|(&index, (_, used))| (*used, index)).min();
+            match (oldest_tile, oldest_page) {
+                (Some((tile_used, index)), page) if page.is_none_or(|(page_used, _)| tile_used < page_used) => {
+                    let (tile, _) = self.tiles.remove(&index).unwrap();
+                    self.used -= tile.data.len() as u64 * 4;
+                }
+                (_, Some((_, index))) => {
+                    let (page, _) = self.pages.remove(&index).unwrap();
+                    self.used -= (page.len() * std::mem::size_of::<Cell>()) as u64;
+                }
+                _ => break,
+            }
+        }
+        self.used += bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::{grid_world, TempFile};
+    use crate::export::{MapExporter, NativeExporter};
+    
+    /// World of two pages of cells and six tiles of heights, saved to a file
+    fn saved_world() -> (WorldMap, TempFile) {
+        let mut world_map = grid_world(70);
+        world_map.heightmap = Grid::from_fn(600, 300, |x, y| (x * 7 + y * 3) as f32 / 1000.0);
+        let file = TempFile::new("wfm");
+        NativeExporter::new().export(&world_map, &file.0).unwrap();
+        (world_map, file)
+    }
+    
+    #[test]
+    fn paged_worlds_read_what_was_saved() {
+        let (world_map, file) = saved_world();
+        let mut paged = PagedWorldMap::open(&file.0, PagingOptions::default()).unwrap();
+        assert_eq!(paged.cell_count(), world_map.cells.len());
+        assert_eq!(paged.heightmap_size(), (600, 300));
+        assert_eq!(paged.skeleton().states.len(), world_map.states.len());
+        
+        for (x, y) in [(0, 0), (255, 255), (256, 0), (599, 299), (300, 260)] {
+            assert_eq!(paged.height(x, y).unwrap(), world_map.heightmap.get(x, y).copied());
+        }
+        assert_eq!(paged.height(600, 0).unwrap(), None);
+        let region = paged.heightmap_region(250, 250, 20, 100).unwrap();
+        assert_eq!(region.data, world_map.heightmap.crop(250, 250, 20, 100).data);
+        
+        for index in [0, 4095, 4096, 4899] {
+            assert_eq!(paged.cell(index).unwrap().map(|cell| cell.id), Some(world_map.cells[index].id));
+        }
+        assert!(paged.cell(4900).unwrap().is_none());
+        
+        let (min, max) = (Point2::new(100.0, 400.0), Point2::new(150.0, 650.0));
+        let inside = |cell: &&Cell| {
+            let point = cell.coordinates;
+            point.x >= min.x && point.y >= min.y && point.x <= max.x && point.y <= max.y
+        };
+        let expected: Vec<CellId> = world_map.cells.iter().filter(inside).map(|cell| cell.id).collect();
+        let cells: Vec<CellId> = paged.cells_in(min, max).unwrap().iter().map(|cell| cell.id).collect();
+        assert_eq!(cells, expected);
+    }
+    
+    #[test]
+    fn the_cache_drops_least_recently_used_chunks() {
+        let (world_map, file) = saved_world();
+        let tile_bytes = 256 * 256 * 4;
+        let mut paged = PagedWorldMap::open(&file.0, PagingOptions { cache_bytes: 2 * tile_bytes }).unwrap();
+        
+        paged.height(0, 0).unwrap();
+        paged.height(300, 0).unwrap();
+        assert_eq!(paged.cached_bytes(), 2 * tile_bytes);
+        // Touching the first tile again makes the second the oldest
+        paged.height(1, 1).unwrap();
+        // The bottom row of tiles is 44 points high
+        paged.height(0, 280).unwrap();
+        assert_eq!(paged.cached_bytes(), tile_bytes + 256 * 44 * 4);
+        assert!(paged.cache.tiles.contains_key(&0) && !paged.cache.tiles.contains_key(&1));
+        
+        // Chunks larger than the whole budget are still kept, one at a time
+        let mut paged = PagedWorldMap::open(&file.0, PagingOptions { cache_bytes: 1 }).unwrap();
+        assert_eq!(paged.cell(10).unwrap().map(|cell| cell.id), Some(world_map.cells[10].id));
+        assert_eq!((paged.cache.tiles.len(), paged.cache.pages.len()), (0, 1));
+        assert_eq!(paged.height(5, 5).unwrap(), world_map.heightmap.get(5, 5).copied());
+        assert_eq!((paged.cache.tiles.len(), paged.cache.pages.len()), (1, 0));
+    }
+}
@@ -3,15 +3,21 @@
 //! A high-performance, cross-platform fantasy map generator core engine
 //! that provides world generation, rendering, and import/export capabilities.
 
+pub mod analytics;
 pub mod data;
+pub mod editing;
 pub mod generation;
+pub mod navigation;
 pub mod rendering;
 pub mod import;
 pub mod export;
 pub mod platform;
 
+pub use analytics::*;
 pub use data::*;
+pub use editing::*;
 pub use generation::*;
+pub use navigation::*;
 pub use rendering::*;
 pub use import::*;
 pub use export::*;
@@ -29,6 +35,9 @@ pub enum WorldFoundryError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
     
+    #[error("Map uses schema version {found}, newer than the supported version {supported}; update World Foundry to open it")]
+    UnsupportedSchema { found: u32, supported: u32 },
+    
     #[error("Import error: {0}")]
     Import(String),
     
@@ -41,6 +50,12 @@ pub enum WorldFoundryError {
     #[error("Generation error: {0}")]
     Generation(String),
     
+    #[error("Edit error: {0}")]
+    Edit(String),
+    
+    #[error("Merge error: {0}")]
+    Merge(String),
+    
     #[error("Platform error: {0}")]
     Platform(String),
 }
@@ -0,0 +1,338 @@
+//! Travel-time pathfinding over the cell graph
+//!
+//! Journeys move from cell centre to neighbouring cell centre. Each step takes
+//! as long as its length at the traveller's speed, which roads raise and rough
+//! biomes and steep slopes lower; land travellers lose time fording rivers
+//! where no road bridges them, while ships keep to the sea, lakes and rivers.
+
+use crate::{BiomeType, CellId, WorldMap};
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+/// How a journey is made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TravelMode {
+    Foot,
+    Horse,
+    Cart,
+    Ship,
+}
+
+/// Fastest journey found between two points
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journey {
+    pub mode: TravelMode,
+    /// Cells passed through, from start to end
+    pub cells: Vec<CellId>,
+    /// Distance covered, in the map's distance unit
+    pub distance: f32,
+    /// Travel time, in days
+    pub days: f32,
+}
+
+/// Route finder for one world
+///
+/// Building it indexes roads and rivers once, so many journeys can be planned
+/// on the same map cheaply.
+pub struct Pathfinder<'a> {
+    world_map: &'a WorldMap,
+    /// Pairs of neighbouring cell indices joined by a route, lower index first
+    roads: HashSet<(usize, usize)>,
+    /// Cells a river flows through
+    rivers: Vec<bool>,
+    /// Elevation of every cell, in metres
+    elevations: Vec<f32>,
+    /// Kilometres per map unit
+    kilometres: f32,
+}
+
+/// Frontier entry of the search, ordered so the heap pops the earliest estimate
+struct Visit {
+    estimate: f32,
+    index: usize,
+}
+
+impl TravelMode {
+    /// Kilometres covered in a day over flat, open ground
+    pub fn speed(self) -> f32 {
+        match self {
+            TravelMode::Foot => 30.0,
+            TravelMode::Horse => 60.0,
+            TravelMode::Cart => 25.0,
+            TravelMode::Ship => 120.0,
+        }
+    }
+    
+    /// Speed gained on a road
+    fn road_factor(self) -> f32 {
+        match self {
+            TravelMode::Foot | TravelMode::Horse => 1.25,
+            TravelMode::Cart => 1.6,
+            TravelMode::Ship => 1.0,
+        }
+    }
+    
+    /// Days lost crossing a river without a bridge
+    fn fording_days(self) -> f32 {
+        match self {
+            TravelMode::Foot | TravelMode::Horse => 0.25,
+            TravelMode::Cart => 0.5,
+            TravelMode::Ship => 0.0,
+        }
+    }
+    
+    /// Speed kept off-road in a biome; 0 where the mode cannot go
+    fn terrain_factor(self, biome: BiomeType, river: bool) -> f32 {
+        if self == TravelMode::Ship {
+            return if biome.is_water() || river { 1.0 } else { 0.0 };
+        }
+        let terrain = match biome {
+            BiomeType::Marine | BiomeType::Freshwater => return 0.0,
+            BiomeType::Temperate => 1.0,
+            BiomeType::Hot | BiomeType::Cold => 0.8,
+            BiomeType::Dry => 0.7,
+            BiomeType::Frozen => 0.5,
+        };
+        if self == TravelMode::Cart {
+            terrain * 0.6
+        } else {
+            terrain
+        }
+    }
+}
+
+impl<'a> Pathfinder<'a> {
+    pub fn new(world_map: &'a WorldMap) -> Self {
+        let topology = world_map.topology();
+        let mut roads = HashSet::new();
+        for route in &world_map.routes {
+            let indices: Vec<usize> = route.cells.iter().filter_map(|&cell| topology.index_of(cell)).collect();
+            roads.extend(indices.windows(2).map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1]))));
+        }
+        let mut rivers = vec![false; world_map.cells.len()];
+        for index in world_map.rivers.iter().flat_map(|river| &river.cells).filter_map(|&cell| topology.index_of(cell)) {
+            rivers[index] = true;
+        }
+        let settings = &world_map.metadata.settings;
+        let elevations = world_map
+            .cells
+            .iter()
+            .map(|cell| settings.elevation(cell.height) * settings.height_unit.metres().unwrap_or(1.0) as f32)
+            .collect();
+        // Unknown distance units are taken as kilometres
+        let kilometres = settings.length(1.0) * settings.distance_unit.kilometres().unwrap_or(1.0) as f32;
+        Self { world_map, roads, rivers, elevations, kilometres }
+    }
+    
+    /// Fastest journey between the cells containing two points; `None` when
+    /// the mode cannot get from one to the other
+    ///
+    /// Ships may start and end on a land cell, such as a port, but otherwise
+    /// keep to water and river cells.
+    pub fn find(&self, from: Point2<f32>, to: Point2<f32>, mode: TravelMode) -> Option<Journey> {
+        let topology = self.world_map.topology();
+        let start = topology.cell_at(from)?;
+        let goal = topology.cell_at(to)?;
+        let cells = &self.world_map.cells;
+        // Fastest conceivable pace, downhill on a road, keeps the estimate optimistic
+        let fastest = mode.speed() * mode.road_factor() * tobler(-0.05) / tobler(0.0) / self.kilometres;
+        let estimate = |index: usize| nalgebra::distance(&cells[index].coordinates, &cells[goal].coordinates) / fastest;
+        
+        let mut days = vec![f32::INFINITY; cells.len()];
+        let mut previous = vec![usize::MAX; cells.len()];
+        let mut done = vec![false; cells.len()];
+        let mut frontier = BinaryHeap::new();
+        days[start] = 0.0;
+        frontier.push(Visit { estimate: estimate(start), index: start });
+        
+        while let Some(Visit { index, .. }) = frontier.pop() {
+            if index == goal {
+                break;
+            }
+            if std::mem::replace(&mut done[index], true) {
+                continue;
+            }
+            for &next in &topology.neighbours[index] {
+                let Some(step) = self.step_days(index, next, mode, next == goal) else {
+                    continue;
+                };
+                let arrival = days[index] + step;
+                if arrival < days[next] {
+                    days[next] = arrival;
+                    previous[next] = index;
+                    frontier.push(Visit { estimate: arrival + estimate(next), index: next });
+                }
+            }
+        }
+        if !days[goal].is_finite() {
+            return None;
+        }
+        
+        let mut path = vec![goal];
+        while let Some(&last) = path.last().filter(|&&last| last != start) {
+            path.push(previous[last]);
+        }
+        path.reverse();
+        let map_units: f32 = path
+            .windows(2)
+            .map(|pair| nalgebra::distance(&cells[pair[0]].coordinates, &cells[pair[1]].coordinates))
+            .sum();
+        Some(Journey {
+            mode,
+            cells: path.iter().map(|&index| cells[index].id).collect(),
+            distance: self.world_map.metadata.settings.length(map_units),
+            days: days[goal],
+        })
+    }
+    
+    /// Days needed to move between neighbouring cells; `None` if the mode cannot
+    fn step_days(&self, from: usize, to: usize, mode: TravelMode, arriving: bool) -> Option<f32> {
+        let cells = &self.world_map.cells;
+        let kilometres = nalgebra::distance(&cells[from].coordinates, &cells[to].coordinates) * self.kilometres;
+        // Sea routes carry ships no faster, and cannot be walked
+        let road = mode != TravelMode::Ship
+            && !cells[to].biome.is_water()
+            && self.roads.contains(&(from.min(to), from.max(to)));
+        
+        let (mut speed, mut delay) = (mode.speed(), 0.0);
+        if road {
+            speed *= mode.road_factor();
+        } else {
+            let terrain = mode.terrain_factor(cells[to].biome, self.rivers[to]);
+            if terrain > 0.0 {
+                speed *= terrain;
+            } else if !(mode == TravelMode::Ship && arriving) {
+                // Ships may put in at the destination
+                return None;
+            }
+            if self.rivers[to] && !self.rivers[from] {
+                delay = mode.fording_days();
+            }
+        }
+        if mode != TravelMode::Ship && kilometres > 0.0 {
+            let grade = (self.elevations[to] - self.elevations[from]) / (kilometres * 1000.0);
+            speed *= tobler(grade) / tobler(0.0);
+        }
+        Some(kilometres / speed + delay)
+    }
+}
+
+impl WorldMap {
+    /// Fastest journey between two points; see `Pathfinder::find`
+    pub fn find_journey(&self, from: Point2<f32>, to: Point2<f32>, mode: TravelMode) -> Option<Journey> {
+        Pathfinder::new(self).find(from, to, mode)
+    }
+}
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Walking pace on a slope relative to its best, after Tobler's hiking function
+fn tobler(grade: f32) -> f32 {
+    (-3.5 * (grade + 0.05).abs()).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testing::grid_world;
+    use crate::{DistanceUnit, River, RiverId, RiverType, Route, RouteId};
+    
+    /// Centre of the cell in column `x` and row `y` of a grid world
+    fn at(x: u32, y: u32) -> Point2<f32> {
+        Point2::new(x as f32 * 10.0 + 5.0, y as f32 * 10.0 + 5.0)
+    }
+    
+    fn world() -> WorldMap {
+        let mut world_map = grid_world(6);
+        world_map.metadata.settings.distance_unit = DistanceUnit::Kilometre;
+        world_map.metadata.settings.distance_scale = 1.0;
+        world_map
+    }
+    
+    #[test]
+    fn land_journeys_follow_the_terrain() {
+        let world_map = world();
+        let walk = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Foot).unwrap();
+        assert_eq!(walk.cells.first(), Some(&CellId(13)));
+        assert_eq!(walk.cells.last(), Some(&CellId(17)));
+        assert!((walk.distance - 40.0).abs() < 1e-3);
+        assert!(walk.days > 40.0 / TravelMode::Foot.speed());
+        assert_eq!(Pathfinder::new(&world_map).find(at(1, 2), at(5, 2), TravelMode::Foot), Some(walk.clone()));
+        
+        let ride = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Horse).unwrap();
+        let cart = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Cart).unwrap();
+        assert!(ride.days < walk.days && walk.days < cart.days);
+        
+        // The land rises eastwards, so the way back is downhill and quicker
+        let back = world_map.find_journey(at(5, 2), at(1, 2), TravelMode::Foot).unwrap();
+        assert!(back.days < walk.days);
+        
+        assert_eq!(world_map.find_journey(at(1, 2), at(0, 2), TravelMode::Foot), None);
+        assert_eq!(world_map.find_journey(at(1, 2), Point2::new(-50.0, 0.0), TravelMode::Foot), None);
+    }
+    
+    #[test]
+    fn roads_speed_travellers_and_bridge_rivers() {
+        let mut world_map = world();
+        let before = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Foot).unwrap();
+        world_map.rivers.push(River {
+            id: RiverId(1),
+            source: CellId(3),
+            mouth: CellId(33),
+            discharge: 1.0,
+            length: 50.0,
+            width: 1.0,
+            cells: (0..6).map(|y| CellId(3 + 6 * y)).collect(),
+            basin: RiverId(1),
+            name: "Divide".to_string(),
+            type_: RiverType::River,
+            note: None,
+            attributes: Default::default(),
+        });
+        let fording = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Foot).unwrap();
+        assert!((fording.days - before.days - TravelMode::Foot.fording_days()).abs() < 1e-3);
+        
+        world_map.routes.push(Route {
+            id: RouteId(1),
+            group: 0,
+            cells: (13..18).map(CellId).collect(),
+            feature: Default::default(),
+            length: 40.0,
+        });
+        let road = world_map.find_journey(at(1, 2), at(5, 2), TravelMode::Foot).unwrap();
+        assert_eq!(road.cells, (13..18).map(CellId).collect::<Vec<_>>());
+        assert!(road.days < before.days);
+    }
+    
+    #[test]
+    fn ships_keep_to_water_and_put_in_at_ports() {
+        let world_map = world();
+        let voyage = world_map.find_journey(at(0, 0), at(0, 5), TravelMode::Ship).unwrap();
+        assert_eq!(voyage.cells.len(), 6);
+        assert!((voyage.days - 50.0 / TravelMode::Ship.speed()).abs() < 1e-3);
+        
+        let landing = world_map.find_journey(at(0, 0), at(1, 3), TravelMode::Ship).unwrap();
+        assert_eq!(landing.cells.last(), Some(&CellId(19)));
+        assert_eq!(world_map.find_journey(at(0, 0), at(3, 3), TravelMode::Ship), None);
+    }
+}
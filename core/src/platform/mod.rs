@@ -135,6 +135,14 @@ pub struct PlatformCapabilities {
     pub memory_limit: Option<u64>,
 }
 
+impl PlatformCapabilities {
+    /// Whether data of a given size can be held in memory at once, leaving
+    /// half of the memory limit for everything else
+    pub fn fits_in_memory(&self, bytes: u64) -> bool {
+        self.memory_limit.is_none_or(|limit| bytes <= limit / 2)
+    }
+}
+
 /// Main platform interface
 pub trait Platform: Send + Sync {
     /// Get platform capabilities
@@ -0,0 +1,455 @@
+//! Rendering of coats of arms with Skia
+
+use crate::data::{CoatOfArms, DivisionKind, LineStyle, OrdinaryKind, ShieldShape, Tincture};
+use crate::Result;
+use skia_safe as skia;
+
+/// Side of the square design space all geometry is expressed in
+const UNIT: f32 = 200.0;
+
+/// Renderer for coats of arms
+///
+/// Geometric charges (roundels, lozenges, mullets, crescents...) are drawn
+/// exactly; figurative charges such as beasts are drawn as roundels until a
+/// charge library is available.
+pub struct HeraldryRenderer;
+
+impl HeraldryRenderer {
+    /// Render arms to a square PNG image
+    pub fn render_png(coa: &CoatOfArms, size: u32) -> Result<Vec<u8>> {
+        let info = skia::ImageInfo::new_n32_premul((size as i32, size as i32), None);
+        let mut surface = skia::surfaces::raster(&info, None, None)
+            .ok_or_else(|| crate::WorldFoundryError::Rendering("Failed to create surface".to_string()))?;
+        
+        let canvas = surface.canvas();
+        canvas.clear(skia::Color::TRANSPARENT);
+        Self::draw(canvas, coa, 0.0, 0.0, size as f32);
+        
+        let image = surface.image_snapshot();
+        let data = image.encode(None, skia::EncodedImageFormat::PNG, None)
+            .ok_or_else(|| crate::WorldFoundryError::Rendering("Failed to encode image".to_string()))?;
+        
+        Ok(data.as_bytes().to_vec())
+    }
+    
+    /// Draw arms into the square at `(x, y)` with side `size` of an existing canvas
+    pub fn draw(canvas: &skia::Canvas, coa: &CoatOfArms, x: f32, y: f32, size: f32) {
+        canvas.save();
+        canvas.translate((x, y));
+        canvas.scale((size / UNIT, size / UNIT));
+        
+        let shield = Self::shield_path(&coa.shield);
+        
+        canvas.save();
+        canvas.clip_path(&shield, skia::ClipOp::Intersect, true);
+        
+        Self::paint_region(canvas, &Self::rect_path(0.0, 0.0, UNIT, UNIT), &coa.field);
+        
+        if let Some(division) = &coa.division {
+            if let Some(region) = Self::division_path(&division.kind, &division.line) {
+                Self::paint_region(canvas, &region, &division.tincture);
+            }
+        }
+        
+        for ordinary in &coa.ordinaries {
+            if ordinary.kind == OrdinaryKind::Bordure {
+                let mut paint = Self::fill_paint(&ordinary.tincture);
+                paint.set_style(skia::PaintStyle::Stroke);
+                paint.set_stroke_width(32.0);
+                canvas.draw_path(&shield, &paint);
+            } else if let Some(region) = Self::ordinary_path(&ordinary.kind, &ordinary.line) {
+                Self::paint_region(canvas, &region, &ordinary.tincture);
+            }
+        }
+        
+        for charge in &coa.charges {
+            let radius = 20.0 * charge.size.max(0.1);
+            for position in charge.positions.chars() {
+                if let Some(center) = Self::position(position) {
+                    Self::draw_charge(canvas, &charge.charge, &charge.tincture, center, radius, charge.sinister, charge.reversed);
+                }
+            }
+        }
+        
+        canvas.restore();
+        
+        let mut outline = skia::Paint::default();
+        outline.set_anti_alias(true);
+        outline.set_style(skia::PaintStyle::Stroke);
+        outline.set_stroke_width(2.0);
+        outline.set_color(skia::Color::from_rgb(51, 51, 51));
+        canvas.draw_path(&shield, &outline);
+        
+        canvas.restore();
+    }
+    
+    fn shield_path(shape: &ShieldShape) -> skia::Path {
+        let mut path = skia::Path::new();
+        match shape {
+            ShieldShape::Spanish | ShieldShape::Horsehead => {
+                path.move_to((25.0, 25.0));
+                path.line_to((175.0, 25.0));
+                path.line_to((175.0, 115.0));
+                path.cubic_to((175.0, 200.0), (25.0, 200.0), (25.0, 115.0));
+                path.close();
+            }
+            ShieldShape::French => {
+                path.move_to((25.0, 25.0));
+                path.line_to((175.0, 25.0));
+                path.line_to((175.0, 150.0));
+                path.quad_to((175.0, 172.0), (150.0, 172.0));
+                path.line_to((118.0, 172.0));
+                path.line_to((100.0, 188.0));
+                path.line_to((82.0, 172.0));
+                path.line_to((50.0, 172.0));
+                path.quad_to((25.0, 172.0), (25.0, 150.0));
+                path.close();
+            }
+            ShieldShape::Swiss => {
+                path.move_to((25.0, 25.0));
+                path.quad_to((100.0, 45.0), (175.0, 25.0));
+                path.line_to((175.0, 110.0));
+                path.quad_to((175.0, 165.0), (100.0, 190.0));
+                path.quad_to((25.0, 165.0), (25.0, 110.0));
+                path.close();
+            }
+            ShieldShape::Kite => {
+                path.move_to((100.0, 10.0));
+                path.quad_to((172.0, 20.0), (168.0, 80.0));
+                path.line_to((100.0, 195.0));
+                path.line_to((32.0, 80.0));
+                path.quad_to((28.0, 20.0), (100.0, 10.0));
+                path.close();
+            }
+            ShieldShape::Round => return Self::ellipse_path((100.0, 100.0), 85.0, 85.0),
+            ShieldShape::Oval => return Self::ellipse_path((100.0, 100.0), 70.0, 90.0),
+            ShieldShape::Square => return Self::rect_path(25.0, 25.0, 175.0, 175.0),
+            ShieldShape::Banner => return Self::rect_path(30.0, 10.0, 170.0, 190.0),
+            ShieldShape::Diamond => {
+                path.move_to((100.0, 10.0));
+                path.line_to((190.0, 100.0));
+                path.line_to((100.0, 190.0));
+                path.line_to((10.0, 100.0));
+                path.close();
+            }
+            ShieldShape::Pennon => {
+                path.move_to((15.0, 40.0));
+                path.line_to((190.0, 100.0));
+                path.line_to((15.0, 160.0));
+                path.close();
+            }
+            ShieldShape::Heater | ShieldShape::Other(_) => {
+                path.move_to((25.0, 25.0));
+                path.line_to((175.0, 25.0));
+                path.line_to((175.0, 100.0));
+                path.quad_to((175.0, 160.0), (100.0, 185.0));
+                path.quad_to((25.0, 160.0), (25.0, 100.0));
+                path.close();
+            }
+        }
+        path
+    }
+    
+    /// Area painted in the second tincture of a partition
+    fn division_path(kind: &DivisionKind, line: &LineStyle) -> Option<skia::Path> {
+        let mut path = skia::Path::new();
+        match kind {
+            DivisionKind::PerPale => Self::add_polygon(&mut path, &[(100.0, 0.0), (200.0, 0.0), (200.0, 200.0), (100.0, 200.0)], &[false, false, false, true], line),
+            DivisionKind::PerFess => Self::add_polygon(&mut path, &[(0.0, 100.0), (200.0, 100.0), (200.0, 200.0), (0.0, 200.0)], &[true, false, false, false], line),
+            DivisionKind::PerBend => Self::add_polygon(&mut path, &[(0.0, 0.0), (200.0, 200.0), (0.0, 200.0)], &[true, false, false], line),
+            DivisionKind::PerBendSinister => Self::add_polygon(&mut path, &[(200.0, 0.0), (200.0, 200.0), (0.0, 200.0)], &[false, false, true], line),
+            DivisionKind::PerChevron => Self::add_polygon(&mut path, &[(0.0, 200.0), (100.0, 90.0), (200.0, 200.0)], &[true, true, false], line),
+            DivisionKind::PerCross => {
+                Self::add_polygon(&mut path, &[(100.0, 0.0), (200.0, 0.0), (200.0, 100.0), (100.0, 100.0)], &[false, false, true, true], line);
+                Self::add_polygon(&mut path, &[(0.0, 100.0), (100.0, 100.0), (100.0, 200.0), (0.0, 200.0)], &[true, true, false, false], line);
+            }
+            DivisionKind::PerSaltire => {
+                Self::add_polygon(&mut path, &[(0.0, 0.0), (100.0, 100.0), (0.0, 200.0)], &[true, true, false], line);
+                Self::add_polygon(&mut path, &[(200.0, 0.0), (200.0, 200.0), (100.0, 100.0)], &[false, true, true], line);
+            }
+            DivisionKind::Gyronny => {
+                let center = (100.0, 100.0);
+                for triangle in [
+                    [center, (0.0, 0.0), (100.0, 0.0)],
+                    [center, (200.0, 0.0), (200.0, 100.0)],
+                    [center, (200.0, 200.0), (100.0, 200.0)],
+                    [center, (0.0, 200.0), (0.0, 100.0)],
+                ] {
+                    Self::add_polygon(&mut path, &triangle, &[true, false, true], line);
+                }
+            }
+            DivisionKind::Other(_) => return None,
+        }
+        Some(path)
+    }
+    
+    fn ordinary_path(kind: &OrdinaryKind, line: &LineStyle) -> Option<skia::Path> {
+        // Offset of a diagonal band edge from the diagonal itself
+        const BAND: f32 = 18.0;
+        
+        let mut path = skia::Path::new();
+        match kind {
+            OrdinaryKind::Pale => Self::add_polygon(&mut path, &[(70.0, 0.0), (130.0, 0.0), (130.0, 200.0), (70.0, 200.0)], &[false, true, false, true], line),
+            OrdinaryKind::Fess => Self::add_polygon(&mut path, &[(0.0, 70.0), (200.0, 70.0), (200.0, 130.0), (0.0, 130.0)], &[true, false, true, false], line),
+            OrdinaryKind::Bend => Self::add_polygon(&mut path, &[(-20.0 + BAND, -20.0 - BAND), (220.0 + BAND, 220.0 - BAND), (220.0 - BAND, 220.0 + BAND), (-20.0 - BAND, -20.0 + BAND)], &[true, false, true, false], line),
+            OrdinaryKind::BendSinister => Self::add_polygon(&mut path, &[(220.0 - BAND, -20.0 - BAND), (-20.0 - BAND, 220.0 - BAND), (-20.0 + BAND, 220.0 + BAND), (220.0 + BAND, -20.0 + BAND)], &[true, false, true, false], line),
+            OrdinaryKind::Chief => Self::add_polygon(&mut path, &[(0.0, 0.0), (200.0, 0.0), (200.0, 65.0), (0.0, 65.0)], &[false, false, true, false], line),
+            OrdinaryKind::Base => Self::add_polygon(&mut path, &[(0.0, 150.0), (200.0, 150.0), (200.0, 200.0), (0.0, 200.0)], &[true, false, false, false], line),
+            OrdinaryKind::Chevron => Self::add_polygon(&mut path, &[(0.0, 160.0), (100.0, 60.0), (200.0, 160.0), (200.0, 215.0), (100.0, 115.0), (0.0, 215.0)], &[true, true, false, true, true, false], line),
+            OrdinaryKind::Cross => {
+                Self::add_polygon(&mut path, &[(75.0, 0.0), (125.0, 0.0), (125.0, 200.0), (75.0, 200.0)], &[false, true, false, true], line);
+                Self::add_polygon(&mut path, &[(0.0, 75.0), (200.0, 75.0), (200.0, 125.0), (0.0, 125.0)], &[true, false, true, false], line);
+            }
+            OrdinaryKind::Saltire => {
+                Self::add_polygon(&mut path, &[(-20.0 + BAND, -20.0 - BAND), (220.0 + BAND, 220.0 - BAND), (220.0 - BAND, 220.0 + BAND), (-20.0 - BAND, -20.0 + BAND)], &[true, false, true, false], line);
+                Self::add_polygon(&mut path, &[(220.0 - BAND, -20.0 - BAND), (-20.0 - BAND, 220.0 - BAND), (-20.0 + BAND, 220.0 + BAND), (220.0 + BAND, -20.0 + BAND)], &[true, false, true, false], line);
+            }
+            OrdinaryKind::Pile => Self::add_polygon(&mut path, &[(55.0, 0.0), (145.0, 0.0), (100.0, 170.0)], &[false, true, true], line),
+            OrdinaryKind::Canton => Self::add_polygon(&mut path, &[(0.0, 0.0), (75.0, 0.0), (75.0, 75.0), (0.0, 75.0)], &[false, true, true, false], line),
+            OrdinaryKind::Bordure | OrdinaryKind::Other(_) => return None,
+        }
+        Some(path)
+    }
+    
+    /// Add a closed polygon; edges flagged in `styled` are drawn with the line style
+    fn add_polygon(path: &mut skia::Path, points: &[(f32, f32)], styled: &[bool], line: &LineStyle) {
+        path.move_to(points[0]);
+        for (index, &from) in points.iter().enumerate() {
+            let to = points[(index + 1) % points.len()];
+            if styled.get(index).copied().unwrap_or(false) {
+                Self::styled_edge(path, from, to, line);
+            } else {
+                path.line_to(to);
+            }
+        }
+        path.close();
+    }
+    
+    fn styled_edge(path: &mut skia::Path, from: (f32, f32), to: (f32, f32), line: &LineStyle) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < f32::EPSILON {
+            return;
+        }
+        
+        // Unit normal of the edge and number of repeats of the line pattern
+        let (nx, ny) = (-dy / length, dx / length);
+        let steps = (length / 20.0).round().max(1.0) as usize;
+        let point = |t: f32, offset: f32| (from.0 + dx * t + nx * offset, from.1 + dy * t + ny * offset);
+        
+        for step in 0..steps {
+            let start = step as f32 / steps as f32;
+            let end = (step + 1) as f32 / steps as f32;
+            let middle = (start + end) / 2.0;
+            let sign = if step % 2 == 0 { 1.0 } else { -1.0 };
+            match line {
+                LineStyle::Wavy => {
+                    path.quad_to(point(middle, 12.0 * sign), point(end, 0.0));
+                }
+                LineStyle::Indented => {
+                    path.line_to(point(middle, 7.0));
+                    path.line_to(point(end, 0.0));
+                }
+                LineStyle::Embattled => {
+                    let offset = if step % 2 == 0 { 6.0 } else { -6.0 };
+                    path.line_to(point(start, offset));
+                    path.line_to(point(end, offset));
+                    path.line_to(point(end, 0.0));
+                }
+                LineStyle::Engrailed => {
+                    path.quad_to(point(middle, 10.0), point(end, 0.0));
+                }
+                LineStyle::Invected => {
+                    path.quad_to(point(middle, -10.0), point(end, 0.0));
+                }
+                LineStyle::Straight | LineStyle::Other(_) => {
+                    path.line_to(point(end, 0.0));
+                }
+            }
+        }
+    }
+    
+    /// Centre of a position on Azgaar's 3×3 charge grid
+    fn position(position: char) -> Option<(f32, f32)> {
+        let index = "abcdefghi".find(position)? as f32;
+        let column = index % 3.0;
+        let row = (index / 3.0).floor();
+        Some((60.0 + column * 40.0, 60.0 + row * 40.0))
+    }
+    
+    fn draw_charge(
+        canvas: &skia::Canvas,
+        charge: &str,
+        tincture: &Tincture,
+        center: (f32, f32),
+        radius: f32,
+        sinister: bool,
+        reversed: bool,
+    ) {
+        let paint = Self::fill_paint(tincture);
+        
+        canvas.save();
+        canvas.translate(center);
+        canvas.scale((if sinister { -1.0 } else { 1.0 }, if reversed { -1.0 } else { 1.0 }));
+        
+        match charge {
+            "annulet" => {
+                let mut ring = paint.clone();
+                ring.set_style(skia::PaintStyle::Stroke);
+                ring.set_stroke_width(radius * 0.35);
+                canvas.draw_circle((0.0, 0.0), radius * 0.8, &ring);
+            }
+            "lozenge" | "fusil" | "mascle" => {
+                let width = if charge == "fusil" { 0.5 } else { 0.75 };
+                let mut path = skia::Path::new();
+                path.move_to((0.0, -radius));
+                path.line_to((radius * width, 0.0));
+                path.line_to((0.0, radius));
+                path.line_to((-radius * width, 0.0));
+                path.close();
+                if charge == "mascle" {
+                    let mut outline = paint.clone();
+                    outline.set_style(skia::PaintStyle::Stroke);
+                    outline.set_stroke_width(radius * 0.3);
+                    canvas.draw_path(&path, &outline);
+                } else {
+                    canvas.draw_path(&path, &paint);
+                }
+            }
+            "billet" => {
+                canvas.draw_rect(skia::Rect::from_xywh(-radius * 0.45, -radius * 0.8, radius * 0.9, radius * 1.6), &paint);
+            }
+            "mullet" | "estoile" => {
+                let points = if charge == "estoile" { 6 } else { 5 };
+                let mut path = skia::Path::new();
+                for index in 0..points * 2 {
+                    let angle = std::f32::consts::PI * index as f32 / points as f32 - std::f32::consts::FRAC_PI_2;
+                    let distance = if index % 2 == 0 { radius } else { radius * 0.4 };
+                    let point = (angle.cos() * distance, angle.sin() * distance);
+                    if index == 0 {
+                        path.move_to(point);
+                    } else {
+                        path.line_to(point);
+                    }
+                }
+                path.close();
+                canvas.draw_path(&path, &paint);
+            }
+            "crescent" => {
+                canvas.save();
+                canvas.clip_path(&Self::ellipse_path((0.0, -radius * 0.35), radius * 0.8, radius * 0.8), skia::ClipOp::Difference, true);
+                canvas.draw_circle((0.0, 0.0), radius, &paint);
+                canvas.restore();
+            }
+            "cross" => {
+                canvas.draw_rect(skia::Rect::from_xywh(-radius * 0.25, -radius, radius * 0.5, radius * 2.0), &paint);
+                canvas.draw_rect(skia::Rect::from_xywh(-radius, -radius * 0.25, radius * 2.0, radius * 0.5), &paint);
+            }
+            _ => {
+                canvas.draw_circle((0.0, 0.0), radius, &paint);
+            }
+        }
+        
+        canvas.restore();
+    }
+    
+    /// Fill a region with a tincture, including fur patterns
+    fn paint_region(canvas: &skia::Canvas, region: &skia::Path, tincture: &Tincture) {
+        canvas.save();
+        canvas.clip_path(region, skia::ClipOp::Intersect, true);
+        canvas.draw_rect(skia::Rect::from_xywh(-UNIT, -UNIT, UNIT * 3.0, UNIT * 3.0), &Self::fill_paint(tincture));
+        
+        match tincture {
+            Tincture::Ermine => {
+                let spots = Self::fill_paint(&Tincture::Sable);
+                for row in 0..8 {
+                    for column in 0..8 {
+                        let x = column as f32 * 25.0 + if row % 2 == 0 { 6.0 } else { 18.5 };
+                        let y = row as f32 * 25.0 + 12.0;
+                        let mut tail = skia::Path::new();
+                        tail.move_to((x, y - 5.0));
+                        tail.line_to((x + 3.0, y + 4.0));
+                        tail.line_to((x - 3.0, y + 4.0));
+                        tail.close();
+                        canvas.draw_path(&tail, &spots);
+                    }
+                }
+            }
+            Tincture::Vair => {
+                let bells = Self::fill_paint(&Tincture::Argent);
+                for row in 0..8 {
+                    for column in 0..9 {
+                        let x = column as f32 * 25.0 - if row % 2 == 0 { 0.0 } else { 12.5 };
+                        let y = row as f32 * 25.0;
+                        let mut bell = skia::Path::new();
+                        bell.move_to((x + 12.5, y));
+                        bell.line_to((x + 20.0, y + 8.0));
+                        bell.line_to((x + 20.0, y + 25.0));
+                        bell.line_to((x + 5.0, y + 25.0));
+                        bell.line_to((x + 5.0, y + 8.0));
+                        bell.close();
+                        canvas.draw_path(&bell, &bells);
+                    }
+                }
+            }
+            _ => {}
+        }
+        
+        canvas.restore();
+    }
+    
+    fn fill_paint(tincture: &Tincture) -> skia::Paint {
+        let mut paint = skia::Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_style(skia::PaintStyle::Fill);
+        paint.set_color(Self::hex_to_color(tincture.hex_color()));
+        paint
+    }
+    
+    fn hex_to_color(hex: &str) -> skia::Color {
+        let value = u32::from_str_radix(hex.trim_start_matches('#'), 16).unwrap_or(0x808080);
+        skia::Color::from_rgb((value >> 16) as u8, (value >> 8) as u8, value as u8)
+    }
+    
+    fn rect_path(left: f32, top: f32, right: f32, bottom: f32) -> skia::Path {
+        let mut path = skia::Path::new();
+        path.move_to((left, top));
+        path.line_to((right, top));
+        path.line_to((right, bottom));
+        path.line_to((left, bottom));
+        path.close();
+        path
+    }
+    
+    fn ellipse_path(center: (f32, f32), rx: f32, ry: f32) -> skia::Path {
+        // Control point distance for approximating a quarter ellipse with a cubic
+        const KAPPA: f32 = 0.552_284_8;
+        let (cx, cy) = center;
+        let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+        
+        let mut path = skia::Path::new();
+        path.move_to((cx + rx, cy));
+        path.cubic_to((cx + rx, cy + ky), (cx + kx, cy + ry), (cx, cy + ry));
+        path.cubic_to((cx - kx, cy + ry), (cx - rx, cy + ky), (cx - rx, cy));
+        path.cubic_to((cx - rx, cy - ky), (cx - kx, cy - ry), (cx, cy - ry));
+        path.cubic_to((cx + kx, cy - ry), (cx + rx, cy - ky), (cx + rx, cy));
+        path.close();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn arms_render_inside_the_shield() {
+        let arms = CoatOfArms::plain(ShieldShape::Heater, Tincture::Gules);
+        let png = HeraldryRenderer::render_png(&arms, 64).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        
+        assert_eq!(image.dimensions(), (64, 64));
+        assert_eq!(image.get_pixel(32, 32).0, [0xd7, 0x37, 0x4a, 0xff]);
+        assert_eq!(image.get_pixel(2, 2).0[3], 0);
+    }
+}
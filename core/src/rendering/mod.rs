@@ -1,5 +1,9 @@
 //! Rendering system for world maps
 
+mod heraldry;
+
+pub use heraldry::*;
+
 use crate::{WorldMap, Result};
 use skia_safe as skia;
 